@@ -0,0 +1,41 @@
+; Timer interrupt demo for Sampo CPU
+; Counts timer ticks in an interrupt handler and halts after five
+
+.equ    TIMER_CTRL  0x90        ; Control/status (read acknowledges)
+.equ    TIMER_LO    0x91        ; Reload value, low byte
+.equ    TIMER_HI    0x92        ; Reload value, high byte
+.equ    TICKS       5
+
+        .org 0x0000
+        J    start              ; Reset
+
+        .org 0x0004
+        J    isr                ; Interrupt vector
+
+        .org 0x0100
+start:
+        LIX  R4, 0              ; Tick counter
+        LIX  R5, 100            ; Period: 100 cycles
+        OUTI TIMER_LO, R5
+        LIX  R5, 0
+        OUTI TIMER_HI, R5
+        LIX  R5, 3              ; Enable timer and its IRQ
+        OUTI TIMER_CTRL, R5
+        LIX  R6, TICKS
+        EI
+
+wait:
+        CMP  R4, R6
+        BNE  wait
+
+        DI
+        HALT
+
+isr:
+        PUSH R7
+        GETF R7                 ; Preserve flags for the interrupted code
+        ADDI R4, 1
+        INI  R5, TIMER_CTRL     ; Acknowledge the expiry
+        SETF R7
+        POP  R7
+        RETI
@@ -26,6 +26,10 @@ class CPUState(Enum, shape=4):
     MEMORY      = 5
     WRITEBACK   = 6
     HALTED      = 7
+    INT_PUSH_FLAGS = 8   # Interrupt entry: FLAGS word to sp+2
+    INT_PUSH_PC    = 9   # Interrupt entry: return PC to sp, enter the vector
+    RETI_POP_PC    = 10  # RETI: PC from sp
+    RETI_POP_FLAGS = 11  # RETI: FLAGS from sp+2
 
 
 class SampoCPU(Elaboratable):
@@ -108,6 +112,14 @@ class SampoCPU(Elaboratable):
         # Interrupt state
         int_enabled = Signal()
         int_pending = Signal()
+        int_vector = Signal(16)  # Handler for the frame being pushed
+        int_return = Signal(16)  # PC saved in, or popped from, the frame
+        int_di = Signal()        # Entry disables interrupts (IRQ, not SWI)
+        sp_frame = Signal(16)    # SP with the frame pushed
+
+        # FLAGS as a frame word; the I bit (bit 2) lives in int_enabled
+        flags_word = Signal(16)
+        m.d.comb += flags_word.eq(Cat(flags[0:2], int_enabled, flags[3:8], Const(0, 8)))
 
         # Debug outputs
         m.d.comb += [
@@ -265,14 +277,28 @@ class SampoCPU(Elaboratable):
                 m.next = CPUState.FETCH
 
             with m.State(CPUState.FETCH):
-                # Fetch instruction from memory
-                m.d.comb += [
-                    self.mem_addr.eq(pc),
-                    self.mem_valid.eq(1),
-                ]
-                with m.If(self.mem_ready):
-                    m.d.sync += instr.eq(self.mem_rdata)
-                    m.next = CPUState.DECODE
+                with m.If(self.irq & int_enabled):
+                    # Take the interrupt at the instruction boundary. The
+                    # NOP keeps the WRITEBACK after the frame from
+                    # repeating the last instruction's register write.
+                    m.d.comb += regfile.rd_addr1.eq(2)
+                    m.d.sync += [
+                        int_vector.eq(0x0004),
+                        int_return.eq(pc),
+                        int_di.eq(1),
+                        sp_frame.eq(regfile.rd_data1 - 4),
+                        instr.eq(0xE000),
+                    ]
+                    m.next = CPUState.INT_PUSH_FLAGS
+                with m.Else():
+                    # Fetch instruction from memory
+                    m.d.comb += [
+                        self.mem_addr.eq(pc),
+                        self.mem_valid.eq(1),
+                    ]
+                    with m.If(self.mem_ready):
+                        m.d.sync += instr.eq(self.mem_rdata)
+                        m.next = CPUState.DECODE
 
             with m.State(CPUState.DECODE):
                 # Check if this is an extended instruction
@@ -426,6 +452,20 @@ class SampoCPU(Elaboratable):
                         with m.Elif(decoder.is_di):
                             m.d.sync += int_enabled.eq(0)
                             m.next = CPUState.FETCH
+                        with m.Elif(decoder.is_swi):
+                            # Push the frame, then enter the vector at imm*2
+                            m.d.comb += regfile.rd_addr1.eq(2)
+                            m.d.sync += [
+                                int_vector.eq(decoder.imm8[:8] << 1),
+                                int_return.eq(pc_plus_2),
+                                int_di.eq(0),
+                                sp_frame.eq(regfile.rd_data1 - 4),
+                            ]
+                            m.next = CPUState.INT_PUSH_FLAGS
+                        with m.Elif(decoder.is_reti):
+                            m.d.comb += regfile.rd_addr1.eq(2)
+                            m.d.sync += sp_frame.eq(regfile.rd_data1)
+                            m.next = CPUState.RETI_POP_PC
                         with m.Else():
                             m.next = CPUState.FETCH
 
@@ -527,6 +567,68 @@ class SampoCPU(Elaboratable):
                     ]
                 m.next = CPUState.FETCH
 
+            # Interrupt frame: FLAGS word at sp+2, PC at sp. The frame
+            # states end in WRITEBACK, which writes nothing for SWI, RETI
+            # or the NOP an IRQ leaves in instr, so that FETCH starts
+            # with a fresh memory request.
+            with m.State(CPUState.INT_PUSH_FLAGS):
+                m.d.comb += [
+                    self.mem_addr.eq(sp_frame + 2),
+                    self.mem_wdata.eq(flags_word),
+                    self.mem_we.eq(1),
+                    self.mem_valid.eq(1),
+                ]
+                with m.If(self.mem_ready):
+                    m.next = CPUState.INT_PUSH_PC
+
+            with m.State(CPUState.INT_PUSH_PC):
+                m.d.comb += [
+                    self.mem_addr.eq(sp_frame),
+                    self.mem_wdata.eq(int_return),
+                    self.mem_we.eq(1),
+                    self.mem_valid.eq(1),
+                ]
+                with m.If(self.mem_ready):
+                    m.d.comb += [
+                        regfile.wr_addr.eq(2),
+                        regfile.wr_data.eq(sp_frame),
+                        regfile.wr_en.eq(1),
+                    ]
+                    m.d.sync += pc.eq(int_vector)
+                    with m.If(int_di):
+                        m.d.sync += int_enabled.eq(0)
+                    m.next = CPUState.WRITEBACK
+
+            with m.State(CPUState.RETI_POP_PC):
+                m.d.comb += [
+                    self.mem_addr.eq(sp_frame),
+                    self.mem_valid.eq(1),
+                ]
+                with m.If(self.mem_ready):
+                    m.d.sync += int_return.eq(self.mem_rdata)
+                    # Address the FLAGS word now so it is read next cycle
+                    m.d.comb += self.mem_addr.eq(sp_frame + 2)
+                    m.next = CPUState.RETI_POP_FLAGS
+
+            with m.State(CPUState.RETI_POP_FLAGS):
+                m.d.comb += [
+                    self.mem_addr.eq(sp_frame + 2),
+                    self.mem_valid.eq(1),
+                ]
+                with m.If(self.mem_ready):
+                    # Restore FLAGS in full, I included
+                    m.d.comb += [
+                        regfile.wr_addr.eq(2),
+                        regfile.wr_data.eq(sp_frame + 4),
+                        regfile.wr_en.eq(1),
+                    ]
+                    m.d.sync += [
+                        pc.eq(int_return),
+                        flags.eq(self.mem_rdata[:8]),
+                        int_enabled.eq(self.mem_rdata[2]),
+                    ]
+                    m.next = CPUState.WRITEBACK
+
             with m.State(CPUState.HALTED):
                 # Stay halted (can be reset externally)
                 pass
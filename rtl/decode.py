@@ -105,6 +105,7 @@ class Decoder(Elaboratable):
         self.is_ei = Signal()
         self.is_di = Signal()
         self.is_reti = Signal()
+        self.is_swi = Signal()
 
         # I/O
         self.is_io_in = Signal()
@@ -170,6 +171,7 @@ class Decoder(Elaboratable):
             self.is_ei.eq(0),
             self.is_di.eq(0),
             self.is_reti.eq(0),
+            self.is_swi.eq(0),
             self.is_io_in.eq(0),
             self.is_io_out.eq(0),
             self.io_port_imm.eq(0),
@@ -267,31 +269,12 @@ class Decoder(Elaboratable):
                     self.is_branch.eq(1),
                 ]
 
-            # JUMP
+            # JUMP: J offset12 only (register jumps are MISC func 0xE)
             with m.Case(0x9):
-                # Check for JR (9F0R pattern)
-                with m.If((self.instr & 0x0F0F) == 0x0F00):
-                    m.d.comb += [
-                        self.inst_type.eq(InstType.JUMP_REG),
-                        self.is_jump.eq(1),
-                    ]
-                    # JR R1 is a return
-                    with m.If(rs1 == 1):
-                        m.d.comb += self.is_ret.eq(1)
-                # JALR (func == 1 and rd != 0)
-                with m.Elif((func == 1) & (rd != 0)):
-                    m.d.comb += [
-                        self.inst_type.eq(InstType.JUMP_REG),
-                        self.is_jump.eq(1),
-                        self.is_call.eq(1),
-                        self.reg_write.eq(1),
-                    ]
-                # J offset12
-                with m.Else():
-                    m.d.comb += [
-                        self.inst_type.eq(InstType.JUMP),
-                        self.is_jump.eq(1),
-                    ]
+                m.d.comb += [
+                    self.inst_type.eq(InstType.JUMP),
+                    self.is_jump.eq(1),
+                ]
 
             # SHIFT
             with m.Case(0xA):
@@ -342,6 +325,19 @@ class Decoder(Elaboratable):
                         m.d.comb += self.reg_write.eq(1)
                     with m.Case(0xD):  # SETF
                         pass
+                    with m.Case(0xE):  # JALR Rd, Rs1 (JR Rs1 = JALR R0, Rs1)
+                        m.d.comb += [
+                            self.inst_type.eq(InstType.JUMP_REG),
+                            self.is_jump.eq(1),
+                        ]
+                        with m.If(rd != 0):
+                            m.d.comb += [
+                                self.is_call.eq(1),
+                                self.reg_write.eq(1),
+                            ]
+                        # JR R1 is a return
+                        with m.Elif(rs1 == 1):
+                            m.d.comb += self.is_ret.eq(1)
 
             # I/O
             with m.Case(0xD):
@@ -380,6 +376,8 @@ class Decoder(Elaboratable):
                         m.d.comb += self.is_ei.eq(1)
                     with m.Case(0x4):  # RETI
                         m.d.comb += self.is_reti.eq(1)
+                    with m.Case(0x5):  # SWI
+                        m.d.comb += self.is_swi.eq(1)
 
             # EXTENDED (32-bit)
             with m.Case(0xF):
@@ -425,8 +423,11 @@ class Decoder(Elaboratable):
                             self.alu_op.eq(ALUOp.PASS_B),
                             self.reg_write.eq(1),
                         ]
-                    with m.Case(0x8):  # JX
-                        m.d.comb += self.is_jump.eq(1)
+                    with m.Case(0x8):  # JX (Rs1 = 0); CAS is not implemented
+                        with m.If(rs1 == 0):
+                            m.d.comb += self.is_jump.eq(1)
+                        with m.Else():
+                            m.d.comb += self.inst_type.eq(InstType.INVALID)
                     with m.Case(0x9):  # JALX
                         m.d.comb += [
                             self.is_jump.eq(1),
@@ -435,12 +436,17 @@ class Decoder(Elaboratable):
                         ]
                     with m.Case(0xA):  # CMPIX
                         m.d.comb += self.alu_op.eq(ALUOp.SUB)
-                    with m.Case(0xB):  # INX
-                        m.d.comb += [
-                            self.is_io_in.eq(1),
-                            self.io_port_imm.eq(1),
-                            self.reg_write.eq(1),
-                        ]
+                    with m.Case(0xB):  # INX (imm16[15:8] = 0)
+                        with m.If(self.imm16[8:16] == 0):
+                            m.d.comb += [
+                                self.is_io_in.eq(1),
+                                self.io_port_imm.eq(1),
+                                self.reg_write.eq(1),
+                            ]
+                        with m.Else():
+                            # ADDS/SUBS/ADDSU/SUBSU and BSET/BCLR/BTST
+                            # are not implemented
+                            m.d.comb += self.inst_type.eq(InstType.INVALID)
                     with m.Case(0xC):  # OUTX
                         m.d.comb += [
                             self.is_io_out.eq(1),
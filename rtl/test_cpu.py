@@ -201,6 +201,62 @@ def test_regfile():
     return True
 
 
+def test_interrupt_frame():
+    """Test that RETI restores the FLAGS an ISR changed.
+
+    The program, as sasm assembles it:
+
+                .org 0x0080
+        handler:
+                ADDI R4, 1              ; Clears Z
+                RETI
+                .org 0x0100
+        start:
+                LIX  R2, 0x8000         ; Stack
+                SUB  R4, R0, R0         ; Sets Z
+                SWI  0x40               ; Handler at 0x0080
+                BEQ  kept
+                LI   R5, 'N'
+                J    done
+        kept:   LI   R5, 'Y'
+        done:   OUTI 0x81, R5
+                HALT
+    """
+    code = {
+        0x0080: [0x5401, 0xE400],
+        0x0100: [0xF207, 0x8000, 0x1400, 0xE540, 0x8003, 0xF507, 0x004E,
+                 0x9002, 0xF507, 0x0059, 0xF05C, 0x0081, 0xE100],
+    }
+    program = [0] * 0x0200
+    for addr, words in code.items():
+        for i, word in enumerate(words):
+            program[addr + 2 * i] = word & 0xFF
+            program[addr + 2 * i + 1] = word >> 8
+
+    soc = SampoSoC(program=program, reset_vector=0x0100)
+    output = []
+
+    def testbench():
+        yield soc.tx_ready.eq(1)
+        for cycle in range(1000):
+            if (yield soc.tx_valid):
+                output.append(chr((yield soc.tx_data)))
+            if (yield soc.halted):
+                break
+            yield
+
+    sim = Simulator(soc)
+    sim.add_clock(1e-6)
+    sim.add_testbench(testbench)
+    sim.run()
+
+    result = ''.join(output)
+    print(f"Output: {repr(result)}")
+    assert result == "Y", "FLAGS changed by the ISR survived RETI"
+    print("\nInterrupt frame test passed!")
+    return True
+
+
 def main():
     print("=" * 60)
     print("Sampo CPU Tests")
@@ -209,6 +265,7 @@ def main():
     tests = [
         ("ALU", test_alu),
         ("Register File", test_regfile),
+        ("Interrupt Frame", test_interrupt_frame),
         ("Hello World", test_hello_world),
     ]
 
@@ -1,25 +1,185 @@
 //! Code generator for Sampo assembly
 
-use crate::parser::{Operand, Program, Statement, DirectiveArg};
-use std::collections::HashMap;
+use crate::parser::{Condition, Operand, Program, Statement, DirectiveArg, Term};
+use crate::preprocess;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+
+/// Advisory warnings and whether each is on by default; `-W <name>`
+/// enables one and `-W no-<name>` disables it
+pub const WARNINGS: &[(&str, bool)] = &[
+    ("jump-size", true),
+    ("unreachable", false),
+    ("sp-write", true),
+    ("odd-address", true),
+    ("stale-flags", true),
+    ("signedness", false),
+];
+
+/// Instructions that write their first (register) operand; ones that
+/// adjust or load SP on purpose (ADDI, ADDIX, SUBIX, MOV, LIX, LA, POP) are left out
+const WRITES_RD: &[&str] = &[
+    "ADD", "SUB", "AND", "OR", "XOR", "LW", "LB", "LBU", "LUI", "LI", "LWX",
+    "SLL", "SRL", "SRA", "ROL", "ROR", "SWAP", "MUL", "MULH", "MULHU",
+    "DIV", "DIVU", "REM", "REMU", "ADDS", "SUBS", "ADDSU", "SUBSU", "BSET", "BCLR", "GETF", "IN", "INI", "INX", "NEG", "NOT",
+    "ANDIX", "ORIX", "XORIX", "SLLX", "SRLX", "SRAX", "JALR", "RDCYC", "CAS",
+];
+
+/// Instructions that leave N/Z/C/V as they were. Anything not listed here
+/// or in CALLS is assumed to set them.
+const KEEPS_FLAGS: &[&str] = &[
+    "MOV", "LW", "LWX", "LB", "LBU", "LUI", "LI", "LIX", "LA", "SW", "SWX", "SB",
+    "PUSH", "POP", "EXX", "GETF", "IN", "INI", "INX", "OUT", "OUTI", "OUTX", "NOP", "DI", "EI", "TRAP",
+    "SWI",
+];
+
+/// Instructions whose callee may hand back flags, e.g. carry for an error.
+/// SWI is not one: its entry saves FLAGS and RETI restores the caller's.
+const CALLS: &[&str] = &["JAL", "JALR", "JALX"];
+
+/// Pass-1 scans allowed for value-dependent sizes (LI) to settle
+const MAX_SIZING_PASSES: usize = 16;
+
+/// Every mnemonic `emit_instruction` knows, in the order of its match arms;
+/// anything else is an unknown instruction
+const MNEMONICS: &[&str] = &[
+    "ADD", "SUB", "AND", "OR", "XOR", "ADDI", "LW", "LIX", "LWX", "LB", "LBU", "LUI", "LI",
+    "SW", "SWX", "SB",
+    "BEQ", "BNE", "BLT", "BGE", "BLTU", "BGEU", "BMI", "BPL",
+    "BVS", "BVC", "BCS", "BCC", "BGT", "BLE", "BHI", "BLS",
+    "J", "JR", "JALR", "JAL", "SLL", "SRL", "SRA", "ROL", "ROR", "SWAP",
+    "MUL", "MULH", "MULHU", "DIV", "DIVU", "REM", "REMU", "DAA",
+    "ADDS", "SUBS", "ADDSU", "SUBSU", "BSET", "BCLR", "BTST", "PUSH", "POP", "CMP", "TEST", "MOV",
+    "LDI", "LDD", "LDIR", "LDDR", "CPIR", "FILL", "EXX", "GETF", "SETF",
+    "IN", "INI", "INX", "OUT", "OUTI", "OUTX", "NOP", "HALT", "DI", "EI", "RETI", "SWI", "TRAP",
+    "SCF", "CCF", "CSKIP", "RDCYC", "LA", "JX", "JALX", "CAS", "NEG", "NOT",
+];
+
+/// Condition codes in encoding order, as used by the Bcc mnemonics and CSKIP
+const CONDITIONS: [&str; 16] = [
+    "EQ", "NE", "LT", "GE", "LTU", "GEU", "MI", "PL",
+    "VS", "VC", "CS", "CC", "GT", "LE", "HI", "LS",
+];
 
 pub struct CodeGen {
-    origin: u16,
     pc: u16,
+    section: Section,
+    sections: [SectionState; 3],
     symbols: HashMap<String, u16>,
+    constants: HashSet<String>, // Symbols defined by .equ rather than as labels
+    definitions: HashMap<String, usize>, // Source line defining each symbol
+    references: Vec<(String, u16, usize)>, // Symbol, statement address and line of each use
+    global: HashSet<String>, // Symbols exported with `.global`/`.export`
     output: Vec<u8>,
+    emitted: Vec<bool>,    // Output bytes written so far, to catch .org overlaps
+    overlap: Option<u16>,  // First address the current statement wrote twice
     fixups: Vec<Fixup>,
+    pool: Vec<PoolEntry>,   // Literals awaiting the next .pool or section end
+    listing: Vec<ListEntry>,
+    long_jumps: Vec<Fixup>, // JX to a label, checked for a short J after fixups
+    checksums: Vec<Checksum>, // Filled in after fixups, in source order
+    pad: Option<(usize, u8)>, // Final image size and fill byte
+    max_size: Option<usize>,  // Largest image allowed, counted from address 0
+    byte_sums: Vec<ByteSum>,  // lo()/hi() bytes, filled in with the fixups
+    conditionals: Vec<Conditional>, // Open .if blocks, innermost last
+    line: usize,            // Source line of the statement being assembled
+    origins: Vec<(String, usize)>, // File and line of each source line, if preprocessed
+    warnings: Vec<String>,
+    enabled_warnings: HashSet<String>,
+    dead_after: Option<String>, // Unconditional transfer that ended the last instruction
+    after_skip: bool,           // Last instruction was CSKIP, so the next may not run
+    flags: FlagState,           // What the next conditional branch would test
+    sp_is_general: bool,        // `.def sp_is_general`: R2 may be used freely
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Section {
+    Text,
+    Data,
+    Bss,
+}
+
+/// Location counter for one output section
+#[derive(Clone, Copy, Default)]
+struct SectionState {
+    origin: Option<u16>, // Explicit start from `.text/.data/.bss <addr>`
+    start: u16,
+    pc: u16,
+    end: u16, // Highest address reached
+}
+
+/// Where pass 2 put one statement or literal pool, for the listing
+#[derive(Clone, Copy)]
+pub struct ListEntry {
+    pub line: Option<usize>, // Source line, or None for a literal pool
+    pub addr: u16,
+    pub len: u16, // Bytes in the output; 0 for .bss and for directives that move the PC
+}
+
+/// Where a symbol is defined or used, for the cross-reference report
+pub struct SymbolSite {
+    pub addr: u16, // Statement address; for the definition, the symbol's value
+    pub location: String,
+    pub definition: bool,
+}
+
+/// One literal pool word and the instructions that load it
+struct PoolEntry {
+    value: Either,
+    refs: Vec<u16>, // Addresses of the imm16 fields to patch with the word's address
 }
 
 struct Fixup {
     address: u16,
     symbol: String,
     kind: FixupKind,
+    line: usize,
+}
+
+/// `.checksum start, end`: a word holding the 16-bit sum of the bytes in
+/// start..end, counting itself as zero
+struct Checksum {
+    address: u16,
+    start: Either,
+    end: Either,
+    line: usize,
+}
+
+/// `.db lo(...)` or `hi(...)`: one byte of a sum that may use labels
+/// defined later, such as the size `end - start`
+struct ByteSum {
+    address: u16,
+    high: bool,
+    terms: Vec<(i32, Term)>,
+    line: usize,
+}
+
+/// One open `.if` block
+struct Conditional {
+    enclosing: bool, // Whether the code around the block is assembled
+    active: bool,    // Whether the current branch is assembled
+    taken: bool,     // Whether any branch so far was chosen
+    had_else: bool,
+    line: usize,
+}
+
+/// Where the flags come from at the current instruction, for `stale-flags`
+/// and `signedness`. Only straight-line code after a label or jump is followed.
+enum FlagState {
+    Entry,         // Start of code or a label: flags come from elsewhere
+    Stale(String), // Since the entry only flag-keeping instructions ran; the last one
+    Set,           // An instruction since the entry set them
+    Unknown,       // A call since the entry may have set them
+    ZeroCompare(String, bool), // Set by a CMP/SUB with R0 on one side (true: the right)
 }
 
 #[derive(Clone, Copy)]
 enum FixupKind {
     Absolute16,
+    Absolute16Be,
+    PcRel16,
+    PcRel16Be,
+    TableRel16(u16), // Target minus the address of a `.jmptable`
     Relative8,
     Relative12,
 }
@@ -27,11 +187,123 @@ enum FixupKind {
 impl CodeGen {
     pub fn new() -> Self {
         CodeGen {
-            origin: 0,
             pc: 0,
+            section: Section::Text,
+            sections: [SectionState::default(); 3],
             symbols: HashMap::new(),
+            constants: HashSet::new(),
+            definitions: HashMap::new(),
+            references: Vec::new(),
+            global: HashSet::new(),
             output: Vec::new(),
+            emitted: Vec::new(),
+            overlap: None,
             fixups: Vec::new(),
+            pool: Vec::new(),
+            listing: Vec::new(),
+            long_jumps: Vec::new(),
+            checksums: Vec::new(),
+            pad: None,
+            max_size: None,
+            byte_sums: Vec::new(),
+            conditionals: Vec::new(),
+            line: 0,
+            origins: Vec::new(),
+            warnings: Vec::new(),
+            enabled_warnings: WARNINGS.iter()
+                .filter(|(_, on)| *on)
+                .map(|(name, _)| name.to_string())
+                .collect(),
+            dead_after: None,
+            after_skip: false,
+            flags: FlagState::Entry,
+            sp_is_general: false,
+        }
+    }
+
+    /// Name diagnostics by file and line using the preprocessor's map
+    pub fn set_origins(&mut self, origins: Vec<(String, usize)>) {
+        self.origins = origins;
+    }
+
+    fn location(&self, line: usize) -> String {
+        preprocess::location(&self.origins, line)
+    }
+
+    /// Enable or disable one of the `WARNINGS` by name
+    pub fn set_warning(&mut self, name: &str, enabled: bool) -> Result<(), String> {
+        if !WARNINGS.iter().any(|(known, _)| *known == name) {
+            return Err(format!("Unknown warning: {}", name));
+        }
+        if enabled {
+            self.enabled_warnings.insert(name.to_string());
+        } else {
+            self.enabled_warnings.remove(name);
+        }
+        Ok(())
+    }
+
+    /// Pad the image with `fill` to exactly `size` bytes, e.g. for a ROM
+    pub fn set_pad(&mut self, size: usize, fill: u8) {
+        self.pad = Some((size, fill));
+    }
+
+    /// Fail if the final image, from address 0, exceeds `size` bytes
+    pub fn set_max_size(&mut self, size: usize) {
+        self.max_size = Some(size);
+    }
+
+    /// Address range of each non-empty section, for verbose output
+    pub fn section_ranges(&self) -> Vec<(&'static str, u16, u16)> {
+        [("text", Section::Text), ("data", Section::Data), ("bss", Section::Bss)]
+            .into_iter()
+            .map(|(name, sec)| (name, self.sections[sec as usize].start, self.sections[sec as usize].end))
+            .filter(|&(_, start, end)| end > start)
+            .collect()
+    }
+
+    /// All symbols sorted by address, with whether each is exported
+    pub fn symbol_table(&self) -> Vec<(&str, u16, bool)> {
+        let mut table: Vec<_> = self.symbols.iter()
+            .map(|(name, &addr)| (name.as_str(), addr, self.global.contains(name)))
+            .collect();
+        table.sort_by(|a, b| a.1.cmp(&b.1).then(a.0.cmp(b.0)));
+        table
+    }
+
+    /// Each symbol by name with its definition and then its uses in source
+    /// order
+    pub fn cross_reference(&self) -> Vec<(&str, Vec<SymbolSite>)> {
+        let mut names: Vec<&String> = self.symbols.keys().collect();
+        names.sort();
+        names.into_iter()
+            .map(|name| {
+                let definition = self.definitions.get(name).map(|&line| SymbolSite {
+                    addr: self.symbols[name],
+                    location: self.location(line),
+                    definition: true,
+                });
+                let uses = self.references.iter()
+                    .filter(|(sym, _, _)| sym == name)
+                    .map(|&(_, addr, line)| SymbolSite { addr, location: self.location(line), definition: false });
+                (name.as_str(), definition.into_iter().chain(uses).collect())
+            })
+            .collect()
+    }
+
+    /// Output placement of each statement and literal pool, in assembly order
+    pub fn listing(&self) -> &[ListEntry] {
+        &self.listing
+    }
+
+    /// Warnings collected by the last `generate`
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    fn warn(&mut self, name: &str, line: usize, msg: String) {
+        if self.enabled_warnings.contains(name) {
+            self.warnings.push(format!("{}: {} [{}]", self.location(line), msg, name));
         }
     }
 
@@ -42,162 +314,854 @@ impl CodeGen {
         // Pass 2: Generate code
         self.pass2(program)?;
 
-        // Pass 3: Apply fixups
+        // Pass 3: Apply fixups, pad, then sum the final bytes
         self.apply_fixups()?;
+        self.apply_byte_sums()?;
+        self.apply_pad()?;
+        self.apply_checksums()?;
+        self.check_max_size()?;
+
+        // Advisory pass over the final addresses
+        self.check_long_jumps();
 
         Ok(self.output.clone())
     }
 
     fn pass1(&mut self, program: &Program) -> Result<(), String> {
-        self.pc = self.origin;
-
-        for stmt in &program.statements {
-            match stmt {
-                Statement::Label(name) => {
-                    self.symbols.insert(name.clone(), self.pc);
-                }
-                Statement::Directive { name, args } => {
-                    match name.as_str() {
-                        "org" => {
-                            if let Some(DirectiveArg::Number(addr)) = args.first() {
-                                self.origin = *addr as u16;
-                                self.pc = self.origin;
-                            }
+        // Size each section first, so that sections without an explicit
+        // origin can be placed directly after the preceding one
+        self.scan(program)?;
+
+        // Then assign label addresses, rescanning while an LI whose size
+        // depends on a later symbol keeps moving them
+        for _ in 0..MAX_SIZING_PASSES {
+            self.place_sections();
+            let symbols = self.symbols.clone();
+            let sections = self.sections.map(|s| (s.start, s.end));
+            self.scan(program)?;
+            if self.symbols == symbols && self.sections.map(|s| (s.start, s.end)) == sections {
+                return Ok(());
+            }
+        }
+        Err(format!(
+            "Instruction sizes did not settle after {} passes (an LI whose target moves with its own size?)",
+            MAX_SIZING_PASSES
+        ))
+    }
+
+    fn scan(&mut self, program: &Program) -> Result<(), String> {
+        self.begin_sections();
+
+        for (stmt, &line) in program.statements.iter().zip(&program.lines) {
+            self.line = line;
+            let skip = self.conditional(stmt)
+                .map_err(|e| format!("{}: {}", self.location(line), e))?;
+            if !skip {
+                self.scan_statement(&self.expand_builtins(stmt))
+                    .map_err(|e| format!("{}: {}", self.location(line), e))?;
+            }
+        }
+        self.end_conditionals()?;
+
+        self.skip_pool();
+        self.end_sections();
+        Ok(())
+    }
+
+    /// Replace the built-in symbols: `__LINE__` is the source line using it
+    /// and `__FILE__` the name of that line's file, as a string for `.db`,
+    /// `.ascii` or `.asciz`
+    fn expand_builtins<'a>(&self, stmt: &'a Statement) -> Cow<'a, Statement> {
+        let (file, line) = match self.origins.get(self.line.wrapping_sub(1)) {
+            Some((file, line)) => (file.as_str(), *line),
+            None => ("", self.line),
+        };
+        match stmt {
+            Statement::Instruction { mnemonic, operands }
+                if operands.iter().any(|op| matches!(op, Operand::Label(name) if name == "__LINE__")) =>
+            {
+                let operands = operands.iter()
+                    .map(|op| match op {
+                        Operand::Label(name) if name == "__LINE__" => Operand::Immediate(line as i32),
+                        op => op.clone(),
+                    })
+                    .collect();
+                Cow::Owned(Statement::Instruction { mnemonic: mnemonic.clone(), operands })
+            }
+            Statement::Directive { name, args }
+                if args.iter().any(|arg| matches!(arg, DirectiveArg::Ident(sym) if sym == "__LINE__" || sym == "__FILE__")) =>
+            {
+                let args = args.iter()
+                    .map(|arg| match arg {
+                        DirectiveArg::Ident(sym) if sym == "__LINE__" => DirectiveArg::Number(line as i64),
+                        DirectiveArg::Ident(sym) if sym == "__FILE__" => DirectiveArg::String(file.as_bytes().to_vec()),
+                        arg => arg.clone(),
+                    })
+                    .collect();
+                Cow::Owned(Statement::Directive { name: name.clone(), args })
+            }
+            stmt => Cow::Borrowed(stmt),
+        }
+    }
+
+    fn scan_statement(&mut self, stmt: &Statement) -> Result<(), String> {
+        match stmt {
+            Statement::Label(name) => {
+                self.symbols.insert(name.clone(), self.pc);
+                self.definitions.insert(name.clone(), self.line);
+            }
+            Statement::Directive { name, args } => {
+                match name.as_str() {
+                    "org" => {
+                        let addr = self.org_address(args)?;
+                        self.set_pc(addr);
+                    }
+                    "vectors" => {
+                        let (base, targets) = self.vector_table(args)?;
+                        self.set_pc(base);
+                        self.pc += (targets.len() * 2) as u16;
+                    }
+                    "text" | "data" | "bss" => {
+                        self.skip_pool();
+                        self.switch_section(name, args);
+                    }
+                    "pool" => self.skip_pool(),
+                    "equ" if args.len() >= 2 => {
+                        if let (DirectiveArg::Ident(sym), DirectiveArg::Number(val)) =
+                            (&args[0], &args[1])
+                        {
+                            self.symbols.insert(sym.clone(), *val as u16);
+                            self.constants.insert(sym.clone());
+                            self.definitions.insert(sym.clone(), self.line);
+                        }
+                    }
+                    "db" => {
+                        for arg in args {
+                            self.pc += match arg {
+                                DirectiveArg::String(s) => s.len() as u16,
+                                _ => 1,
+                            };
                         }
-                        "equ" => {
-                            if args.len() >= 2 {
-                                if let (DirectiveArg::Ident(sym), DirectiveArg::Number(val)) =
-                                    (&args[0], &args[1])
-                                {
-                                    self.symbols.insert(sym.clone(), *val as u16);
+                    }
+                    "dw" | "dwbe" | "jmptable" => {
+                        self.pc += (args.len() * 2) as u16;
+                    }
+                    "dd" => {
+                        self.pc += (args.len() * 4) as u16;
+                    }
+                    "checksum" => {
+                        self.pc += 2;
+                    }
+                    "ascii" | "asciz" => {
+                        for arg in args {
+                            if let DirectiveArg::String(s) = arg {
+                                self.pc += s.len() as u16;
+                                if name == "asciz" {
+                                    self.pc += 1;
                                 }
                             }
                         }
-                        "db" => {
-                            self.pc += args.len() as u16;
-                        }
-                        "dw" => {
-                            self.pc += (args.len() * 2) as u16;
-                        }
-                        "ascii" | "asciz" => {
-                            for arg in args {
-                                if let DirectiveArg::String(s) = arg {
-                                    self.pc += s.len() as u16;
-                                    if name == "asciz" {
-                                        self.pc += 1;
-                                    }
-                                }
+                    }
+                    "space" => {
+                        self.pc += self.space_size(args)?;
+                    }
+                    "align" => {
+                        self.pc += self.align_padding(args)?.0;
+                    }
+                    "global" | "export" => {
+                        for arg in args {
+                            if let DirectiveArg::Ident(sym) = arg {
+                                self.global.insert(sym.clone());
                             }
                         }
-                        _ => {}
                     }
+                    _ => {}
                 }
-                Statement::Instruction { mnemonic, operands } => {
-                    self.pc += self.instruction_size(mnemonic, operands)?;
+            }
+            Statement::Instruction { mnemonic, operands } => {
+                if let Some(value) = pool_literal(operands)? {
+                    self.pool_entry(value);
                 }
+                self.pc += self.instruction_size(mnemonic, operands)?;
             }
         }
-
         Ok(())
     }
 
     fn pass2(&mut self, program: &Program) -> Result<(), String> {
-        self.pc = self.origin;
+        self.begin_sections();
+
+        for (stmt, &line) in program.statements.iter().zip(&program.lines) {
+            self.line = line;
+            if self.conditional(stmt).map_err(|e| format!("{}: {}", self.location(line), e))? {
+                continue;
+            }
+            let start = self.pc;
+            let stmt = self.expand_builtins(stmt);
+            self.note_references(&stmt, start);
+            self.emit_statement(&stmt)
+                .map_err(|e| format!("{}: {}", self.location(line), e))?;
+            if let Some(addr) = self.overlap.take() {
+                return Err(format!(
+                    "{}: Overwrites code or data already emitted at 0x{:04X} (check .org)",
+                    self.location(line), addr
+                ));
+            }
+            // A vector table lists at its own base, not where it was written
+            let start = match &*stmt {
+                Statement::Directive { name, args } if name == "vectors" => self.vector_table(args)?.0,
+                _ => start,
+            };
+            let moves_pc = matches!(&*stmt, Statement::Directive { name, .. }
+                if matches!(name.as_str(), "org" | "text" | "data" | "bss" | "pool"));
+            self.listing.push(if moves_pc || self.section == Section::Bss {
+                ListEntry { line: Some(line), addr: if moves_pc { self.pc } else { start }, len: 0 }
+            } else {
+                ListEntry { line: Some(line), addr: start, len: self.pc - start }
+            });
+        }
+
+        self.end_conditionals()?;
+
+        self.emit_pool();
+        self.end_sections();
+        Ok(())
+    }
+
+    /// Track `.if`/`.elif`/`.else`/`.endif`. Returns true when `stmt` is
+    /// one of them or sits in a branch that is not assembled.
+    fn conditional(&mut self, stmt: &Statement) -> Result<bool, String> {
+        let assembling = self.conditionals.last().is_none_or(|c| c.active);
+        let Statement::Directive { name, args } = stmt else {
+            return Ok(!assembling);
+        };
+        match name.as_str() {
+            "if" => {
+                // Conditions in skipped code are not evaluated, so they may
+                // name symbols that code would have defined
+                let holds = assembling && self.condition_holds(args)?;
+                self.conditionals.push(Conditional {
+                    enclosing: assembling,
+                    active: holds,
+                    taken: holds,
+                    had_else: false,
+                    line: self.line,
+                });
+            }
+            "elif" | "else" | "endif" => {
+                let Some(cond) = self.conditionals.last() else {
+                    return Err(format!(".{} without .if", name));
+                };
+                if cond.had_else && name != "endif" {
+                    return Err(format!(".{} after .else", name));
+                }
+                let open = cond.enclosing && !cond.taken;
+                let holds = match name.as_str() {
+                    "elif" => open && self.condition_holds(args)?,
+                    "else" => open,
+                    _ => {
+                        self.conditionals.pop();
+                        return Ok(true);
+                    }
+                };
+                let cond = self.conditionals.last_mut().unwrap();
+                cond.active = holds;
+                cond.taken |= holds;
+                cond.had_else = name == "else";
+            }
+            _ => return Ok(!assembling),
+        }
+        Ok(true)
+    }
 
-        // Pad output to origin if needed
-        while self.output.len() < self.origin as usize {
-            self.output.push(0);
+    fn condition_holds(&self, args: &[DirectiveArg]) -> Result<bool, String> {
+        match args {
+            [DirectiveArg::Condition(cond)] => Ok(self.evaluate(cond)? != 0),
+            _ => Err(".if needs a condition".to_string()),
         }
+    }
+
+    /// Value of a condition, with numbers and symbols taken as unsigned
+    /// 16-bit values (so -1 is 0xFFFF, as `.equ` stores it) and each
+    /// comparison or logical operator giving 1 or 0
+    fn evaluate(&self, cond: &Condition) -> Result<u16, String> {
+        Ok(match cond {
+            Condition::Number(n) => *n as u16,
+            Condition::Symbol(sym) => *self.symbols.get(sym)
+                .ok_or_else(|| format!("Undefined symbol in condition: {} (define it before the .if)", sym))?,
+            Condition::Not(inner) => (self.evaluate(inner)? == 0) as u16,
+            Condition::And(a, b) => (self.evaluate(a)? != 0 && self.evaluate(b)? != 0) as u16,
+            Condition::Or(a, b) => (self.evaluate(a)? != 0 || self.evaluate(b)? != 0) as u16,
+            Condition::Compare(op, a, b) => {
+                let (a, b) = (self.evaluate(a)?, self.evaluate(b)?);
+                let holds = match op.as_str() {
+                    "==" => a == b,
+                    "!=" => a != b,
+                    "<" => a < b,
+                    "<=" => a <= b,
+                    ">" => a > b,
+                    _ => a >= b,
+                };
+                holds as u16
+            }
+        })
+    }
+
+    /// Every `.if` must be closed by the end of the source
+    fn end_conditionals(&mut self) -> Result<(), String> {
+        match self.conditionals.pop() {
+            Some(cond) => {
+                self.conditionals.clear();
+                Err(format!("{}: .if without .endif", self.location(cond.line)))
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Record each known symbol a statement uses, at the statement's address
+    fn note_references(&mut self, stmt: &Statement, addr: u16) {
+        let names: Vec<&String> = match stmt {
+            Statement::Instruction { operands, .. } => operands.iter()
+                .filter_map(|op| match op {
+                    Operand::Label(name) => Some(name),
+                    Operand::Literal(value) => match value.as_ref() {
+                        Operand::Label(name) => Some(name),
+                        _ => None,
+                    },
+                    _ => None,
+                })
+                .collect(),
+            // .equ defines its first argument; the others name symbols
+            // without using their values
+            Statement::Directive { name, .. } if matches!(name.as_str(), "equ" | "global" | "export" | "def") => Vec::new(),
+            Statement::Directive { args, .. } => args.iter()
+                .flat_map(|arg| match arg {
+                    DirectiveArg::Ident(name) | DirectiveArg::PcRel(name) => vec![name],
+                    DirectiveArg::ByteOf(_, terms) => terms.iter()
+                        .filter_map(|(_, term)| match term {
+                            Term::Symbol(name) => Some(name),
+                            Term::Number(_) => None,
+                        })
+                        .collect(),
+                    _ => Vec::new(),
+                })
+                .collect(),
+            Statement::Label(_) => Vec::new(),
+        };
+        for name in names {
+            if self.symbols.contains_key(name) {
+                self.references.push((name.clone(), addr, self.line));
+            }
+        }
+    }
 
-        for stmt in &program.statements {
-            match stmt {
-                Statement::Label(_) => {}
-                Statement::Directive { name, args } => {
-                    self.emit_directive(name, args)?;
+    fn emit_statement(&mut self, stmt: &Statement) -> Result<(), String> {
+        match stmt {
+            Statement::Label(_) => {
+                // A label makes the following code reachable
+                self.dead_after = None;
+                self.flags = FlagState::Entry;
+            }
+            Statement::Directive { name, args } => {
+                if !matches!(name.as_str(), "equ" | "global" | "export" | "def") {
+                    self.dead_after = None;
+                    self.flags = FlagState::Entry;
+                }
+                self.emit_directive(name, args)?;
+            }
+            Statement::Instruction { mnemonic, operands } => {
+                if self.section == Section::Bss {
+                    return Err(format!("Instruction {} in .bss section", mnemonic));
+                }
+                if self.pc & 1 != 0 {
+                    self.warn("odd-address", self.line,
+                        format!("{} at odd address 0x{:04X}; add .align 2 before it", mnemonic, self.pc));
                 }
-                Statement::Instruction { mnemonic, operands } => {
-                    self.emit_instruction(mnemonic, operands)?;
+                if let Some(prev) = self.dead_after.take() {
+                    self.warn("unreachable", self.line,
+                        format!("{} is unreachable after {} (no label)", mnemonic, prev));
+                }
+                if !self.sp_is_general && WRITES_RD.contains(&mnemonic.as_str())
+                    && matches!(operands.first(), Some(Operand::Register(2)))
+                {
+                    self.warn("sp-write", self.line,
+                        format!("{} overwrites SP (R2); use .def sp_is_general if intended", mnemonic));
+                }
+                self.track_flags(mnemonic, operands);
+                self.emit_instruction(mnemonic, operands)?;
+                // A skipped J leaves the code after it reachable
+                let writes_pc = mnemonic == "MOV"
+                    && matches!(operands.first(), Some(Operand::Label(pc)) if pc.eq_ignore_ascii_case("PC"));
+                if (writes_pc || matches!(mnemonic.as_str(), "J" | "JX" | "JR" | "HALT")) && !self.after_skip {
+                    self.dead_after = Some(mnemonic.clone());
+                }
+                self.after_skip = mnemonic == "CSKIP";
+            }
+        }
+        Ok(())
+    }
+
+    /// Warn on a conditional branch that can only see flags from before the
+    /// last label, such as `MOV R4, R5` then `BEQ done`, and record what
+    /// this instruction does to them
+    fn track_flags(&mut self, mnemonic: &str, operands: &[Operand]) {
+        let sets_flags_reg = mnemonic == "MOV"
+            && matches!(operands.first(), Some(Operand::Label(f)) if f.eq_ignore_ascii_case("FLAGS"));
+        let branch = mnemonic.strip_prefix('B').is_some_and(|cc| CONDITIONS.contains(&cc));
+        if branch {
+            if let FlagState::Stale(prev) = &self.flags {
+                let msg = format!("{} tests flags nothing has set since the last label ({} leaves them alone)", mnemonic, prev);
+                self.warn("stale-flags", self.line, msg);
+            }
+            if let FlagState::ZeroCompare(compare, zero_right) = &self.flags {
+                // x <u 0 and 0 >u x never hold; their opposites always do
+                let outcome = match (&mnemonic[1..], zero_right) {
+                    ("LTU", true) | ("HI", false) => Some("never"),
+                    ("GEU", true) | ("LS", false) => Some("always"),
+                    _ => None,
+                };
+                if let Some(outcome) = outcome {
+                    let signed = match &mnemonic[1..] {
+                        "LTU" => "BLT",
+                        "GEU" => "BGE",
+                        "HI" => "BGT",
+                        _ => "BLE",
+                    };
+                    let msg = format!(
+                        "{} after {} against zero is {} taken; {} compares signed",
+                        mnemonic, compare, outcome, signed
+                    );
+                    self.warn("signedness", self.line, msg);
                 }
             }
+        } else if matches!(mnemonic, "J" | "JX" | "JR" | "HALT" | "RETI")
+            || (mnemonic == "MOV" && matches!(operands.first(), Some(Operand::Label(pc)) if pc.eq_ignore_ascii_case("PC")))
+        {
+            // Whatever follows is reached by jumps, which may bring flags along
+            self.flags = FlagState::Entry;
+        } else if CALLS.contains(&mnemonic) {
+            self.flags = FlagState::Unknown;
+        } else if KEEPS_FLAGS.contains(&mnemonic) && !sets_flags_reg {
+            if matches!(self.flags, FlagState::Entry | FlagState::Stale(_)) {
+                self.flags = FlagState::Stale(mnemonic.to_string());
+            }
+        } else {
+            self.flags = match (mnemonic, operands) {
+                ("CMP", [Operand::Register(a), Operand::Register(b)])
+                | ("SUB", [Operand::Register(_), Operand::Register(a), Operand::Register(b)])
+                    if *a == 0 || *b == 0 => FlagState::ZeroCompare(mnemonic.to_string(), *b == 0),
+                _ => FlagState::Set,
+            };
         }
+    }
 
+    /// Rewind every section to its start and make .text current
+    fn begin_sections(&mut self) {
+        for sec in self.sections.iter_mut() {
+            sec.pc = sec.start;
+            sec.end = sec.start;
+        }
+        self.section = Section::Text;
+        self.pc = self.sections[Section::Text as usize].start;
+    }
+
+    /// Record the final location counter of the current section
+    fn end_sections(&mut self) {
+        let sec = &mut self.sections[self.section as usize];
+        sec.pc = self.pc;
+        sec.end = sec.end.max(self.pc);
+    }
+
+    /// Place .data after .text and .bss after .data, unless given an origin
+    fn place_sections(&mut self) {
+        let text_end = self.sections[Section::Text as usize].end;
+        let data = &mut self.sections[Section::Data as usize];
+        let data_size = data.end.saturating_sub(data.start);
+        data.start = data.origin.unwrap_or(text_end.wrapping_add(1) & !1);
+        let data_end = data.start.wrapping_add(data_size);
+        let bss = &mut self.sections[Section::Bss as usize];
+        bss.start = bss.origin.unwrap_or(data_end.wrapping_add(1) & !1);
+    }
+
+    fn switch_section(&mut self, name: &str, args: &[DirectiveArg]) {
+        let section = match name {
+            "data" => Section::Data,
+            "bss" => Section::Bss,
+            _ => Section::Text,
+        };
+        self.end_sections();
+        self.section = section;
+        self.pc = self.sections[section as usize].pc;
+        if let Some(DirectiveArg::Number(addr)) = args.first() {
+            let addr = *addr as u16;
+            let sec = &mut self.sections[section as usize];
+            if sec.origin.is_none() {
+                sec.origin = Some(addr);
+                sec.start = addr;
+                sec.end = addr;
+            }
+            self.set_pc(addr);
+        }
+    }
+
+    /// Move the location counter of the current section, as `.org` does
+    fn set_pc(&mut self, addr: u16) {
+        let sec = &mut self.sections[self.section as usize];
+        sec.end = sec.end.max(self.pc);
+        self.pc = addr;
+    }
+
+    /// Index of the pool entry for `value`, adding it if new
+    fn pool_entry(&mut self, value: Either) -> usize {
+        match self.pool.iter().position(|e| e.value == value) {
+            Some(idx) => idx,
+            None => {
+                self.pool.push(PoolEntry { value, refs: Vec::new() });
+                self.pool.len() - 1
+            }
+        }
+    }
+
+    /// Pass 1: reserve space for the pending literal pool
+    fn skip_pool(&mut self) {
+        if !self.pool.is_empty() {
+            self.pc += (self.pc & 1) + 2 * self.pool.len() as u16;
+            self.pool.clear();
+        }
+    }
+
+    /// Pass 2: place the pending literals here, word-aligned, and point
+    /// the loads that use them at their addresses
+    fn emit_pool(&mut self) {
+        if self.pool.is_empty() {
+            return;
+        }
+        let start = self.pc;
+        if self.pc & 1 != 0 {
+            self.emit_byte(0);
+        }
+        for entry in std::mem::take(&mut self.pool) {
+            let addr = self.pc;
+            match &entry.value {
+                Either::Imm(v) => self.emit_word(*v as u16),
+                Either::Label(sym) => self.emit_symbol_word(sym, false),
+            }
+            for r in entry.refs {
+                self.output[r as usize] = addr as u8;
+                self.output[r as usize + 1] = (addr >> 8) as u8;
+            }
+        }
+        self.listing.push(ListEntry { line: None, addr: start, len: self.pc - start });
+    }
+
+    /// `LW`/`LIX Rd, =value`: LWX Rd, pool_addr(R0)
+    fn emit_pool_load(&mut self, operands: &[Operand]) -> Result<(), String> {
+        let rd = match operands.first() {
+            Some(Operand::Register(r)) if operands.len() == 2 => *r,
+            _ => return Err("Expected register and =value".to_string()),
+        };
+        let value = pool_literal(operands)?.expect("caller checked for a literal");
+        let idx = self.pool_entry(value);
+        self.emit_word(0xF000 | ((rd as u16) << 8) | 0x05);
+        self.pool[idx].refs.push(self.pc);
+        self.emit_word(0);
         Ok(())
     }
 
-    fn instruction_size(&self, mnemonic: &str, _operands: &[Operand]) -> Result<u16, String> {
+    /// Target of `.org`: a number, or a constant or label defined earlier
+    fn org_address(&self, args: &[DirectiveArg]) -> Result<u16, String> {
+        match args.first() {
+            Some(DirectiveArg::Number(n)) => u16::try_from(*n)
+                .map_err(|_| format!(".org address out of range: {}", n)),
+            Some(DirectiveArg::Ident(sym)) => self.symbols.get(sym).copied()
+                .ok_or_else(|| format!("Undefined symbol in .org: {}", sym)),
+            _ => Err(".org requires an address".to_string()),
+        }
+    }
+
+    /// `.vectors base, count, default[, slot, label]...`: the table's base
+    /// and the jump target of each slot, `default` unless a pair names it
+    fn vector_table(&self, args: &[DirectiveArg]) -> Result<(u16, Vec<String>), String> {
+        let number = |arg: &DirectiveArg| match arg {
+            DirectiveArg::Number(n) => Ok(*n),
+            DirectiveArg::Ident(sym) if self.constants.contains(sym) => Ok(self.symbols[sym] as i64),
+            _ => Err(".vectors base, count and slots must be numbers or constants".to_string()),
+        };
+        let [base, count, DirectiveArg::Ident(default), overrides @ ..] = args else {
+            return Err(".vectors requires base, count, default label".to_string());
+        };
+        let (base, count) = (number(base)?, number(count)?);
+        if !(0..=0xFFFF).contains(&base) || base % 2 != 0 {
+            return Err(format!(".vectors base must be an even address: {}", base));
+        }
+        if count < 1 || base + count * 2 > 0x10000 {
+            return Err(format!(".vectors count out of range: {}", count));
+        }
+        let mut targets = vec![default.clone(); count as usize];
+        for pair in overrides.chunks(2) {
+            let [slot, DirectiveArg::Ident(label)] = pair else {
+                return Err(".vectors overrides are slot, label pairs".to_string());
+            };
+            let slot = number(slot)?;
+            let target = usize::try_from(slot).ok().and_then(|s| targets.get_mut(s))
+                .ok_or_else(|| format!(".vectors slot {} out of range (0..{})", slot, count - 1))?;
+            *target = label.clone();
+        }
+        Ok((base as u16, targets))
+    }
+
+    fn space_size(&self, args: &[DirectiveArg]) -> Result<u16, String> {
+        match args.first() {
+            Some(DirectiveArg::Number(n)) if *n >= 0 => Ok(*n as u16),
+            Some(DirectiveArg::Ident(sym)) => self.symbols.get(sym).copied()
+                .ok_or_else(|| format!("Undefined symbol: {}", sym)),
+            _ => Err(".space requires a byte count".to_string()),
+        }
+    }
+
+    /// Bytes needed to reach the `.align <n>[, fill]` boundary, and the fill byte
+    fn align_padding(&self, args: &[DirectiveArg]) -> Result<(u16, u8), String> {
+        let value = |arg: &DirectiveArg| match arg {
+            DirectiveArg::Number(n) => Ok(*n),
+            DirectiveArg::Ident(sym) => self.symbols.get(sym).map(|&v| v as i64)
+                .ok_or_else(|| format!("Undefined symbol: {}", sym)),
+            _ => Err(".align expects numbers".to_string()),
+        };
+        let boundary = match args.first() {
+            Some(arg) => value(arg)?,
+            None => return Err(".align requires a boundary".to_string()),
+        };
+        if !(1..=0x8000).contains(&boundary) {
+            return Err(format!(".align boundary out of range: {}", boundary));
+        }
+        let fill = match args.get(1) {
+            Some(arg) => value(arg)?,
+            None => 0,
+        };
+        if !(-128..=255).contains(&fill) {
+            return Err(format!(".align fill byte out of range: {}", fill));
+        }
+        let boundary = boundary as u32;
+        let pad = (boundary - self.pc as u32 % boundary) % boundary;
+        Ok((pad as u16, fill as u8))
+    }
+
+    fn instruction_size(&self, mnemonic: &str, operands: &[Operand]) -> Result<u16, String> {
         // Most instructions are 2 bytes (16-bit)
         // Extended instructions (0xF prefix) are 4 bytes
         match mnemonic.to_uppercase().as_str() {
             // Extended 32-bit instructions
-            "LIX" | "ADDIX" | "SUBIX" | "ANDIX" | "ORIX" | "XORIX" |
-            "LWX" | "SWX" | "JX" | "JALX" | "CMPIX" | "INX" | "OUTX" |
-            "SLLX" | "SRLX" | "SRAX" |
-            // INI and OUTI also use extended format for 8-bit port
+            "LIX" | "LA" | "ADDIX" | "SUBIX" | "ANDIX" | "ORIX" | "XORIX" |
+            "LWX" | "SWX" | "JX" | "JALX" | "CAS" | "CMPIX" | "INX" | "OUTX" |
+            "SLLX" | "SRLX" | "SRAX" | "ADDS" | "SUBS" | "ADDSU" | "SUBSU" |
+            "BSET" | "BCLR" | "BTST" |
+            // JAL is JALX with RA, and NOT is XORIX with 0xFFFF
+            "JAL" | "NOT" |
+            // INI and OUTI also use extended format for 8-bit port,
+            // as do IN/OUT when given a literal port
             "INI" | "OUTI" => Ok(4),
+            "IN" | "OUT" if literal_port(mnemonic, operands) => Ok(4),
+            // Short only once the value is known; a forward reference
+            // starts long and may shrink on a later scan
+            "LI" => Ok(if self.li_upper(operands).is_some() { 2 } else { 4 }),
+            // Loads from the literal pool use LWX
+            "LW" if matches!(operands.get(1), Some(Operand::Literal(_))) => Ok(4),
+            // Offsets without a short func are promoted to LWX/SWX
+            "LW" => Ok(match operands.get(1) {
+                Some(Operand::Indirect(_, off)) if classify_offset("LW", *off, true)? == OffsetForm::Extended => 4,
+                _ => 2,
+            }),
+            "SW" => Ok(match operands.first() {
+                Some(Operand::Indirect(_, off)) if classify_offset("SW", *off, false)? == OffsetForm::Extended => 4,
+                _ => 2,
+            }),
             // All others are 16-bit
             _ => Ok(2),
         }
     }
 
     fn emit_directive(&mut self, name: &str, args: &[DirectiveArg]) -> Result<(), String> {
+        if self.section == Section::Bss && matches!(name, "db" | "dw" | "dwbe" | "dd" | "jmptable" | "vectors" | "checksum" | "ascii" | "asciz") {
+            return Err(format!(".{} in .bss section (use .space)", name));
+        }
         match name {
             "org" => {
-                if let Some(DirectiveArg::Number(addr)) = args.first() {
-                    self.pc = *addr as u16;
-                    while self.output.len() < self.pc as usize {
-                        self.output.push(0);
+                let addr = self.org_address(args)?;
+                self.set_pc(addr);
+            }
+            "vectors" => {
+                // Each slot is the `J` that SWI n or an interrupt lands on
+                let (base, targets) = self.vector_table(args)?;
+                self.set_pc(base);
+                for target in targets {
+                    self.emit_jump(&[Operand::Label(target)])?;
+                }
+            }
+            "text" | "data" | "bss" => {
+                self.emit_pool();
+                self.switch_section(name, args);
+            }
+            "pool" => self.emit_pool(),
+            "space" => {
+                let size = self.space_size(args)?;
+                if self.section == Section::Bss {
+                    self.pc += size;
+                } else {
+                    for _ in 0..size {
+                        self.emit_byte(0);
+                    }
+                }
+            }
+            "align" => {
+                let (pad, fill) = self.align_padding(args)?;
+                if self.section == Section::Bss {
+                    self.pc += pad;
+                } else {
+                    for _ in 0..pad {
+                        self.emit_byte(fill);
+                    }
+                }
+            }
+            "def" => {
+                for arg in args {
+                    match arg {
+                        DirectiveArg::Ident(flag) if flag == "sp_is_general" => self.sp_is_general = true,
+                        DirectiveArg::Ident(flag) => return Err(format!("Unknown .def flag: {}", flag)),
+                        _ => return Err(".def requires a flag name".to_string()),
                     }
                 }
             }
             "equ" => {} // Already handled in pass 1
+            "global" | "export" => {
+                for arg in args {
+                    match arg {
+                        DirectiveArg::Ident(sym) if self.symbols.contains_key(sym) => {}
+                        DirectiveArg::Ident(sym) => {
+                            return Err(format!("Undefined global symbol: {}", sym));
+                        }
+                        _ => return Err(format!(".{} requires symbol names", name)),
+                    }
+                }
+            }
             "db" => {
                 for arg in args {
                     match arg {
                         DirectiveArg::Number(n) => {
-                            self.emit_byte(*n as u8);
+                            self.emit_byte(data_byte(*n)?);
                         }
                         DirectiveArg::String(s) => {
-                            for b in s.bytes() {
+                            for &b in s {
                                 self.emit_byte(b);
                             }
                         }
                         DirectiveArg::Ident(sym) => {
                             if let Some(&val) = self.symbols.get(sym) {
-                                self.emit_byte(val as u8);
+                                // Symbols hold 16 bits, so 0xFF80-0xFFFF is -128..-1
+                                let byte = data_byte(val as i16 as i64)
+                                    .map_err(|_| format!("{} (0x{:04X}) does not fit in a byte", sym, val))?;
+                                self.emit_byte(byte);
                             } else {
                                 return Err(format!("Undefined symbol: {}", sym));
                             }
                         }
+                        DirectiveArg::PcRel(_) => {
+                            return Err("%pcrel() needs a word directive (.dw or .dwbe)".to_string());
+                        }
+                        DirectiveArg::ByteOf(high, terms) => {
+                            // Always resolved after pass 2, when every label is placed
+                            self.byte_sums.push(ByteSum {
+                                address: self.pc,
+                                high: *high,
+                                terms: terms.clone(),
+                                line: self.line,
+                            });
+                            self.emit_byte(0);
+                        }
+                        DirectiveArg::Condition(_) => unreachable!("only .if/.elif take a condition"),
                     }
                 }
             }
-            "dw" => {
+            "dw" | "dwbe" => {
+                let big_endian = name == "dwbe";
                 for arg in args {
                     match arg {
                         DirectiveArg::Number(n) => {
-                            self.emit_word(*n as u16);
+                            if !(-32768..=65535).contains(n) {
+                                return Err(format!("Value {} does not fit in a word (-32768..65535)", n));
+                            }
+                            self.emit_data_word(*n as u16, big_endian);
                         }
-                        DirectiveArg::Ident(sym) => {
-                            if let Some(&val) = self.symbols.get(sym) {
-                                self.emit_word(val);
-                            } else {
-                                // Add fixup
-                                self.fixups.push(Fixup {
-                                    address: self.pc,
-                                    symbol: sym.clone(),
-                                    kind: FixupKind::Absolute16,
-                                });
-                                self.emit_word(0);
+                        DirectiveArg::Ident(sym) => self.emit_symbol_word(sym, big_endian),
+                        DirectiveArg::PcRel(sym) => {
+                            // Always resolved at fixup time, relative to this word
+                            self.fixups.push(Fixup {
+                                address: self.pc,
+                                symbol: sym.clone(),
+                                kind: if big_endian { FixupKind::PcRel16Be } else { FixupKind::PcRel16 },
+                                line: self.line,
+                            });
+                            self.emit_word(0);
+                        }
+                        _ => return Err(format!("Invalid .{} argument", name)),
+                    }
+                }
+            }
+            "dd" => {
+                // 32-bit values as two little-endian words, low word first
+                for arg in args {
+                    match arg {
+                        DirectiveArg::Number(n) => {
+                            if !(-0x8000_0000..=0xFFFF_FFFF).contains(n) {
+                                return Err(format!("Value {} does not fit in 32 bits (-2147483648..4294967295)", n));
                             }
+                            let n = *n as u32;
+                            self.emit_word(n as u16);
+                            self.emit_word((n >> 16) as u16);
+                        }
+                        DirectiveArg::Ident(sym) => {
+                            self.emit_symbol_word(sym, false);
+                            self.emit_word(0);
                         }
-                        _ => return Err("Invalid .dw argument".to_string()),
+                        _ => return Err("Invalid .dd argument".to_string()),
                     }
                 }
             }
+            "jmptable" => {
+                // Offsets from the table itself, so the table and the code
+                // around it can be moved together
+                let base = self.pc;
+                for arg in args {
+                    let DirectiveArg::Ident(sym) = arg else {
+                        return Err(".jmptable entries must be labels".to_string());
+                    };
+                    self.fixups.push(Fixup {
+                        address: self.pc,
+                        symbol: sym.clone(),
+                        kind: FixupKind::TableRel16(base),
+                        line: self.line,
+                    });
+                    self.emit_word(0);
+                }
+            }
+            "checksum" => {
+                let bound = |arg: &DirectiveArg| match arg {
+                    DirectiveArg::Number(n) => i32::try_from(*n).ok().map(Either::Imm),
+                    DirectiveArg::Ident(sym) => Some(Either::Label(sym.clone())),
+                    _ => None,
+                };
+                let [start, end] = args else {
+                    return Err(".checksum requires start, end".to_string());
+                };
+                let (Some(start), Some(end)) = (bound(start), bound(end)) else {
+                    return Err(".checksum bounds must be addresses or symbols".to_string());
+                };
+                self.checksums.push(Checksum { address: self.pc, start, end, line: self.line });
+                self.emit_word(0);
+            }
             "ascii" => {
                 for arg in args {
                     if let DirectiveArg::String(s) = arg {
-                        for b in s.bytes() {
+                        for &b in s {
                             self.emit_byte(b);
                         }
                     }
@@ -206,7 +1170,7 @@ impl CodeGen {
             "asciz" => {
                 for arg in args {
                     if let DirectiveArg::String(s) = arg {
-                        for b in s.bytes() {
+                        for &b in s {
                             self.emit_byte(b);
                         }
                         self.emit_byte(0);
@@ -218,68 +1182,149 @@ impl CodeGen {
         Ok(())
     }
 
+    fn emit_data_word(&mut self, val: u16, big_endian: bool) {
+        if big_endian {
+            self.emit_byte((val >> 8) as u8);
+            self.emit_byte(val as u8);
+        } else {
+            self.emit_word(val);
+        }
+    }
+
+    /// Emit a symbol's address as a data word, patched later if it is not yet known
+    fn emit_symbol_word(&mut self, sym: &str, big_endian: bool) {
+        if let Some(&val) = self.symbols.get(sym) {
+            self.emit_data_word(val, big_endian);
+        } else {
+            self.fixups.push(Fixup {
+                address: self.pc,
+                symbol: sym.to_string(),
+                kind: if big_endian { FixupKind::Absolute16Be } else { FixupKind::Absolute16 },
+                line: self.line,
+            });
+            self.emit_word(0);
+        }
+    }
+
     fn emit_instruction(&mut self, mnemonic: &str, operands: &[Operand]) -> Result<(), String> {
         let upper = mnemonic.to_uppercase();
+        if !MNEMONICS.contains(&upper.as_str()) {
+            // A lone word is more likely a label missing its colon
+            if operands.is_empty() {
+                return Err(format!(
+                    "Unknown instruction: {} (did you mean a label? Labels end with ':')",
+                    mnemonic
+                ));
+            }
+            return Err(format!("Unknown instruction: {}", mnemonic));
+        }
         match upper.as_str() {
-            // Opcode 0x0: ADD Rd, Rs1, Rs2
+            // Opcode 0x0: ADD Rd, Rs1, Rs2 (ADD Rd, Rs is ADD Rd, Rd, Rs, as for SUB/AND/OR/XOR)
+            #[allow(clippy::identity_op)] // Zero fields spelled out to match the encoding table
             "ADD" => {
-                let (rd, rs1, rs2) = self.get_three_regs(operands)?;
+                let (rd, rs1, rs2) = self.get_alu_regs(operands)?;
                 self.emit_word(0x0000 | ((rd as u16) << 8) | ((rs1 as u16) << 4) | (rs2 as u16));
             }
             // Opcode 0x1: SUB Rd, Rs1, Rs2
             "SUB" => {
-                let (rd, rs1, rs2) = self.get_three_regs(operands)?;
+                let (rd, rs1, rs2) = self.get_alu_regs(operands)?;
                 self.emit_word(0x1000 | ((rd as u16) << 8) | ((rs1 as u16) << 4) | (rs2 as u16));
             }
             // Opcode 0x2: AND Rd, Rs1, Rs2
             "AND" => {
-                let (rd, rs1, rs2) = self.get_three_regs(operands)?;
+                let (rd, rs1, rs2) = self.get_alu_regs(operands)?;
                 self.emit_word(0x2000 | ((rd as u16) << 8) | ((rs1 as u16) << 4) | (rs2 as u16));
             }
             // Opcode 0x3: OR Rd, Rs1, Rs2
             "OR" => {
-                let (rd, rs1, rs2) = self.get_three_regs(operands)?;
+                let (rd, rs1, rs2) = self.get_alu_regs(operands)?;
                 self.emit_word(0x3000 | ((rd as u16) << 8) | ((rs1 as u16) << 4) | (rs2 as u16));
             }
             // Opcode 0x4: XOR Rd, Rs1, Rs2
             "XOR" => {
-                let (rd, rs1, rs2) = self.get_three_regs(operands)?;
+                let (rd, rs1, rs2) = self.get_alu_regs(operands)?;
                 self.emit_word(0x4000 | ((rd as u16) << 8) | ((rs1 as u16) << 4) | (rs2 as u16));
             }
             // Opcode 0x5: ADDI Rd, imm8
             "ADDI" => {
                 let (rd, imm) = self.get_reg_imm(operands)?;
-                if imm < -128 || imm > 127 {
+                if !(-128..=127).contains(&imm) {
                     return Err(format!("Immediate {} out of range for ADDI", imm));
                 }
                 self.emit_word(0x5000 | ((rd as u16) << 8) | ((imm as u8) as u16));
             }
             // Opcode 0x6: Load operations
+            "LW" | "LIX" if matches!(operands.get(1), Some(Operand::Literal(_))) => {
+                self.emit_pool_load(operands)?;
+            }
+            "LW" if matches!(operands.get(1), Some(Operand::PostInc(_) | Operand::PreDec(_))) => {
+                let (rd, rs, func) = match operands {
+                    [Operand::Register(rd), Operand::PostInc(rs)] => (*rd, *rs, 0x9),
+                    [Operand::Register(rd), Operand::PreDec(rs)] => (*rd, *rs, 0xA),
+                    _ => return Err("Expected LW Rd, (Rs)+ or LW Rd, -(Rs)".to_string()),
+                };
+                self.emit_word(0x6000 | ((rd as u16) << 8) | ((rs as u16) << 4) | func);
+            }
             "LW" => {
                 let (rd, rs, offset) = self.get_load_store_ops(operands)?;
-                let func = self.offset_to_func(offset, true)?;
-                self.emit_word(0x6000 | ((rd as u16) << 8) | ((rs as u16) << 4) | func);
+                match classify_offset("LW", offset, true)? {
+                    OffsetForm::Short(func) => self.emit_word(0x6000 | ((rd as u16) << 8) | ((rs as u16) << 4) | func),
+                    // Other offsets need the extended form
+                    OffsetForm::Extended => self.emit_extended(0x5, rd, rs, offset)?,
+                }
+            }
+            "LWX" => {
+                let (rd, rs, offset) = self.get_load_store_ops(operands)?;
+                classify_offset("LWX", offset, true)?;
+                self.emit_extended(0x5, rd, rs, offset)?;
             }
             "LB" => {
-                let (rd, rs, _) = self.get_load_store_ops(operands)?;
+                let (rd, rs) = self.get_byte_ops(mnemonic, self.get_load_store_ops(operands)?)?;
                 self.emit_word(0x6000 | ((rd as u16) << 8) | ((rs as u16) << 4) | 0x1);
             }
             "LBU" => {
-                let (rd, rs, _) = self.get_load_store_ops(operands)?;
+                let (rd, rs) = self.get_byte_ops(mnemonic, self.get_load_store_ops(operands)?)?;
                 self.emit_word(0x6000 | ((rd as u16) << 8) | ((rs as u16) << 4) | 0x2);
             }
+            // The immediate sits in the Rs1 field, so only 0-15 fit
             "LUI" => {
                 let (rd, imm) = self.get_reg_imm(operands)?;
-                self.emit_word(0x6000 | ((rd as u16) << 8) | ((imm as u8) as u16) | 0x08);
+                if !(0..=15).contains(&imm) {
+                    return Err(format!("Immediate {} out of range for LUI (0-15)", imm));
+                }
+                self.emit_word(0x6000 | ((rd as u16) << 8) | ((imm as u16) << 4) | 0x8);
             }
+            // LI Rd, value: LUI when the value is n << 8 with n < 16, else LIX
+            "LI" => match self.li_upper(operands) {
+                Some(n) => {
+                    let rd = self.get_one_reg(&operands[..1])?;
+                    self.emit_word(0x6000 | ((rd as u16) << 8) | (n << 4) | 0x8);
+                }
+                None => self.emit_instruction("LIX", operands)?,
+            },
             // Opcode 0x7: Store operations
+            "SW" if matches!(operands.first(), Some(Operand::PostInc(_) | Operand::PreDec(_))) => {
+                let (rs2, rs1, func) = match operands {
+                    [Operand::PostInc(rs1), Operand::Register(rs2)] => (*rs2, *rs1, 0x7),
+                    [Operand::PreDec(rs1), Operand::Register(rs2)] => (*rs2, *rs1, 0x8),
+                    _ => return Err("Expected SW (Rs)+, Rs2 or SW -(Rs), Rs2".to_string()),
+                };
+                self.emit_word(0x7000 | ((rs2 as u16) << 8) | ((rs1 as u16) << 4) | func);
+            }
             "SW" => {
                 let (rs2, rs1, offset) = self.get_store_ops(operands)?;
-                let func = self.offset_to_func(offset, false)?;
-                self.emit_word(0x7000 | ((rs2 as u16) << 8) | ((rs1 as u16) << 4) | func);
+                match classify_offset("SW", offset, false)? {
+                    OffsetForm::Short(func) => self.emit_word(0x7000 | ((rs2 as u16) << 8) | ((rs1 as u16) << 4) | func),
+                    OffsetForm::Extended => self.emit_extended(0x6, rs2, rs1, offset)?,
+                }
+            }
+            "SWX" => {
+                let (rs2, rs1, offset) = self.get_store_ops(operands)?;
+                classify_offset("SWX", offset, false)?;
+                self.emit_extended(0x6, rs2, rs1, offset)?;
             }
             "SB" => {
-                let (rs2, rs1, _) = self.get_store_ops(operands)?;
+                let (rs2, rs1) = self.get_byte_ops(mnemonic, self.get_store_ops(operands)?)?;
                 self.emit_word(0x7000 | ((rs2 as u16) << 8) | ((rs1 as u16) << 4) | 0x1);
             }
             // Opcode 0x8: Branch operations
@@ -301,32 +1346,35 @@ impl CodeGen {
             "BLS" => self.emit_branch(0xF, operands)?,
             // Opcode 0x9: Jump operations
             "J" => self.emit_jump(operands)?,
+            // Register jumps live in the misc group (func 0xE); JR is JALR R0
             "JR" => {
                 let rs = self.get_one_reg(operands)?;
-                self.emit_word(0x9F00 | ((rs as u16) << 4));
+                self.emit_word(0xC000 | ((rs as u16) << 4) | 0xE);
             }
             "JALR" => {
                 let (rd, rs) = self.get_two_regs(operands)?;
-                self.emit_word(0x9000 | ((rd as u16) << 8) | ((rs as u16) << 4) | 0x1);
+                self.emit_word(0xC000 | ((rd as u16) << 8) | ((rs as u16) << 4) | 0xE);
             }
             "JAL" => {
                 // JAL uses extended format for full address
                 if let Some(Operand::Label(label)) = operands.first() {
-                    self.emit_word(0xF000 | ((1 as u16) << 8) | 0x09); // RA, sub=9 (JALX)
+                    self.emit_word(0xF000 | (1u16 << 8) | 0x09); // RA, sub=9 (JALX)
                     self.fixups.push(Fixup {
                         address: self.pc,
                         symbol: label.clone(),
                         kind: FixupKind::Absolute16,
+                        line: self.line,
                     });
                     self.emit_word(0);
                 } else if let Some(Operand::Immediate(addr)) = operands.first() {
-                    self.emit_word(0xF000 | ((1 as u16) << 8) | 0x09);
+                    self.emit_word(0xF000 | (1u16 << 8) | 0x09);
                     self.emit_word(*addr as u16);
                 } else {
                     return Err("JAL requires a label or address".to_string());
                 }
             }
             // Opcode 0xA: Shift operations
+            #[allow(clippy::identity_op)]
             "SLL" => {
                 let (rd, rs) = self.get_two_regs(operands)?;
                 self.emit_word(0xA000 | ((rd as u16) << 8) | ((rs as u16) << 4) | 0x0);
@@ -352,6 +1400,7 @@ impl CodeGen {
                 self.emit_word(0xA000 | ((rd as u16) << 8) | ((rs as u16) << 4) | 0x7);
             }
             // Opcode 0xB: Multiply/Divide
+            #[allow(clippy::identity_op)]
             "MUL" => {
                 let (rd, rs) = self.get_two_regs(operands)?;
                 self.emit_word(0xB000 | ((rd as u16) << 8) | ((rs as u16) << 4) | 0x0);
@@ -384,7 +1433,34 @@ impl CodeGen {
                 let rd = self.get_one_reg(operands)?;
                 self.emit_word(0xB000 | ((rd as u16) << 8) | 0x7);
             }
+            // Extended sub 0xB (INX) with the op in imm16[15:8]
+            "ADDS" | "SUBS" | "ADDSU" | "SUBSU" => {
+                let op = match upper.as_str() {
+                    "ADDS" => 0x01,
+                    "SUBS" => 0x02,
+                    "ADDSU" => 0x03,
+                    _ => 0x04,
+                };
+                let (rd, rs) = self.get_two_regs(operands)?;
+                self.emit_word(0xF000 | ((rd as u16) << 8) | ((rs as u16) << 4) | 0xB);
+                self.emit_word(op << 8);
+            }
+            // Also extended sub 0xB, with the bit number in imm16[3:0]
+            "BSET" | "BCLR" | "BTST" => {
+                let op = match upper.as_str() {
+                    "BSET" => 0x05,
+                    "BCLR" => 0x06,
+                    _ => 0x07,
+                };
+                let (rd, bit) = self.get_reg_imm(operands)?;
+                if !(0..=15).contains(&bit) {
+                    return Err(format!("Bit number {} out of range for {} (0-15)", bit, upper));
+                }
+                self.emit_word(0xF000 | ((rd as u16) << 8) | 0xB);
+                self.emit_word((op << 8) | bit as u16);
+            }
             // Opcode 0xC: Stack and misc
+            #[allow(clippy::identity_op)]
             "PUSH" => {
                 let rs = self.get_one_reg(operands)?;
                 self.emit_word(0xC000 | ((rs as u16) << 4) | 0x0);
@@ -401,6 +1477,24 @@ impl CodeGen {
                 let (rd, rs) = self.get_two_regs(operands)?;
                 self.emit_word(0xC000 | ((rd as u16) << 8) | ((rs as u16) << 4) | 0x3);
             }
+            // PC and FLAGS operands: reading PC has its own func; the
+            // others are JR, GETF and SETF
+            "MOV" if matches!(operands, [Operand::Register(_), Operand::Label(pc)] if pc.eq_ignore_ascii_case("PC")) => {
+                let rd = self.get_one_reg(&operands[..1])?;
+                self.emit_word(0xC000 | ((rd as u16) << 8) | 0xF);
+            }
+            "MOV" if matches!(operands, [Operand::Label(pc), Operand::Register(_)] if pc.eq_ignore_ascii_case("PC")) => {
+                let rs = self.get_one_reg(&operands[1..])?;
+                self.emit_word(0xC000 | ((rs as u16) << 4) | 0xE);
+            }
+            "MOV" if matches!(operands, [Operand::Register(_), Operand::Label(f)] if f.eq_ignore_ascii_case("FLAGS")) => {
+                let rd = self.get_one_reg(&operands[..1])?;
+                self.emit_word(0xC000 | ((rd as u16) << 8) | 0xC);
+            }
+            "MOV" if matches!(operands, [Operand::Label(f), Operand::Register(_)] if f.eq_ignore_ascii_case("FLAGS")) => {
+                let rs = self.get_one_reg(&operands[1..])?;
+                self.emit_word(0xC000 | ((rs as u16) << 4) | 0xD);
+            }
             "MOV" => {
                 let (rd, rs) = self.get_two_regs(operands)?;
                 self.emit_word(0xC000 | ((rd as u16) << 8) | ((rs as u16) << 4) | 0x4);
@@ -421,32 +1515,18 @@ impl CodeGen {
                 self.emit_word(0xC000 | ((rs as u16) << 4) | 0xD);
             }
             // Opcode 0xD: I/O
+            "IN" if literal_port(&upper, operands) => self.emit_in_port(&upper, operands)?,
             "IN" => {
                 let (rd, port) = self.get_in_operands(operands)?;
                 self.emit_word(0xD000 | ((rd as u16) << 8) | ((port as u16) << 4) | 0x2);
             }
-            "INI" => {
-                let (rd, port) = self.get_reg_imm(operands)?;
-                if port < 0 || port > 255 {
-                    return Err("Port number out of range".to_string());
-                }
-                // Use extended format for 8-bit port
-                self.emit_word(0xF000 | ((rd as u16) << 8) | 0x0B);
-                self.emit_word(port as u16);
-            }
+            "INI" | "INX" => self.emit_in_port(&upper, operands)?,
+            "OUT" if literal_port(&upper, operands) => self.emit_out_port(&upper, operands)?,
             "OUT" => {
                 let (port, rs) = self.get_out_operands(operands)?;
                 self.emit_word(0xD000 | ((rs as u16) << 8) | ((port as u16) << 4) | 0x3);
             }
-            "OUTI" => {
-                let (port, rs) = self.get_imm_reg(operands)?;
-                if port < 0 || port > 255 {
-                    return Err("Port number out of range".to_string());
-                }
-                // Use extended format for 8-bit port
-                self.emit_word(0xF000 | ((rs as u16) << 4) | 0x0C);
-                self.emit_word(port as u16);
-            }
+            "OUTI" | "OUTX" => self.emit_out_port(&upper, operands)?,
             // Opcode 0xE: System
             "NOP" => self.emit_word(0xE000),
             "HALT" => self.emit_word(0xE100),
@@ -455,12 +1535,50 @@ impl CodeGen {
             "RETI" => self.emit_word(0xE400),
             "SWI" => {
                 let imm = self.get_imm(operands)?;
-                self.emit_word(0xE500 | ((imm as u8) as u16));
+                if !(0..=255).contains(&imm) {
+                    return Err(format!("SWI vector out of range: {}", imm));
+                }
+                if imm == 0x7F {
+                    return Err("SWI 127 is reserved: its slot at 0x00FE is the TRAP vector".to_string());
+                }
+                self.emit_word(0xE500 | (imm as u16));
+            }
+            "TRAP" => {
+                let imm = match operands {
+                    [] => 0,
+                    _ => self.get_imm(operands)?,
+                };
+                if !(0..=255).contains(&imm) {
+                    return Err(format!("TRAP code out of range: {}", imm));
+                }
+                self.emit_word(0xEC00 | (imm as u16));
             }
             "SCF" => self.emit_word(0xE600),
             "CCF" => self.emit_word(0xE700),
+            "CSKIP" => {
+                let cond = match operands {
+                    [Operand::Label(name)] => CONDITIONS.iter()
+                        .position(|c| c.eq_ignore_ascii_case(name))
+                        .ok_or_else(|| format!("Unknown condition: {}", name))?,
+                    _ => return Err("CSKIP expects a condition (EQ, NE, LT, ...)".to_string()),
+                };
+                self.emit_word(0xEA00 | cond as u16);
+            }
+            "RDCYC" => {
+                let (rd, word) = match operands {
+                    [Operand::Register(rd)] => (*rd, 0),
+                    [Operand::Register(rd), Operand::Immediate(w)] => (*rd, *w),
+                    _ => return Err("Expected RDCYC Rd[, word]".to_string()),
+                };
+                if !(0..=3).contains(&word) {
+                    return Err(format!("RDCYC word out of range (0-3): {}", word));
+                }
+                self.emit_word(0xEB00 | ((rd as u16) << 4) | word as u16);
+            }
             // Extended 32-bit instructions
-            "LIX" => {
+            // LA Rd, symbol: a full 16-bit address in one LIX, so there is
+            // no LUI/ADDI pair for ADDI's sign extension to break
+            "LIX" | "LA" => {
                 let (rd, imm) = self.get_reg_imm_or_label(operands)?;
                 self.emit_word(0xF000 | ((rd as u16) << 8) | 0x07);
                 match imm {
@@ -470,6 +1588,7 @@ impl CodeGen {
                             address: self.pc,
                             symbol: l,
                             kind: FixupKind::Absolute16,
+                            line: self.line,
                         });
                         self.emit_word(0);
                     }
@@ -477,11 +1596,18 @@ impl CodeGen {
             }
             "JX" => {
                 if let Some(Operand::Label(label)) = operands.first() {
+                    self.long_jumps.push(Fixup {
+                        address: self.pc,
+                        symbol: label.clone(),
+                        kind: FixupKind::Relative12,
+                        line: self.line,
+                    });
                     self.emit_word(0xF008);
                     self.fixups.push(Fixup {
                         address: self.pc,
                         symbol: label.clone(),
                         kind: FixupKind::Absolute16,
+                        line: self.line,
                     });
                     self.emit_word(0);
                 } else if let Some(Operand::Immediate(addr)) = operands.first() {
@@ -498,6 +1624,7 @@ impl CodeGen {
                         address: self.pc,
                         symbol: label.clone(),
                         kind: FixupKind::Absolute16,
+                        line: self.line,
                     });
                     self.emit_word(0);
                 } else if let Some(Operand::Immediate(addr)) = operands.first() {
@@ -507,10 +1634,21 @@ impl CodeGen {
                     return Err("JALX requires address".to_string());
                 }
             }
+            // CAS shares sub 0x8 with JX, which leaves Rs1 zero
+            "CAS" => {
+                let (rd, rs1, rs2) = match operands {
+                    [Operand::Register(rd), Operand::Indirect(rs1, 0), Operand::Register(rs2)] => (*rd, *rs1, *rs2),
+                    _ => return Err("Expected CAS Rd, (Rs1), Rs2".to_string()),
+                };
+                if rs1 == 0 {
+                    return Err("CAS cannot address through R0 (that encoding is JX)".to_string());
+                }
+                self.emit_extended(0x8, rd, rs1, rs2 as i32)?;
+            }
             "NEG" => {
                 let (rd, rs) = self.get_two_regs(operands)?;
                 // NEG is SUB Rd, R0, Rs
-                self.emit_word(0x1000 | ((rd as u16) << 8) | ((rs as u16)));
+                self.emit_word(0x1000 | ((rd as u16) << 8) | (rs as u16));
             }
             "NOT" => {
                 let (rd, rs) = self.get_two_regs(operands)?;
@@ -518,24 +1656,98 @@ impl CodeGen {
                 self.emit_word(0xF000 | ((rd as u16) << 8) | ((rs as u16) << 4) | 0x04);
                 self.emit_word(0xFFFF);
             }
-            _ => return Err(format!("Unknown instruction: {}", mnemonic)),
+            _ => unreachable!("{} is in MNEMONICS but has no encoding", upper),
         }
         Ok(())
     }
 
     fn emit_byte(&mut self, b: u8) {
-        self.output.push(b);
+        // Output is addressed from 0; gaps are zero-filled
+        let addr = self.pc as usize;
+        if self.output.len() <= addr {
+            self.output.resize(addr + 1, 0);
+            self.emitted.resize(addr + 1, false);
+        }
+        if self.emitted[addr] && self.overlap.is_none() {
+            self.overlap = Some(self.pc);
+        }
+        self.output[addr] = b;
+        self.emitted[addr] = true;
         self.pc += 1;
     }
 
     fn emit_word(&mut self, w: u16) {
         // Little-endian
-        self.output.push((w & 0xFF) as u8);
-        self.output.push((w >> 8) as u8);
-        self.pc += 2;
+        self.emit_byte((w & 0xFF) as u8);
+        self.emit_byte((w >> 8) as u8);
+    }
+
+    /// INX Rd, port8 (extended format)
+    fn emit_in_port(&mut self, mnemonic: &str, operands: &[Operand]) -> Result<(), String> {
+        let (rd, port) = self.get_port_operands(mnemonic, operands, false)?;
+        self.emit_word(0xF000 | ((rd as u16) << 8) | 0x0B);
+        self.emit_word(port);
+        Ok(())
+    }
+
+    /// OUTX port8, Rs (extended format)
+    fn emit_out_port(&mut self, mnemonic: &str, operands: &[Operand]) -> Result<(), String> {
+        let (rs, port) = self.get_port_operands(mnemonic, operands, true)?;
+        self.emit_word(0xF000 | ((rs as u16) << 4) | 0x0C);
+        self.emit_word(port);
+        Ok(())
+    }
+
+    /// Register and port number of `IN Rd, port` (or `OUT port, Rs` when
+    /// `port_first`); the port may be a number or a constant
+    fn get_port_operands(&self, mnemonic: &str, operands: &[Operand], port_first: bool) -> Result<(u8, u16), String> {
+        let expected = if port_first { "port, register" } else { "register, port" };
+        let (reg, port) = match (operands, port_first) {
+            ([reg, port], false) | ([port, reg], true) => (reg, port),
+            _ => return Err(format!("{} expects {}; got {} operands", mnemonic, expected, operands.len())),
+        };
+        let (reg, port) = match (reg, port) {
+            (Operand::Register(r), Operand::Immediate(n)) => (*r, *n),
+            (Operand::Register(r), Operand::Label(sym)) => match self.symbols.get(sym) {
+                Some(&val) => (*r, val as i32),
+                None => return Err(format!("Undefined port constant: {}", sym)),
+            },
+            _ => {
+                let got: Vec<&str> = operands.iter().map(operand_kind).collect();
+                return Err(format!("{} expects {}; got {}", mnemonic, expected, got.join(", ")));
+            }
+        };
+        if !(0..=255).contains(&port) {
+            return Err(format!("Port number out of range: {}", port));
+        }
+        Ok((reg, port as u16))
+    }
+
+    /// Branch/jump displacement given as a number, an `.equ` constant or
+    /// `$`-relative, as opposed to a label to reach
+    fn displacement(&self, operand: Option<&Operand>) -> Result<Option<i32>, String> {
+        let offset = match operand {
+            Some(Operand::Immediate(offset)) => *offset,
+            Some(Operand::Label(sym)) if self.constants.contains(sym) => self.symbols[sym] as i16 as i32,
+            // Displacements count from the next instruction
+            Some(Operand::Here(offset)) => offset - 2,
+            _ => return Ok(None),
+        };
+        if offset % 2 != 0 {
+            return Err(format!("Odd displacement {}: instructions are word aligned", offset));
+        }
+        Ok(Some(offset))
     }
 
     fn emit_branch(&mut self, cond: u16, operands: &[Operand]) -> Result<(), String> {
+        if let Some(offset) = self.displacement(operands.first())? {
+            let off = offset / 2; // Convert to words
+            if !(-128..=127).contains(&off) {
+                return Err(format!("Branch offset {} out of range (-256..254)", offset));
+            }
+            self.emit_word(0x8000 | (cond << 8) | ((off as u8) as u16));
+            return Ok(());
+        }
         match operands.first() {
             Some(Operand::Label(label)) => {
                 self.emit_word(0x8000 | (cond << 8));
@@ -544,21 +1756,23 @@ impl CodeGen {
                     address: fixup_addr,
                     symbol: label.clone(),
                     kind: FixupKind::Relative8,
+                    line: self.line,
                 });
             }
-            Some(Operand::Immediate(offset)) => {
-                let off = *offset / 2; // Convert to words
-                if off < -128 || off > 127 {
-                    return Err("Branch offset out of range".to_string());
-                }
-                self.emit_word(0x8000 | (cond << 8) | ((off as u8) as u16));
-            }
             _ => return Err("Branch requires target".to_string()),
         }
         Ok(())
     }
 
     fn emit_jump(&mut self, operands: &[Operand]) -> Result<(), String> {
+        if let Some(offset) = self.displacement(operands.first())? {
+            let off = offset / 2;
+            if !(-2048..=2047).contains(&off) {
+                return Err(format!("Jump offset {} out of range (-4096..4094)", offset));
+            }
+            self.emit_word(0x9000 | ((off as u16) & 0x0FFF));
+            return Ok(());
+        }
         match operands.first() {
             Some(Operand::Label(label)) => {
                 self.emit_word(0x9000);
@@ -567,21 +1781,18 @@ impl CodeGen {
                     address: fixup_addr,
                     symbol: label.clone(),
                     kind: FixupKind::Relative12,
+                    line: self.line,
                 });
             }
-            Some(Operand::Immediate(offset)) => {
-                let off = *offset / 2;
-                if off < -2048 || off > 2047 {
-                    return Err("Jump offset out of range".to_string());
-                }
-                self.emit_word(0x9000 | ((off as u16) & 0x0FFF));
-            }
             _ => return Err("Jump requires target".to_string()),
         }
         Ok(())
     }
 
     fn get_one_reg(&self, operands: &[Operand]) -> Result<u8, String> {
+        if operands.len() > 1 {
+            return Err(format!("Expected a single register, got {} operands", operands.len()));
+        }
         match operands.first() {
             Some(Operand::Register(r)) => Ok(*r),
             _ => Err("Expected register".to_string()),
@@ -598,15 +1809,14 @@ impl CodeGen {
         }
     }
 
-    fn get_three_regs(&self, operands: &[Operand]) -> Result<(u8, u8, u8), String> {
-        if operands.len() < 3 {
-            return Err("Expected three registers".to_string());
-        }
-        match (&operands[0], &operands[1], &operands[2]) {
-            (Operand::Register(r1), Operand::Register(r2), Operand::Register(r3)) => {
-                Ok((*r1, *r2, *r3))
+    /// `Rd, Rs1, Rs2`, or the two-operand shorthand `Rd, Rs` for `Rd, Rd, Rs`
+    fn get_alu_regs(&self, operands: &[Operand]) -> Result<(u8, u8, u8), String> {
+        match operands {
+            [Operand::Register(rd), Operand::Register(rs1), Operand::Register(rs2)] => {
+                Ok((*rd, *rs1, *rs2))
             }
-            _ => Err("Expected three registers".to_string()),
+            [Operand::Register(rd), Operand::Register(rs)] => Ok((*rd, *rd, *rs)),
+            _ => Err("Expected two or three registers".to_string()),
         }
     }
 
@@ -627,24 +1837,10 @@ impl CodeGen {
         }
     }
 
-    fn get_imm_reg(&self, operands: &[Operand]) -> Result<(i32, u8), String> {
-        if operands.len() < 2 {
-            return Err("Expected immediate and register".to_string());
-        }
-        match (&operands[0], &operands[1]) {
-            (Operand::Immediate(i), Operand::Register(r)) => Ok((*i, *r)),
-            (Operand::Label(sym), Operand::Register(r)) => {
-                if let Some(&val) = self.symbols.get(sym) {
-                    Ok((val as i32, *r))
-                } else {
-                    Err(format!("Undefined symbol: {}", sym))
-                }
-            }
-            _ => Err("Expected immediate and register".to_string()),
-        }
-    }
-
     fn get_imm(&self, operands: &[Operand]) -> Result<i32, String> {
+        if operands.len() > 1 {
+            return Err(format!("Expected a single immediate, got {} operands", operands.len()));
+        }
         match operands.first() {
             Some(Operand::Immediate(i)) => Ok(*i),
             Some(Operand::Label(sym)) => {
@@ -705,16 +1901,33 @@ impl CodeGen {
         }
     }
 
-    fn offset_to_func(&self, offset: i32, is_load: bool) -> Result<u16, String> {
-        match offset {
-            0 => Ok(0x0),
-            2 => Ok(0x3),
-            4 => Ok(0x4),
-            6 => Ok(0x5),
-            -2 => Ok(0x6),
-            -4 => Ok(0x7),
-            _ => Err(format!("Unsupported offset {} for short load/store", offset)),
+    /// Registers of a byte load/store, which has no offset form
+    fn get_byte_ops(&self, mnemonic: &str, (r1, r2, offset): (u8, u8, i32)) -> Result<(u8, u8), String> {
+        if offset != 0 {
+            return Err(format!("{} does not take an offset", mnemonic.to_uppercase()));
         }
+        Ok((r1, r2))
+    }
+
+    /// LUI immediate for `LI Rd, value` if the value is known and LUI can
+    /// load it
+    fn li_upper(&self, operands: &[Operand]) -> Option<u16> {
+        let value = match operands {
+            [Operand::Register(_), Operand::Immediate(n)] => *n as u16,
+            [Operand::Register(_), Operand::Label(sym)] => *self.symbols.get(sym)?,
+            _ => return None,
+        };
+        (value & 0xF0FF == 0).then_some(value >> 8)
+    }
+
+    /// Extended `0xF Rd Rs sub` followed by a 16-bit offset
+    fn emit_extended(&mut self, sub: u16, rd: u8, rs: u8, offset: i32) -> Result<(), String> {
+        if !(-32768..=65535).contains(&offset) {
+            return Err(format!("Offset {} out of range", offset));
+        }
+        self.emit_word(0xF000 | ((rd as u16) << 8) | ((rs as u16) << 4) | sub);
+        self.emit_word(offset as u16);
+        Ok(())
     }
 
     fn get_reg_imm_or_label(&self, operands: &[Operand]) -> Result<(u8, Either), String> {
@@ -740,19 +1953,44 @@ impl CodeGen {
                     self.output[addr] = (target & 0xFF) as u8;
                     self.output[addr + 1] = (target >> 8) as u8;
                 }
+                FixupKind::Absolute16Be => {
+                    self.output[addr] = (target >> 8) as u8;
+                    self.output[addr + 1] = (target & 0xFF) as u8;
+                }
+                FixupKind::PcRel16 => {
+                    let distance = target.wrapping_sub(fixup.address);
+                    self.output[addr] = (distance & 0xFF) as u8;
+                    self.output[addr + 1] = (distance >> 8) as u8;
+                }
+                FixupKind::PcRel16Be => {
+                    let distance = target.wrapping_sub(fixup.address);
+                    self.output[addr] = (distance >> 8) as u8;
+                    self.output[addr + 1] = (distance & 0xFF) as u8;
+                }
+                FixupKind::TableRel16(base) => {
+                    let distance = target.wrapping_sub(base);
+                    self.output[addr] = (distance & 0xFF) as u8;
+                    self.output[addr + 1] = (distance >> 8) as u8;
+                }
                 FixupKind::Relative8 => {
                     let pc_after = fixup.address + 2;
                     let offset = (target as i32 - pc_after as i32) / 2;
-                    if offset < -128 || offset > 127 {
-                        return Err(format!("Branch to {} out of range", fixup.symbol));
+                    if !(-128..=127).contains(&offset) {
+                        return Err(format!(
+                            "{}: Branch to {} out of range ({} words); branch around a JX instead",
+                            self.location(fixup.line), fixup.symbol, offset
+                        ));
                     }
                     self.output[addr] = (offset as i8) as u8;
                 }
                 FixupKind::Relative12 => {
                     let pc_after = fixup.address + 2;
                     let offset = (target as i32 - pc_after as i32) / 2;
-                    if offset < -2048 || offset > 2047 {
-                        return Err(format!("Jump to {} out of range", fixup.symbol));
+                    if !(-2048..=2047).contains(&offset) {
+                        return Err(format!(
+                            "{}: Jump to {} out of range ({} words); use JX instead",
+                            self.location(fixup.line), fixup.symbol, offset
+                        ));
                     }
                     let existing = u16::from_le_bytes([self.output[addr], self.output[addr + 1]]);
                     let new_word = (existing & 0xF000) | ((offset as u16) & 0x0FFF);
@@ -763,9 +2001,850 @@ impl CodeGen {
         }
         Ok(())
     }
+
+    /// Store each `lo()`/`hi()` byte, taking the sum modulo 0x10000
+    fn apply_byte_sums(&mut self) -> Result<(), String> {
+        for sum in &self.byte_sums {
+            let mut value = 0u16;
+            for (sign, term) in &sum.terms {
+                let term = match term {
+                    Term::Number(n) => *n as u16,
+                    Term::Symbol(sym) => *self.symbols.get(sym)
+                        .ok_or_else(|| format!("{}: Undefined symbol: {}", self.location(sum.line), sym))?,
+                };
+                value = if *sign < 0 { value.wrapping_sub(term) } else { value.wrapping_add(term) };
+            }
+            let [lo, hi] = value.to_le_bytes();
+            self.output[sum.address as usize] = if sum.high { hi } else { lo };
+        }
+        Ok(())
+    }
+
+    /// Fill the image out to the `set_pad` size; before the checksums so
+    /// one can cover the whole ROM
+    fn apply_pad(&mut self) -> Result<(), String> {
+        let Some((size, fill)) = self.pad else {
+            return Ok(());
+        };
+        if self.output.len() > size {
+            return Err(format!("Output is {} bytes, over the pad size {}", self.output.len(), size));
+        }
+        self.output.resize(size, fill);
+        Ok(())
+    }
+
+    /// The image starts at address 0, so its length covers the origin too
+    fn check_max_size(&self) -> Result<(), String> {
+        let len = self.output.len();
+        match self.max_size {
+            Some(max) if len > max => Err(format!(
+                "Output is {} bytes (up to 0x{:04X}), over --max-size {} by {}",
+                len, len - 1, max, len - max
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Store each `.checksum` word; a range may cover later code, so this
+    /// waits for the finished image
+    fn apply_checksums(&mut self) -> Result<(), String> {
+        for checksum in &self.checksums {
+            let location = self.location(checksum.line);
+            let resolve = |bound: &Either| match bound {
+                Either::Imm(n) => u16::try_from(*n).map_err(|_| format!("{}: .checksum address {} out of range", location, n)),
+                Either::Label(sym) => self.symbols.get(sym).copied()
+                    .ok_or_else(|| format!("{}: Undefined symbol: {}", location, sym)),
+            };
+            let (start, end) = (resolve(&checksum.start)? as usize, resolve(&checksum.end)? as usize);
+            if start > end || end > self.output.len() {
+                return Err(format!("{}: .checksum range 0x{:04X}..0x{:04X} is not within the output", location, start, end));
+            }
+            // Still zero, so a range over the whole image may include it
+            let addr = checksum.address as usize;
+            let sum = self.output[start..end].iter().fold(0u16, |sum, &b| sum.wrapping_add(b as u16));
+            self.output[addr..addr + 2].copy_from_slice(&sum.to_le_bytes());
+        }
+        Ok(())
+    }
+
+    /// Note each JX whose target is close enough for a 2-byte J
+    fn check_long_jumps(&mut self) {
+        let long_jumps = std::mem::take(&mut self.long_jumps);
+        for jump in &long_jumps {
+            let Some(&target) = self.symbols.get(&jump.symbol) else {
+                continue;
+            };
+            let offset = (target as i32 - (jump.address as i32 + 2)) / 2;
+            if (-2048..=2047).contains(&offset) {
+                self.warn("jump-size", jump.line,
+                    format!("JX to {} is within range of J (saves 2 bytes)", jump.symbol));
+            }
+        }
+    }
 }
 
+#[derive(Clone, PartialEq)]
 enum Either {
     Imm(i32),
     Label(String),
 }
+
+/// Encoding for a word load/store offset
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum OffsetForm {
+    Short(u16), // 16-bit form with this func
+    Extended,   // LWX/SWX, with the offset in the second word
+}
+
+/// The encoding a word load/store offset needs: a short func for 0, +-2,
+/// +-4 and 6, else the extended form if the offset fits in 16 bits. Loads
+/// and stores number their short offsets differently, since func 1/2 are
+/// LB/LBU but only 1 is SB.
+fn classify_offset(mnemonic: &str, offset: i32, is_load: bool) -> Result<OffsetForm, String> {
+    let func = match offset {
+        0 => 0x0,
+        2 => 0x2,
+        4 => 0x3,
+        6 => 0x4,
+        -2 => 0x5,
+        -4 => 0x6,
+        _ if (-32768..=65535).contains(&offset) => return Ok(OffsetForm::Extended),
+        _ => return Err(format!("Offset {} out of range for {} (-32768..65535)", offset, mnemonic)),
+    };
+    // Loads skip func 2 (LBU)
+    Ok(OffsetForm::Short(if is_load && func != 0 { func + 1 } else { func }))
+}
+
+/// The `=value` operand of an instruction, if it has one
+fn pool_literal(operands: &[Operand]) -> Result<Option<Either>, String> {
+    for op in operands {
+        if let Operand::Literal(value) = op {
+            return match value.as_ref() {
+                Operand::Immediate(v) if (-32768..=65535).contains(v) => Ok(Some(Either::Imm(*v))),
+                Operand::Immediate(v) => Err(format!("Literal out of range: {}", v)),
+                Operand::Label(sym) => Ok(Some(Either::Label(sym.clone()))),
+                _ => Err("Invalid literal".to_string()),
+            };
+        }
+    }
+    Ok(None)
+}
+
+/// A `.db` value, which may be written signed or unsigned; anything wider
+/// is an error rather than silently losing its high bits
+fn data_byte(value: i64) -> Result<u8, String> {
+    if (-128..=255).contains(&value) {
+        Ok(value as u8)
+    } else {
+        Err(format!("Value {} does not fit in a byte (-128..255)", value))
+    }
+}
+
+/// Operand class named in operand-order errors
+fn operand_kind(operand: &Operand) -> &'static str {
+    match operand {
+        Operand::Register(_) => "register",
+        Operand::Immediate(_) | Operand::Label(_) => "port",
+        Operand::Here(_) => "address",
+        Operand::Literal(_) => "literal",
+        Operand::Indirect(..) | Operand::PostInc(_) | Operand::PreDec(_) => "memory operand",
+    }
+}
+
+/// IN/OUT given a literal or constant port rather than a port register
+fn literal_port(mnemonic: &str, operands: &[Operand]) -> bool {
+    let port = match mnemonic {
+        "IN" => operands.get(1),
+        "OUT" => operands.first(),
+        _ => None,
+    };
+    matches!(port, Some(Operand::Immediate(_) | Operand::Label(_)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    /// Mnemonic, source assembled at 0x0000, and the expected output as
+    /// little-endian words. Each encoding was worked out by hand from
+    /// ENCODING.md; every mnemonic `emit_instruction` accepts needs a row.
+    const GOLDEN: &[(&str, &str, &[u16])] = &[
+        // Opcodes 0x0-0x5
+        ("ADD", "ADD R4, R5, R6", &[0x0456]),
+        ("ADD", "ADD R4, R5", &[0x0445]),
+        ("SUB", "SUB R4, R5, R6", &[0x1456]),
+        ("AND", "AND R4, R5, R6", &[0x2456]),
+        ("OR", "OR R4, R5, R6", &[0x3456]),
+        ("XOR", "XOR R4, R5, R6", &[0x4456]),
+        ("ADDI", "ADDI R4, 5", &[0x5405]),
+        ("ADDI", "ADDI R4, -1", &[0x54FF]),
+        // Loads
+        ("LW", "LW R4, (R5)", &[0x6450]),
+        ("LW", "LW R4, 2(R5)", &[0x6453]),
+        ("LW", "LW R4, 4(R5)", &[0x6454]),
+        ("LW", "LW R4, 6(R5)", &[0x6455]),
+        ("LW", "LW R4, -2(R5)", &[0x6456]),
+        ("LW", "LW R4, -4(R5)", &[0x6457]),
+        ("LW", "LW R4, (R5)+", &[0x6459]),
+        ("LW", "LW R4, -(R5)", &[0x645A]),
+        ("LW", "LW R4, 8(R5)", &[0xF455, 0x0008]),
+        ("LW", "LW R4, =0x1234", &[0xF405, 0x0004, 0x1234]),
+        ("LWX", "LWX R4, 2(R5)", &[0xF455, 0x0002]),
+        ("LB", "LB R4, (R5)", &[0x6451]),
+        ("LBU", "LBU R4, (R5)", &[0x6452]),
+        ("LUI", "LUI R4, 1", &[0x6418]),
+        ("LUI", "LUI R4, 15", &[0x64F8]),
+        ("LI", "LI R4, 0x0300", &[0x6438]),
+        ("LI", "LI R4, 0", &[0x6408]),
+        ("LI", "LI R4, 0x1234", &[0xF407, 0x1234]),
+        ("LI", "LI R4, here\nhere: NOP", &[0xF407, 0x0004, 0xE000]),
+        // Stores
+        ("SW", "SW (R5), R4", &[0x7450]),
+        ("SW", "SW 2(R5), R4", &[0x7452]),
+        ("SW", "SW 4(R5), R4", &[0x7453]),
+        ("SW", "SW 6(R5), R4", &[0x7454]),
+        ("SW", "SW -2(R5), R4", &[0x7455]),
+        ("SW", "SW -4(R5), R4", &[0x7456]),
+        ("SW", "SW (R5)+, R4", &[0x7457]),
+        ("SW", "SW -(R5), R4", &[0x7458]),
+        ("SW", "SW 8(R5), R4", &[0xF456, 0x0008]),
+        ("SWX", "SWX 2(R5), R4", &[0xF456, 0x0002]),
+        ("SB", "SB (R5), R4", &[0x7451]),
+        // Branches: forward and backward label fixups, then `$`
+        ("BEQ", "BEQ next\nnext: NOP", &[0x8000, 0xE000]),
+        ("BNE", "top: NOP\nBNE top", &[0xE000, 0x81FE]),
+        ("BLT", "BLT $", &[0x82FF]),
+        ("BGE", "BGE $", &[0x83FF]),
+        ("BLTU", "BLTU $", &[0x84FF]),
+        ("BGEU", "BGEU $", &[0x85FF]),
+        ("BMI", "BMI $", &[0x86FF]),
+        ("BPL", "BPL $", &[0x87FF]),
+        ("BVS", "BVS $", &[0x88FF]),
+        ("BVC", "BVC $", &[0x89FF]),
+        ("BCS", "BCS $", &[0x8AFF]),
+        ("BCC", "BCC $", &[0x8BFF]),
+        ("BGT", "BGT $", &[0x8CFF]),
+        ("BLE", "BLE $", &[0x8DFF]),
+        ("BHI", "BHI $", &[0x8EFF]),
+        ("BLS", "BLS $+4", &[0x8F01]),
+        // Jumps
+        ("J", "J fwd\nNOP\nfwd: NOP", &[0x9001, 0xE000, 0xE000]),
+        ("J", "top: J top", &[0x9FFF]),
+        ("JR", "JR R5", &[0xC05E]),
+        ("JALR", "JALR R4, R5", &[0xC45E]),
+        ("JALR", "JALR R1, R5", &[0xC15E]),
+        ("JALR", "JALR R0, R5", &[0xC05E]),
+        ("JAL", "JAL sub\nsub: NOP", &[0xF109, 0x0004, 0xE000]),
+        ("JAL", "JAL 0x1234", &[0xF109, 0x1234]),
+        // Shifts
+        ("SLL", "SLL R4, R5", &[0xA450]),
+        ("SRL", "SRL R4, R5", &[0xA451]),
+        ("SRA", "SRA R4, R5", &[0xA452]),
+        ("ROL", "ROL R4, R5", &[0xA453]),
+        ("ROR", "ROR R4, R5", &[0xA454]),
+        ("SWAP", "SWAP R4, R5", &[0xA457]),
+        // Multiply/divide
+        ("MUL", "MUL R4, R5", &[0xB450]),
+        ("MULH", "MULH R4, R5", &[0xB451]),
+        ("MULHU", "MULHU R4, R5", &[0xB452]),
+        ("DIV", "DIV R4, R5", &[0xB453]),
+        ("DIVU", "DIVU R4, R5", &[0xB454]),
+        ("REM", "REM R4, R5", &[0xB455]),
+        ("REMU", "REMU R4, R5", &[0xB456]),
+        ("DAA", "DAA R4", &[0xB407]),
+        // Stack and misc
+        ("PUSH", "PUSH R4", &[0xC040]),
+        ("POP", "POP R4", &[0xC401]),
+        ("CMP", "CMP R4, R5", &[0xC452]),
+        ("TEST", "TEST R4, R5", &[0xC453]),
+        ("MOV", "MOV R4, R5", &[0xC454]),
+        ("MOV", "MOV R4, PC", &[0xC40F]),
+        ("MOV", "MOV PC, R5", &[0xC05E]),
+        ("MOV", "MOV R4, FLAGS", &[0xC40C]),
+        ("MOV", "MOV FLAGS, R5", &[0xC05D]),
+        ("LDI", "LDI", &[0xC005]),
+        ("LDD", "LDD", &[0xC006]),
+        ("LDIR", "LDIR", &[0xC007]),
+        ("LDDR", "LDDR", &[0xC008]),
+        ("CPIR", "CPIR", &[0xC009]),
+        ("FILL", "FILL", &[0xC00A]),
+        ("EXX", "EXX", &[0xC00B]),
+        ("GETF", "GETF R4", &[0xC40C]),
+        ("SETF", "SETF R5", &[0xC05D]),
+        // I/O
+        ("IN", "IN R4, (R5)", &[0xD452]),
+        ("IN", "IN R4, 0x80", &[0xF40B, 0x0080]),
+        ("INI", "INI R4, 0x80", &[0xF40B, 0x0080]),
+        ("INX", "INX R4, 0x81", &[0xF40B, 0x0081]),
+        ("OUT", "OUT (R5), R4", &[0xD453]),
+        ("OUT", "OUT 0x80, R4", &[0xF04C, 0x0080]),
+        ("OUT", "OUT 0x81, R4", &[0xF04C, 0x0081]),
+        ("OUTI", "OUTI 0x80, R4", &[0xF04C, 0x0080]),
+        ("OUTX", "OUTX 0x81, R4", &[0xF04C, 0x0081]),
+        // System
+        ("NOP", "NOP", &[0xE000]),
+        ("HALT", "HALT", &[0xE100]),
+        ("DI", "DI", &[0xE200]),
+        ("EI", "EI", &[0xE300]),
+        ("RETI", "RETI", &[0xE400]),
+        ("SWI", "SWI 3", &[0xE503]),
+        ("SCF", "SCF", &[0xE600]),
+        ("CCF", "CCF", &[0xE700]),
+        ("CSKIP", "CSKIP EQ", &[0xEA00]),
+        ("CSKIP", "CSKIP LS", &[0xEA0F]),
+        ("RDCYC", "RDCYC R4", &[0xEB40]),
+        ("RDCYC", "RDCYC R4, 3", &[0xEB43]),
+        ("TRAP", "TRAP 7", &[0xEC07]),
+        ("TRAP", "TRAP", &[0xEC00]),
+        // Extended
+        ("LIX", "LIX R4, 0x1234", &[0xF407, 0x1234]),
+        ("LIX", "LIX R4, =0x1234", &[0xF405, 0x0004, 0x1234]),
+        ("LA", "LA R4, data\ndata: .dw 0x5678", &[0xF407, 0x0004, 0x5678]),
+        ("JX", "JX fwd\nfwd: NOP", &[0xF008, 0x0004, 0xE000]),
+        ("JX", "JX 0x1234", &[0xF008, 0x1234]),
+        ("CAS", "CAS R4, (R5), R6", &[0xF458, 0x0006]),
+        ("JALX", "JALX 0x1234", &[0xF109, 0x1234]),
+        ("ADDS", "ADDS R4, R5", &[0xF45B, 0x0100]),
+        ("SUBS", "SUBS R4, R5", &[0xF45B, 0x0200]),
+        ("ADDSU", "ADDSU R4, R5", &[0xF45B, 0x0300]),
+        ("SUBSU", "SUBSU R4, R5", &[0xF45B, 0x0400]),
+        ("BSET", "BSET R4, 3", &[0xF40B, 0x0503]),
+        ("BCLR", "BCLR R4, 15", &[0xF40B, 0x060F]),
+        ("BCLR", "BCLR R4, 3", &[0xF40B, 0x0603]),
+        ("BTST", "BTST R4, 0", &[0xF40B, 0x0700]),
+        ("BTST", "BTST R4, 3", &[0xF40B, 0x0703]),
+        // Pseudo-instructions
+        ("NEG", "NEG R4, R5", &[0x1405]),
+        ("NOT", "NOT R4, R5", &[0xF454, 0xFFFF]),
+    ];
+
+    fn assemble(source: &str) -> Result<Vec<u8>, String> {
+        assemble_with(source, |_| {}).map(|(_, image)| image)
+    }
+
+    /// Assemble `source` after `setup` has configured the CodeGen, which is
+    /// kept for its symbols and warnings
+    fn assemble_with(source: &str, setup: impl FnOnce(&mut CodeGen)) -> Result<(CodeGen, Vec<u8>), String> {
+        let tokens = Lexer::new(source).tokenize()?;
+        let program = Parser::new(tokens).parse()?;
+        let mut codegen = CodeGen::new();
+        setup(&mut codegen);
+        let image = codegen.generate(&program)?;
+        Ok((codegen, image))
+    }
+
+    fn words(bytes: &[u8]) -> Vec<u16> {
+        bytes.chunks(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair.get(1).copied().unwrap_or(0)]))
+            .collect()
+    }
+
+    #[test]
+    fn golden_encodings() {
+        let mut failures = Vec::new();
+        for &(_, source, expected) in GOLDEN {
+            match assemble(source) {
+                Ok(bytes) if words(&bytes) == expected => {}
+                Ok(bytes) => failures.push(format!("{:?}: expected {:04X?}, got {:04X?}", source, expected, words(&bytes))),
+                Err(e) => failures.push(format!("{:?}: {}", source, e)),
+            }
+        }
+        assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+    }
+
+    /// `emit_instruction` only dispatches names in MNEMONICS, so a new
+    /// instruction cannot land without a golden row
+    #[test]
+    fn every_mnemonic_has_a_golden_row() {
+        let missing: Vec<&str> = MNEMONICS.iter().copied()
+            .filter(|name| !GOLDEN.iter().any(|(m, _, _)| m == name))
+            .collect();
+        assert!(missing.is_empty(), "no golden row for {:?}", missing);
+    }
+
+    #[test]
+    fn golden_rows_use_their_mnemonic() {
+        for &(mnemonic, source, _) in GOLDEN {
+            let uses = source.lines()
+                .map(|l| l.rsplit(':').next().unwrap().trim())
+                .any(|l| l.split_whitespace().next() == Some(mnemonic));
+            assert!(uses, "golden row {:?} does not use {}", source, mnemonic);
+        }
+    }
+
+    /// Assemble `source`, keeping the code generator for its symbols
+    fn generated(source: &str) -> (CodeGen, Vec<u8>) {
+        assemble_with(source, |_| {}).unwrap()
+    }
+
+    fn symbol(codegen: &CodeGen, name: &str) -> u16 {
+        codegen.symbol_table().iter().find(|(sym, _, _)| *sym == name).unwrap().1
+    }
+
+    #[test]
+    fn sections_get_separate_addresses() {
+        let source = ".bss\nbuf: .space 16\n.data\nmsg: .db 1, 2\n.text\nstart: LIX R4, 0x1234\nHALT";
+        let (codegen, image) = generated(source);
+        // .data follows .text and .bss follows .data, whatever the source order
+        assert_eq!((symbol(&codegen, "start"), symbol(&codegen, "msg"), symbol(&codegen, "buf")), (0, 6, 8));
+        // .bss reserves addresses but emits nothing
+        assert_eq!(image, [0x07, 0xF4, 0x34, 0x12, 0x00, 0xE1, 1, 2]);
+        assert!(assemble(".bss\nNOP").unwrap_err().contains("Instruction NOP in .bss section"));
+        assert!(assemble(".bss\n.db 1").unwrap_err().contains(".db in .bss section (use .space)"));
+    }
+
+    #[test]
+    fn global_symbols_are_flagged_exported() {
+        let (codegen, _) = generated(".global main\n.export done\nmain: NOP\nloop: J loop\ndone: HALT");
+        assert_eq!(codegen.symbol_table(), [("main", 0, true), ("loop", 2, false), ("done", 4, true)]);
+        assert!(assemble(".global mian\nmain: HALT").unwrap_err().contains("Undefined global symbol: mian"));
+        assert!(assemble(".global 5\nHALT").unwrap_err().contains(".global requires symbol names"));
+    }
+
+    #[test]
+    fn two_operand_alu_ops_reuse_rd_as_a_source() {
+        for op in ["ADD", "SUB", "AND", "OR", "XOR"] {
+            assert_eq!(assemble(&format!("{} R4, R5", op)), assemble(&format!("{} R4, R4, R5", op)), "{}", op);
+        }
+        assert!(assemble("ADD R4").unwrap_err().contains("Expected two or three registers"));
+        assert!(assemble("SUB R4, 5").unwrap_err().contains("Expected two or three registers"));
+    }
+
+    #[test]
+    fn align_pads_with_its_fill_byte() {
+        let (codegen, image) = generated(".db 1, 2, 3\n.align 8, 0x90\nx: .db 4");
+        assert_eq!(image, [1, 2, 3, 0x90, 0x90, 0x90, 0x90, 0x90, 4]);
+        // Pass 1 counted the same padding
+        assert_eq!(symbol(&codegen, "x"), 8);
+        assert_eq!(assemble(".db 1\n.align 2\n.db 2").unwrap(), [1, 0, 2]);
+        assert_eq!(assemble(".align 4, 0xFF\n.db 1").unwrap(), [1]);
+        assert!(assemble(".align 4, 0x100").unwrap_err().contains(".align fill byte out of range: 256"));
+        assert!(assemble(".align 0").unwrap_err().contains(".align boundary out of range: 0"));
+    }
+
+    #[test]
+    fn cross_reference_lists_the_definition_then_each_use() {
+        let (codegen, _) = generated(".global start\nstart: NOP\nJ start\n.dw start\n.equ UNUSED, 7");
+        let xref = codegen.cross_reference();
+        let sites = |i: usize| -> Vec<(u16, &str, bool)> {
+            xref[i].1.iter().map(|s| (s.addr, s.location.as_str(), s.definition)).collect()
+        };
+        assert_eq!(xref.iter().map(|(name, _)| *name).collect::<Vec<_>>(), ["UNUSED", "start"]);
+        assert_eq!(sites(0), [(7, "line 5", true)]);
+        // .global names a symbol without using it
+        assert_eq!(sites(1), [(0, "line 2", true), (2, "line 3", false), (4, "line 4", false)]);
+    }
+
+    #[test]
+    fn lui_rejects_wide_immediates() {
+        assert!(assemble("LUI R4, 16").is_err());
+    }
+
+    #[test]
+    fn forward_li_settles_short() {
+        // SIZE is unknown on the first scan, so `after` starts at 6
+        let bytes = assemble("LI R4, SIZE\nJ after\n.equ SIZE, 0x0200\nafter: HALT").unwrap();
+        assert_eq!(words(&bytes), [0x6428, 0x9000, 0xE100]);
+    }
+
+    #[test]
+    fn oscillating_li_is_an_error() {
+        // Long, `target` is 0x0100 and LUI could load it; short, it is
+        // 0x00FE and LUI cannot
+        let err = assemble(".org 0x00FC\nLI R4, target\ntarget: NOP").unwrap_err();
+        assert!(err.contains("did not settle"), "{}", err);
+    }
+
+    #[test]
+    fn if_selects_by_version() {
+        let source = |version| format!(
+            ".equ VERSION, {}\n.if VERSION >= 2 && VERSION != 3\nNOP\n.elif VERSION == 3\nHALT\n.else\nEI\n.endif",
+            version
+        );
+        assert_eq!(words(&assemble(&source(1)).unwrap()), [0xE300]);
+        assert_eq!(words(&assemble(&source(2)).unwrap()), [0xE000]);
+        assert_eq!(words(&assemble(&source(3)).unwrap()), [0xE100]);
+    }
+
+    #[test]
+    fn if_skips_nested_blocks_and_their_labels() {
+        let source = ".equ A, 1\n.if !(A < 2) || 0\n.if UNDEFINED\nskip: NOP\n.endif\n.else\nJ end\nend: HALT\n.endif";
+        assert_eq!(words(&assemble(source).unwrap()), [0x9000, 0xE100]);
+        assert!(assemble(".if 1\nNOP").unwrap_err().contains(".if without .endif"));
+        assert!(assemble(".if 1\n.else\n.elif 1\n.endif").unwrap_err().contains("after .else"));
+    }
+
+    #[test]
+    fn dwbe_stores_words_high_byte_first() {
+        assert_eq!(assemble(".dwbe 0x1234").unwrap(), [0x12, 0x34]);
+        assert_eq!(assemble(".dw 0x1234").unwrap(), [0x34, 0x12]);
+        // Forward references are patched in the same byte order
+        let image = assemble(".dwbe fwd, %pcrel(fwd)\n.org 0x1234\nfwd: HALT").unwrap();
+        assert_eq!(image[..4], [0x12, 0x34, 0x12, 0x32]);
+        assert!(assemble(".dwbe 0x10000").unwrap_err().contains("does not fit in a word"));
+    }
+
+    #[test]
+    fn pcrel_words_hold_the_distance_from_the_word() {
+        // The word at 2 points 4 bytes on to `fwd`; the one at `fwd` points 2 back
+        let image = assemble("NOP\n.dw %pcrel(fwd)\nback: NOP\nfwd: .dw %pcrel(back)").unwrap();
+        assert_eq!(words(&image), [0xE000, 0x0004, 0xE000, (-2i16) as u16]);
+        assert!(assemble(".db %pcrel(x)\nx: HALT").unwrap_err().contains("%pcrel() needs a word directive"));
+        assert!(assemble(".dw %pcrel(nowhere)").unwrap_err().contains("Undefined symbol: nowhere"));
+    }
+
+    #[test]
+    fn data_values_must_fit() {
+        assert_eq!(assemble(".db 0xFF, -128").unwrap(), [0xFF, 0x80]);
+        assert!(assemble(".db 256").unwrap_err().contains("does not fit in a byte"));
+        assert!(assemble(".db -129").is_err());
+        assert!(assemble(".equ BIG, 0x1234\n.db BIG").unwrap_err().contains("BIG (0x1234)"));
+        assert_eq!(assemble(".equ M1, -1\n.db M1").unwrap(), [0xFF]);
+        assert_eq!(assemble(".dw 0xFFFF, -32768").unwrap(), [0xFF, 0xFF, 0x00, 0x80]);
+        assert!(assemble(".dw 0x10000").unwrap_err().contains("does not fit in a word"));
+        assert_eq!(words(&assemble(".dd 0xFFFFFFFF, -2").unwrap()), [0xFFFF, 0xFFFF, 0xFFFE, 0xFFFF]);
+        assert!(assemble(".dd 0x100000000").unwrap_err().contains("does not fit in 32 bits"));
+        assert!(assemble("LI R4, 0x100000000").unwrap_err().contains("out of range"));
+    }
+
+    #[test]
+    fn jump_table_holds_offsets_in_order() {
+        let source = "NOP\ntable: .jmptable one, two, three\none: NOP\ntwo: NOP\nthree: HALT";
+        // The table at 2, then one/two/three at 8, 10 and 12
+        assert_eq!(words(&assemble(source).unwrap())[1..4], [6, 8, 10]);
+        assert!(assemble(".jmptable 4").unwrap_err().contains("must be labels"));
+        assert!(assemble(".jmptable nowhere").unwrap_err().contains("Undefined symbol"));
+    }
+
+    #[test]
+    fn max_size_allows_the_limit_and_rejects_a_byte_more() {
+        // 6 bytes from address 0x10 end at 0x15: 22 bytes
+        let source = ".org 0x10\nLIX R4, 0x1234\nHALT";
+        let limit = |c: &mut CodeGen| c.set_max_size(22);
+        assert_eq!(assemble_with(source, limit).unwrap().1.len(), 22);
+        let err = assemble_with(&format!("{}\n.db 0", source), limit).err().unwrap();
+        assert_eq!(err, "Output is 23 bytes (up to 0x0016), over --max-size 22 by 1");
+    }
+
+    #[test]
+    fn checksum_is_the_byte_sum_of_its_range() {
+        let image = assemble(".checksum data, end\ndata: .db 0x80, 0x90, 0xFF\nend:").unwrap();
+        assert_eq!(image[..2], (0x80u16 + 0x90 + 0xFF).to_le_bytes());
+        // A range over the checksum word itself sums it as zero
+        let image = assemble(".db 7\n.db 9\n.checksum 0, 6\n.dw 0x0102").unwrap();
+        assert_eq!(image[2..4], [7 + 9 + 2 + 1, 0]);
+        // Forward labels resolve once the image is complete
+        let image = assemble(".checksum 4, fwd\n.dw 0\n.db 0xFF, 0xFF\nfwd:").unwrap();
+        assert_eq!(image[..2], [0xFE, 0x01]);
+        let err = assemble(".checksum 0, 0x100\n.db 1").unwrap_err();
+        assert!(err.contains(".checksum range 0x0000..0x0100 is not within the output"), "{}", err);
+        assert!(assemble(".checksum 4, 2\n.dw 0, 0").is_err());
+    }
+
+    #[test]
+    fn pad_fills_the_image_to_its_size() {
+        let (_, image) = assemble_with("LIX R4, 0x1234\nHALT", |c| c.set_pad(4096, 0xA5)).unwrap();
+        assert_eq!(image.len(), 4096);
+        assert_eq!(image[..6], assemble("LIX R4, 0x1234\nHALT").unwrap()[..]);
+        assert!(image[6..].iter().all(|&b| b == 0xA5));
+        // A checksum may cover the padding
+        let (_, image) = assemble_with(".checksum 2, 8\n.db 1, 2", |c| c.set_pad(8, 0xFF)).unwrap();
+        assert_eq!(image[..2], [0xFF, 0x03]); // 1 + 2 + 4 * 0xFF
+        let err = assemble_with(".org 0x20\nHALT", |c| c.set_pad(16, 0)).err().unwrap();
+        assert!(err.contains("over the pad size"), "{}", err);
+    }
+
+    fn warnings(source: &str) -> Vec<String> {
+        generated(source).0.warnings().to_vec()
+    }
+
+    #[test]
+    fn nearby_jx_could_be_a_short_j() {
+        let near = warnings("NOP\nJX x\nx: HALT");
+        assert_eq!(near, ["line 2: JX to x is within range of J (saves 2 bytes) [jump-size]"]);
+        assert!(warnings("JX x\n.space 5000\nx: HALT").is_empty());
+        // The other way round a short J that can't reach is an error
+        let err = assemble("J x\n.space 5000\nx: HALT").unwrap_err();
+        assert!(err.contains("Jump to x out of range") && err.contains("use JX instead"), "{}", err);
+        let (mut codegen, _) = assemble_with("JX x\nx: HALT", |c| c.set_warning("jump-size", false).unwrap()).unwrap();
+        assert!(codegen.warnings().is_empty());
+        assert_eq!(codegen.set_warning("jump-sise", false).unwrap_err(), "Unknown warning: jump-sise");
+    }
+
+    #[test]
+    fn unsigned_branch_against_zero_warns() {
+        let enable = |c: &mut CodeGen| c.set_warning("signedness", true).unwrap();
+        let signedness = |source| assemble_with(source, enable).unwrap().0.warnings().to_vec();
+        let flagged = signedness("CMP R4, R0\nBLTU x\nx: HALT");
+        assert_eq!(flagged.len(), 1, "{:?}", flagged);
+        assert!(flagged[0].contains("BLTU after CMP against zero is never taken; BLT compares signed"));
+        assert!(flagged[0].ends_with("[signedness]"));
+        assert!(signedness("SUB R5, R0, R4\nMOV R6, R7\nBLS x\nx: HALT")[0].contains("always taken"));
+        // Signed branches, unsigned ones that can go either way, and
+        // compares with a nonzero register are fine
+        assert!(signedness("CMP R4, R0\nBLT x\nBHI x\nx: HALT").is_empty());
+        assert!(signedness("CMP R4, R5\nBLTU x\nx: HALT").is_empty());
+        // Off by default
+        assert!(warnings("CMP R4, R0\nBLTU x\nx: HALT").is_empty());
+    }
+
+    #[test]
+    fn code_after_halt_without_a_label_warns() {
+        let enable = |c: &mut CodeGen| c.set_warning("unreachable", true).unwrap();
+        let unreachable = |source| assemble_with(source, enable).unwrap().0.warnings().to_vec();
+        assert_eq!(unreachable("HALT\nNOP"), ["line 2: NOP is unreachable after HALT (no label) [unreachable]"]);
+        // A label, or a CSKIP that may step over the jump, keeps the code live
+        assert!(unreachable("HALT\nx: NOP").is_empty());
+        assert!(unreachable("CSKIP EQ\nJ x\nNOP\nx: HALT").is_empty());
+        // Off by default
+        assert!(warnings("HALT\nNOP").is_empty());
+    }
+
+    #[test]
+    fn writing_sp_with_alu_ops_warns() {
+        let clobber = warnings("ADD R2, R4, R5\nHALT");
+        assert_eq!(clobber, ["line 1: ADD overwrites SP (R2); use .def sp_is_general if intended [sp-write]"]);
+        // Stack instructions move SP on purpose
+        assert!(warnings("PUSH R4\nPOP R4\nHALT").is_empty());
+        assert!(warnings(".def sp_is_general\nADD R2, R4, R5\nHALT").is_empty());
+        assert!(assemble(".def sp_general\nHALT").unwrap_err().contains("Unknown .def flag: sp_general"));
+    }
+
+    #[test]
+    fn branch_on_stale_flags_warns() {
+        let stale = warnings("MOV R4, R5\nBEQ x\nx: HALT");
+        assert_eq!(stale.len(), 1, "{:?}", stale);
+        assert!(stale[0].contains("BEQ tests flags") && stale[0].ends_with("[stale-flags]"));
+        assert!(warnings("CMP R4, R5\nBEQ x\nx: HALT").is_empty());
+        // Flags survive flag-keeping instructions and other branches
+        assert!(warnings("CMP R4, R5\nMOV R6, R7\nBNE x\nBLT x\nx: HALT").is_empty());
+        // A label or call may bring flags from elsewhere
+        assert!(warnings("x: BEQ x\nJAL f\nMOV R4, R5\nBCS x\nf: HALT").is_empty());
+        // A software interrupt hands back the caller's flags, not its own
+        let stale = warnings("MOV R4, R5\nSWI 3\nBEQ x\nx: HALT");
+        assert_eq!(stale.len(), 1, "{:?}", stale);
+        assert!(stale[0].ends_with("[stale-flags]"));
+    }
+
+    #[test]
+    fn lo_hi_give_the_bytes_of_a_label_difference() {
+        let source = "size: .db lo(end-start), hi(end - start)\nstart: .space 0x123\nend:";
+        let code = assemble(source).unwrap();
+        assert_eq!(code[..2], 0x123u16.to_le_bytes());
+        assert_eq!(assemble(".db lo(x+1), hi(-2)\nx:").unwrap(), [3, 0xFF]);
+        assert_eq!(assemble("x: .db lo(x-1)").unwrap(), [0xFF]);
+        assert!(assemble(".db lo(nowhere)").unwrap_err().contains("Undefined symbol: nowhere"));
+    }
+
+    #[test]
+    fn cas_needs_an_address_register() {
+        assert!(assemble("CAS R4, (R0), R6").unwrap_err().contains("R0"));
+        assert!(assemble("CAS R4, 2(R5), R6").is_err());
+    }
+
+    #[test]
+    fn vectors_default_unlisted_slots() {
+        let table = assemble(".vectors 0, 4, trap, 2, isr\n.org 0x100\ntrap: J trap\nisr: RETI").unwrap();
+        let by_hand = assemble("J trap\nJ trap\nJ isr\nJ trap\n.org 0x100\ntrap: J trap\nisr: RETI").unwrap();
+        assert_eq!(table, by_hand);
+        // Slot 2 reaches isr, the others trap
+        assert_eq!(words(&table)[..4], [0x907F, 0x907E, 0x907E, 0x907C]);
+        assert!(assemble(".vectors 0, 2, trap, 2, isr\ntrap: NOP\nisr: NOP").unwrap_err().contains("slot 2 out of range"));
+        assert!(assemble(".vectors 1, 2, trap\ntrap: NOP").unwrap_err().contains("even address"));
+    }
+
+    #[test]
+    fn strings_are_ascii_one_byte_per_character() {
+        assert_eq!(assemble(".ascii \"Hi!\\n\"").unwrap(), b"Hi!\n");
+        assert_eq!(assemble(".db 'A', 'z'").unwrap(), b"Az");
+        // A multi-byte character is an error naming its UTF-8 escapes
+        let err = assemble(".asciz \"caf\u{e9}\"").unwrap_err();
+        assert!(err.contains("Non-ASCII character '\u{e9}'") && err.contains("\\xC3\\xA9"), "{}", err);
+        assert!(assemble(".db '\u{20ac}'").is_err());
+        assert_eq!(assemble(".asciz \"caf\\xC3\\xA9\"").unwrap(), b"caf\xC3\xA9\0");
+    }
+
+    #[test]
+    fn bar_separates_statements_on_a_line() {
+        let code = assemble("MOV R4, R5 | ADD R4, R4, R6\nloop: J loop").unwrap();
+        assert_eq!(code, assemble("MOV R4, R5\nADD R4, R4, R6\nloop: J loop").unwrap());
+        assert_eq!(words(&code).len(), 3);
+        // Both statements keep the physical line's number
+        assert_eq!(words(&assemble("NOP\nLIX R4, __LINE__ | LIX R5, __LINE__").unwrap()), [0xE000, 0xF407, 2, 0xF507, 2]);
+        // `||` is still a condition operator
+        assert_eq!(words(&assemble(".if 0 || 1 | NOP | .endif").unwrap()), [0xE000]);
+    }
+
+    #[test]
+    fn line_builtin_is_the_line_using_it() {
+        let code = assemble("NOP\n\n.dw __LINE__\nLIX R4, __LINE__").unwrap();
+        assert_eq!(words(&code), [0xE000, 3, 0xF407, 4]);
+    }
+
+    #[test]
+    fn file_builtin_is_a_string() {
+        let tokens = Lexer::new("NOP\n.ascii __FILE__\nend: .dw end").tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        let mut codegen = CodeGen::new();
+        codegen.set_origins(vec![("a.s".to_string(), 1), ("a.s".to_string(), 2), ("a.s".to_string(), 3)]);
+        assert_eq!(codegen.generate(&program).unwrap(), b"\x00\xE0a.s\x05\x00");
+    }
+
+    #[test]
+    fn db_strings_are_sized_in_pass1() {
+        assert_eq!(assemble(".db \"abc\", 1\nx: .dw x").unwrap(), b"abc\x01\x04\x00");
+    }
+
+    #[test]
+    fn load_store_offsets_pick_their_form() {
+        assert_eq!(words(&assemble("LW R4, -4(R5)\nSW 6(R5), R4").unwrap()), [0x6457, 0x7454]);
+        assert_eq!(words(&assemble("LW R4, 100(R5)\nSW -6(R5), R4").unwrap()), [0xF455, 100, 0xF456, 0xFFFA]);
+        for source in ["LW R4, 65536(R5)", "SW -32769(R5), R4", "LWX R4, 70000(R5)", "SWX 70000(R5), R4"] {
+            let err = assemble(source).unwrap_err();
+            assert!(err.contains("out of range for") && err.contains("(-32768..65535)"), "{}: {}", source, err);
+        }
+    }
+
+    #[test]
+    fn store_offsets_use_the_store_funcs_or_swx() {
+        // Stores number their short offsets differently from loads
+        for (offset, func) in [(0, 0x0), (2, 0x2), (4, 0x3), (6, 0x4), (-2, 0x5), (-4, 0x6)] {
+            let source = format!("SW {}(R5), R4\n.dw next\nnext: HALT", offset);
+            assert_eq!(words(&assemble(&source).unwrap()), [0x7450 | func, 4, 0xE100], "{}", source);
+        }
+        // Any other 16-bit offset becomes a 4-byte SWX, and pass 1 sizes it so
+        for offset in [8, -6, 3, 1000, -32768, 65535] {
+            let source = format!("SW {}(R5), R4\n.dw next\nnext: HALT", offset);
+            assert_eq!(words(&assemble(&source).unwrap()), [0xF456, offset as u16, 6, 0xE100], "{}", source);
+        }
+        let err = assemble("SW 65536(R5), R4").unwrap_err();
+        assert!(err.contains("Offset 65536 out of range for SW"), "{}", err);
+    }
+
+    #[test]
+    fn long_forms_are_sized_in_pass1() {
+        // `after` is resolved from pass-1 addresses, so JAL and NOT must
+        // count 4 bytes there as well
+        let bytes = assemble("JAL after\nNOT R4, R5\nafter: J after").unwrap();
+        assert_eq!(words(&bytes), [0xF109, 0x0008, 0xF454, 0xFFFF, 0x9FFF]);
+    }
+
+    #[test]
+    fn swi_127_is_the_trap_vector() {
+        assert_eq!(words(&assemble("SWI 126").unwrap()), [0xE57E]);
+        assert!(assemble("SWI 0x7F").unwrap_err().contains("TRAP vector"));
+    }
+
+    #[test]
+    fn comments_need_no_space_before_them() {
+        assert_eq!(words(&assemble("ADD R4,R5,R6;comment").unwrap()), [0x0456]);
+        assert_eq!(words(&assemble(".dw 1,2,3;comment").unwrap()), [1, 2, 3]);
+    }
+
+    #[test]
+    fn la_loads_addresses_with_the_low_byte_high_bit_set() {
+        // No sign extension of 0x80 to borrow from the high byte
+        let bytes = assemble("LA R4, sym\n.org 0x0180\nsym: .db 1").unwrap();
+        assert_eq!(words(&bytes[..4]), [0xF407, 0x0180]);
+    }
+
+    #[test]
+    fn dollar_offsets_count_from_the_instruction() {
+        // BNE $-2 lands on the NOP before it; J $+4 skips one NOP
+        let bytes = assemble("NOP\nBNE $-2\nJ $+4\nNOP\nNOP").unwrap();
+        assert_eq!(words(&bytes), [0xE000, 0x81FE, 0x9001, 0xE000, 0xE000]);
+    }
+
+    #[test]
+    fn equ_constant_branches_by_displacement() {
+        // -4 bytes from the end of the BNE is the NOP before it
+        let bytes = assemble(".equ back -4\nNOP\nNOP\nBNE back").unwrap();
+        assert_eq!(words(&bytes), [0xE000, 0xE000, 0x81FE]);
+    }
+
+    #[test]
+    fn org_takes_symbols_pads_gaps_and_rejects_overlaps() {
+        assert_eq!(words(&assemble(".equ BASE 0x08\n.org BASE\nNOP").unwrap()), [0, 0, 0, 0, 0xE000]);
+        assert_eq!(words(&assemble("NOP\nhere:\n.org here\nHALT").unwrap()), [0xE000, 0xE100]);
+        assert_eq!(words(&assemble(".org 2\nNOP\n.org 8\nHALT").unwrap()), [0, 0xE000, 0, 0, 0xE100]);
+        let err = assemble("NOP\nNOP\n.org 2\nHALT").unwrap_err();
+        assert!(err.contains("Overwrites code or data already emitted at 0x0002"), "{}", err);
+    }
+
+    #[test]
+    fn equal_pool_constants_share_an_entry() {
+        let bytes = assemble("LIX R4, =0x1234\nLIX R5, =0x1234\nLIX R6, =0x5678\nHALT").unwrap();
+        assert_eq!(words(&bytes), [0xF405, 0x000E, 0xF505, 0x000E, 0xF605, 0x0010, 0xE100, 0x1234, 0x5678]);
+    }
+
+    #[test]
+    fn in_out_with_a_literal_port_use_the_extended_forms() {
+        let literal = assemble("IN R4, 0x80\nOUT 0x81, R4\nend: J end").unwrap();
+        assert_eq!(literal, assemble("INX R4, 0x80\nOUTX 0x81, R4\nend: J end").unwrap());
+        assert_eq!(words(&literal), [0xF40B, 0x0080, 0xF04C, 0x0081, 0x9FFF]);
+    }
+
+    #[test]
+    fn named_ports_assemble_like_literals() {
+        let named = assemble(".equ UART 0x81\n.equ SYS 3\nINI R4, UART\nOUTI UART, R4\nINX R4, UART\nOUTX UART, R4\nSWI SYS");
+        let literal = assemble("INI R4, 0x81\nOUTI 0x81, R4\nINX R4, 0x81\nOUTX 0x81, R4\nSWI 3");
+        assert_eq!(named.unwrap(), literal.unwrap());
+    }
+
+    #[test]
+    fn io_immediates_check_operand_order() {
+        assert_eq!(words(&assemble("INI R4, 0x81\nOUTI 0x81, R4").unwrap()), [0xF40B, 0x0081, 0xF04C, 0x0081]);
+        let err = assemble("OUTI R4, 0x81").unwrap_err();
+        assert!(err.contains("OUTI expects port, register; got register, port"), "{}", err);
+        let err = assemble("INI 0x81, R4").unwrap_err();
+        assert!(err.contains("INI expects register, port; got port, register"), "{}", err);
+    }
+
+    #[test]
+    fn lone_identifier_hints_at_a_missing_colon() {
+        let err = assemble("loop\nNOP").unwrap_err();
+        assert!(err.contains("did you mean a label?"), "{}", err);
+    }
+
+    #[test]
+    fn single_operand_instructions_reject_extras() {
+        for source in ["PUSH R4, R5", "POP R4, R5", "JR R4, R5", "DAA R4, R5", "GETF R4, R5", "SETF R4, R5"] {
+            let err = assemble(source).unwrap_err();
+            assert!(err.contains("Expected a single register, got 2 operands"), "{}: {}", source, err);
+        }
+        assert!(assemble("SWI 1, 2").unwrap_err().contains("Expected a single immediate, got 2 operands"));
+    }
+
+    #[test]
+    fn register_names_cannot_be_labels() {
+        let err = assemble("SP:\nNOP").unwrap_err();
+        assert!(err.contains("Label named like register R2/SP"), "{}", err);
+    }
+
+    #[test]
+    fn register_numbers_stop_at_r15() {
+        let err = assemble("MOV R16, R0").unwrap_err();
+        assert!(err.contains("Register R16 does not exist (valid R0-R15)"), "{}", err);
+    }
+
+    #[test]
+    fn instruction_at_an_odd_address_warns() {
+        let odd = warnings(".db 1\nNOP");
+        assert_eq!(odd.len(), 1);
+        assert!(odd[0].contains("NOP at odd address 0x0001; add .align 2") && odd[0].ends_with("[odd-address]"), "{}", odd[0]);
+        assert!(warnings(".db 1\n.align 2\nNOP").is_empty());
+    }
+
+    #[test]
+    fn times_repeats_a_directive() {
+        // `end` is placed from pass-1 sizes, so .times must count 4 bytes there
+        let bytes = assemble(".times 4 .db 0xFF\nend: J end\nJ end").unwrap();
+        assert_eq!(bytes[..4], [0xFF; 4]);
+        assert_eq!(words(&bytes[4..]), [0x9FFF, 0x9FFE]);
+        assert_eq!(assemble(".equ SIZE, 3\n.times SIZE .db 0").unwrap(), [0; 3]);
+        assert!(assemble(".times 100000000000 NOP").unwrap_err().contains(".times count too large"));
+        assert!(assemble(".times LATER NOP\n.equ LATER, 2").unwrap_err().contains("not a constant defined earlier"));
+    }
+}
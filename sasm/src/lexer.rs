@@ -9,11 +9,11 @@ pub enum Token {
     // Registers
     Register(u8),
     // Numbers
-    Number(i32),
+    Number(i64),
     // Identifiers (labels, symbols)
     Ident(String),
-    // String literals
-    StringLit(String),
+    // String literals (raw bytes, so escapes can produce any byte value)
+    StringLit(Vec<u8>),
     // Punctuation
     Comma,
     Colon,
@@ -21,10 +21,18 @@ pub enum Token {
     RParen,
     Plus,
     Minus,
+    Equals,
+    Dollar, // `$`, the current instruction's address
     // Directives
     Directive(String),
+    // Operators such as %pcrel
+    Operator(String),
+    // Comparison and logical operators in .if conditions: == != < <= > >= && || !
+    Logic(String),
     // End of line
     Newline,
+    // `|` between statements on one line
+    Separator,
     // End of file
     Eof,
 }
@@ -102,6 +110,41 @@ impl<'a> Lexer<'a> {
                 self.advance();
                 Ok(Token::Plus)
             }
+            Some('=') => {
+                self.advance();
+                if self.peek() == Some('=') {
+                    self.advance();
+                    return Ok(Token::Logic("==".to_string()));
+                }
+                Ok(Token::Equals)
+            }
+            Some(c @ ('<' | '>' | '!')) => {
+                self.advance();
+                if self.peek() == Some('=') {
+                    self.advance();
+                    return Ok(Token::Logic(format!("{}=", c)));
+                }
+                Ok(Token::Logic(c.to_string()))
+            }
+            Some('|') => {
+                self.advance();
+                if self.peek() == Some('|') {
+                    self.advance();
+                    return Ok(Token::Logic("||".to_string()));
+                }
+                Ok(Token::Separator)
+            }
+            Some('&') => {
+                self.advance();
+                if self.advance() != Some('&') {
+                    return Err(format!("Expected '&&' at line {}", self.line));
+                }
+                Ok(Token::Logic("&&".to_string()))
+            }
+            Some('$') => {
+                self.advance();
+                Ok(Token::Dollar)
+            }
             Some('-') => {
                 self.advance();
                 // Check if it's a negative number
@@ -118,6 +161,11 @@ impl<'a> Lexer<'a> {
                 let name = self.read_identifier();
                 Ok(Token::Directive(name.to_lowercase()))
             }
+            Some('%') => {
+                self.advance();
+                let name = self.read_identifier();
+                Ok(Token::Operator(name.to_lowercase()))
+            }
             Some('"') => {
                 self.advance();
                 let s = self.read_string()?;
@@ -126,10 +174,11 @@ impl<'a> Lexer<'a> {
             Some('\'') => {
                 self.advance();
                 let c = self.advance().ok_or("Unexpected end of character literal")?;
+                let c = self.ascii_byte(c)?;
                 if self.advance() != Some('\'') {
                     return Err(format!("Expected closing quote at line {}", self.line));
                 }
-                Ok(Token::Number(c as i32))
+                Ok(Token::Number(c as i64))
             }
             Some(c) if c.is_ascii_digit() => {
                 let num = self.read_number()?;
@@ -140,6 +189,8 @@ impl<'a> Lexer<'a> {
                 // Check if it's a register
                 if let Some(reg) = parse_register(&ident) {
                     Ok(Token::Register(reg))
+                } else if is_register_name(&ident) {
+                    Err(format!("Register {} does not exist (valid R0-R15) at line {}", ident, self.line))
                 } else {
                     Ok(Token::Ident(ident))
                 }
@@ -194,7 +245,7 @@ impl<'a> Lexer<'a> {
         ident
     }
 
-    fn read_number(&mut self) -> Result<i32, String> {
+    fn read_number(&mut self) -> Result<i64, String> {
         let mut num_str = String::new();
         let mut base = 10;
 
@@ -225,33 +276,97 @@ impl<'a> Lexer<'a> {
             }
         }
 
-        i32::from_str_radix(&num_str, base)
+        i64::from_str_radix(&num_str, base)
             .map_err(|e| format!("Invalid number at line {}: {}", self.line, e))
     }
 
-    fn read_string(&mut self) -> Result<String, String> {
-        let mut s = String::new();
+    fn read_string(&mut self) -> Result<Vec<u8>, String> {
+        let mut s = Vec::new();
         loop {
             match self.advance() {
                 None => return Err(format!("Unterminated string at line {}", self.line)),
                 Some('"') => break,
                 Some('\\') => {
                     match self.advance() {
-                        Some('n') => s.push('\n'),
-                        Some('r') => s.push('\r'),
-                        Some('t') => s.push('\t'),
-                        Some('0') => s.push('\0'),
-                        Some('\\') => s.push('\\'),
-                        Some('"') => s.push('"'),
-                        Some(c) => s.push(c),
+                        Some('n') => s.push(b'\n'),
+                        Some('r') => s.push(b'\r'),
+                        Some('t') => s.push(b'\t'),
+                        Some('\\') => s.push(b'\\'),
+                        Some('"') => s.push(b'"'),
+                        Some('x') => {
+                            // \xNN - one or two hex digits
+                            let (value, digits) = self.read_escape_digits(16, 2, 0);
+                            if digits == 0 {
+                                return Err(format!("Invalid \\x escape at line {}", self.line));
+                            }
+                            s.push(value as u8);
+                        }
+                        Some(c @ '0'..='7') => {
+                            // \NNN - up to three octal digits (\0 alone is NUL)
+                            let (value, _) = self.read_escape_digits(8, 2, c.to_digit(8).unwrap());
+                            if value > 0xFF {
+                                return Err(format!("Octal escape out of range at line {}", self.line));
+                            }
+                            s.push(value as u8);
+                        }
+                        Some(c) => s.push(self.ascii_byte(c)?),
                         None => return Err(format!("Unterminated escape at line {}", self.line)),
                     }
                 }
-                Some(c) => s.push(c),
+                Some(c) => s.push(self.ascii_byte(c)?),
             }
         }
         Ok(s)
     }
+
+    /// A character of a string or character literal as its one byte.
+    /// Only ASCII is allowed, so each character is exactly one byte;
+    /// anything else has to be spelled out with escapes.
+    fn ascii_byte(&self, c: char) -> Result<u8, String> {
+        if !c.is_ascii() {
+            let mut buf = [0; 4];
+            let escapes: String = c.encode_utf8(&mut buf).bytes().map(|b| format!("\\x{:02X}", b)).collect();
+            return Err(format!(
+                "Non-ASCII character '{}' at line {} (write its bytes as escapes, e.g. \"{}\" for UTF-8)",
+                c, self.line, escapes
+            ));
+        }
+        Ok(c as u8)
+    }
+
+    /// Accumulate up to `max_digits` digits onto `value`, returning the
+    /// result and how many digits were consumed
+    fn read_escape_digits(&mut self, radix: u32, max_digits: usize, mut value: u32) -> (u32, usize) {
+        let mut digits = 0;
+        while digits < max_digits {
+            match self.peek().and_then(|d| d.to_digit(radix)) {
+                Some(d) => {
+                    self.advance();
+                    value = value * radix + d;
+                    digits += 1;
+                }
+                None => break,
+            }
+        }
+        (value, digits)
+    }
+}
+
+/// Canonical and ABI names of a register, e.g. `R2/SP`
+pub fn register_names(r: u8) -> String {
+    const ALIASES: [&str; 16] = [
+        "ZERO", "RA", "SP", "GP", "A0", "A1", "A2", "A3",
+        "T0", "T1", "T2", "T3", "S0", "S1", "S2", "S3",
+    ];
+    format!("R{}/{}", r, ALIASES[r as usize & 0xF])
+}
+
+/// `R<n>` with any number, so out-of-range registers are not taken for labels
+fn is_register_name(name: &str) -> bool {
+    match name.strip_prefix(['R', 'r']) {
+        Some(digits) => !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()),
+        None => false,
+    }
 }
 
 fn parse_register(name: &str) -> Option<u8> {
@@ -276,3 +391,25 @@ fn parse_register(name: &str) -> Option<u8> {
         _ => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(source: &str) -> Result<Vec<u8>, String> {
+        match Lexer::new(source).tokenize()?.into_iter().next() {
+            Some(Token::StringLit(bytes)) => Ok(bytes),
+            other => panic!("expected a string, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn hex_and_octal_escapes_give_any_byte() {
+        assert_eq!(string(r#""\x1b[2J""#).unwrap(), [0x1B, b'[', b'2', b'J']);
+        // One hex digit is enough; a third is a plain character
+        assert_eq!(string(r#""\xA\x414""#).unwrap(), [0x0A, b'A', b'4']);
+        assert_eq!(string(r#""\0\33\177""#).unwrap(), [0, 0o33, 0o177]);
+        assert!(string(r#""\xZZ""#).unwrap_err().contains("Invalid \\x escape"));
+        assert!(string(r#""\777""#).unwrap_err().contains("Octal escape out of range"));
+    }
+}
@@ -7,6 +7,7 @@ use std::fs;
 mod lexer;
 mod parser;
 mod codegen;
+mod preprocess;
 
 use lexer::Lexer;
 use parser::Parser;
@@ -15,34 +16,91 @@ use codegen::CodeGen;
 fn main() {
     let args: Vec<String> = env::args().collect();
 
-    if args.len() < 2 {
-        eprintln!("Usage: sasm <input.s> [-o output.bin]");
-        eprintln!("       sasm --help");
-        std::process::exit(1);
-    }
+    let mut input_file = None;
+    let mut output_file = None;
+    let mut map_file = None;
+    let mut deps_file = None;
+    let mut list_file = None;
+    let mut xref_file = None;
+    let mut max_size = None;
+    let mut pad_to = None;
+    let mut fill = 0xFF;
+    let mut warning_flags = Vec::new();
+    let mut verbose = false;
 
-    if args[1] == "--help" || args[1] == "-h" {
-        print_help();
-        return;
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].as_str() {
+            "-h" | "--help" => {
+                print_help();
+                return;
+            }
+            "-v" | "--verbose" => verbose = true,
+            "-o" | "-m" | "-M" | "-l" | "-x" | "-W" | "--max-size" | "--pad-to" | "--fill" => {
+                let Some(value) = args.get(i + 1) else {
+                    eprintln!("Missing value for {}", args[i]);
+                    std::process::exit(1);
+                };
+                match args[i].as_str() {
+                    "-o" => output_file = Some(value.clone()),
+                    "-m" => map_file = Some(value.clone()),
+                    "-M" => deps_file = Some(value.clone()),
+                    "-l" => list_file = Some(value.clone()),
+                    "-x" => xref_file = Some(value.clone()),
+                    "--max-size" => match parse_size(value) {
+                        Some(n) => max_size = Some(n),
+                        None => {
+                            eprintln!("Invalid --max-size '{}'", value);
+                            std::process::exit(1);
+                        }
+                    },
+                    "--pad-to" => match parse_size(value) {
+                        Some(n) if n <= 0x10000 => pad_to = Some(n),
+                        _ => {
+                            eprintln!("Invalid --pad-to '{}'", value);
+                            std::process::exit(1);
+                        }
+                    },
+                    "--fill" => match parse_size(value).and_then(|n| u8::try_from(n).ok()) {
+                        Some(n) => fill = n,
+                        None => {
+                            eprintln!("Invalid --fill '{}'", value);
+                            std::process::exit(1);
+                        }
+                    },
+                    _ => warning_flags.push(value.clone()),
+                }
+                i += 1;
+            }
+            arg if arg.starts_with('-') => {
+                eprintln!("Unknown option: {}", arg);
+                std::process::exit(1);
+            }
+            arg => input_file = Some(arg.to_string()),
+        }
+        i += 1;
     }
 
-    let input_file = &args[1];
-    let output_file = if args.len() >= 4 && args[2] == "-o" {
-        args[3].clone()
-    } else {
-        input_file.replace(".s", ".bin").replace(".asm", ".bin")
+    let Some(input_file) = input_file else {
+        eprintln!("Usage: sasm <input.s> [-o output.bin] [-l output.lst] [-m output.map] [-x output.xref] [-M output.d] [-W [no-]<warning>]");
+        eprintln!("       sasm --help");
+        std::process::exit(1);
     };
+    let output_file = output_file.unwrap_or_else(|| {
+        input_file.replace(".s", ".bin").replace(".asm", ".bin")
+    });
 
-    let source = match fs::read_to_string(input_file) {
+    // Expand .include/.incbin
+    let source = match preprocess::load(&input_file) {
         Ok(s) => s,
         Err(e) => {
-            eprintln!("Error reading {}: {}", input_file, e);
+            eprintln!("{}", e);
             std::process::exit(1);
         }
     };
 
     // Lexical analysis
-    let mut lexer = Lexer::new(&source);
+    let mut lexer = Lexer::new(&source.text);
     let tokens = match lexer.tokenize() {
         Ok(t) => t,
         Err(e) => {
@@ -63,6 +121,23 @@ fn main() {
 
     // Code generation
     let mut codegen = CodeGen::new();
+    codegen.set_origins(source.origins);
+    if let Some(size) = pad_to {
+        codegen.set_pad(size, fill);
+    }
+    if let Some(max) = max_size {
+        codegen.set_max_size(max);
+    }
+    for flag in &warning_flags {
+        let result = match flag.strip_prefix("no-") {
+            Some(name) => codegen.set_warning(name, false),
+            None => codegen.set_warning(flag, true),
+        };
+        if let Err(e) = result {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
     let binary = match codegen.generate(&program) {
         Ok(b) => b,
         Err(e) => {
@@ -70,6 +145,44 @@ fn main() {
             std::process::exit(1);
         }
     };
+    for warning in codegen.warnings() {
+        eprintln!("Warning: {}", warning);
+    }
+    if verbose {
+        for (name, start, end) in codegen.section_ranges() {
+            println!("  .{:<5} 0x{:04X}-0x{:04X} ({} bytes)", name, start, end, end - start);
+        }
+    }
+
+    if let Some(map_file) = &map_file {
+        if let Err(e) = fs::write(map_file, symbol_map(&codegen)) {
+            eprintln!("Error writing {}: {}", map_file, e);
+            std::process::exit(1);
+        }
+    }
+
+    if let Some(xref_file) = &xref_file {
+        if let Err(e) = fs::write(xref_file, cross_reference(&codegen)) {
+            eprintln!("Error writing {}: {}", xref_file, e);
+            std::process::exit(1);
+        }
+    }
+
+    // Built from the same run and the final (fixed-up) bytes as the binary,
+    // so its addresses are exactly where the bytes sit in the output
+    if let Some(list_file) = &list_file {
+        if let Err(e) = fs::write(list_file, listing(&codegen, &source.text, &binary)) {
+            eprintln!("Error writing {}: {}", list_file, e);
+            std::process::exit(1);
+        }
+    }
+
+    if let Some(deps_file) = &deps_file {
+        if let Err(e) = fs::write(deps_file, dependency_rule(&output_file, &source.files)) {
+            eprintln!("Error writing {}: {}", deps_file, e);
+            std::process::exit(1);
+        }
+    }
 
     // Write output
     match fs::write(&output_file, &binary) {
@@ -83,26 +196,224 @@ fn main() {
     }
 }
 
+/// Decimal or 0x-prefixed hexadecimal byte count
+fn parse_size(s: &str) -> Option<usize> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+/// One line per symbol: address, G (exported) or L (local), name
+fn symbol_map(codegen: &CodeGen) -> String {
+    let mut map = String::new();
+    for (name, addr, global) in codegen.symbol_table() {
+        map.push_str(&format!("{:04X} {} {}\n", addr, if global { 'G' } else { 'L' }, name));
+    }
+    map
+}
+
+/// Each symbol on its own line, then one indented line per site: address,
+/// `def` or `ref`, and source location. Uses are at the address of the
+/// statement that names the symbol.
+fn cross_reference(codegen: &CodeGen) -> String {
+    let mut xref = String::new();
+    for (name, sites) in codegen.cross_reference() {
+        xref.push_str(name);
+        xref.push('\n');
+        for site in sites {
+            let kind = if site.definition { "def" } else { "ref" };
+            xref.push_str(&format!("  {:04X} {} {}\n", site.addr, kind, site.location));
+        }
+    }
+    xref
+}
+
+/// Listing: address, up to four output bytes per row, and the source line.
+/// The binary is a flat image from address 0, so an address is also the
+/// byte's offset in the output file.
+fn listing(codegen: &CodeGen, text: &str, binary: &[u8]) -> String {
+    const BYTES_PER_ROW: usize = 4;
+    let lines: Vec<&str> = text.lines().collect();
+    let mut out = String::new();
+    let mut next_line = 1; // First source line not yet listed
+
+    let row = |out: &mut String, addr: usize, bytes: &[u8], text: &str| {
+        let hex: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        out.push_str(format!("{:04X}  {:<11}  {}", addr, hex.join(" "), text).trim_end());
+        out.push('\n');
+    };
+
+    let entries = codegen.listing();
+    let mut i = 0;
+    while i < entries.len() {
+        // A label and an instruction on one line list as one row
+        let first = entries[i];
+        let mut len = first.len as usize;
+        i += 1;
+        while first.line.is_some() && i < entries.len() && entries[i].line == first.line {
+            len += entries[i].len as usize;
+            i += 1;
+        }
+
+        let source = match first.line {
+            Some(line) => {
+                // Comments and blank lines before the statement
+                while next_line < line {
+                    out.push_str(format!("{:19}{}", "", lines[next_line - 1]).trim_end());
+                    out.push('\n');
+                    next_line += 1;
+                }
+                next_line = line + 1;
+                lines.get(line - 1).copied().unwrap_or("")
+            }
+            None => "        ; literal pool",
+        };
+
+        let start = first.addr as usize;
+        let bytes = &binary[start.min(binary.len())..(start + len).min(binary.len())];
+        let mut chunks = bytes.chunks(BYTES_PER_ROW);
+        row(&mut out, start, chunks.next().unwrap_or(&[]), source);
+        for (n, chunk) in chunks.enumerate() {
+            row(&mut out, start + (n + 1) * BYTES_PER_ROW, chunk, "");
+        }
+    }
+    for line in lines.iter().skip(next_line - 1) {
+        out.push_str(format!("{:19}{}", "", line).trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+/// Makefile rule making the output depend on every file read
+fn dependency_rule(output_file: &str, files: &[String]) -> String {
+    let escape = |path: &str| path.replace(' ', "\\ ");
+    let deps: Vec<String> = files.iter().map(|f| escape(f)).collect();
+    format!("{}: {}\n", escape(output_file), deps.join(" "))
+}
+
 fn print_help() {
     println!("Sampo Assembler (sasm) v0.1.0");
     println!();
-    println!("Usage: sasm <input.s> [-o output.bin]");
+    println!("Usage: sasm <input.s> [-o output.bin] [-l output.lst] [-m output.map] [-x output.xref] [-M output.d] [-W [no-]<warning>]");
     println!();
     println!("Options:");
     println!("  -o <file>    Output file (default: input with .bin extension)");
+    println!("  -l <file>    Write a listing: address, bytes and source of each line");
+    println!("  -m <file>    Write a symbol map (G = .global, L = local)");
+    println!("  -x <file>    Write a cross-reference: where each symbol is defined and used");
+    println!("  --max-size <n>");
+    println!("               Fail if the image (from address 0) exceeds n bytes, e.g. for a ROM");
+    println!("  --pad-to <n> Pad the image (from address 0) to exactly n bytes");
+    println!("  --fill <b>   Byte to pad with (default 0xFF)");
+    println!("  -M <file>    Write a Makefile dependency rule for all input files");
+    println!("  -W <name>    Enable a warning; -W no-<name> disables it");
+    println!("  -v           Print the address range of each section");
     println!("  -h, --help   Show this help message");
     println!();
+    println!("Warnings:");
+    println!("  jump-size    JX whose target is in range of a short J (default on)");
+    println!("  unreachable  Unlabeled instruction after J/JX/JR/HALT (default off)");
+    println!("  sp-write     SP (R2) as destination of a non-stack instruction (default on)");
+    println!("  odd-address  Instruction at an odd address, e.g. after .db (default on)");
+    println!("  stale-flags  Bcc after only flag-keeping instructions (MOV, loads, stores, ...)");
+    println!("               since the last label, so it tests older flags (default on)");
+    println!("  signedness   BLTU/BGEU after CMP/SUB x, R0 (or BHI/BLS after 0, x), which");
+    println!("               always or never branch; probably meant signed (default off)");
+    println!();
     println!("Registers:");
     println!("  R0/ZERO  R1/RA   R2/SP   R3/GP");
     println!("  R4/A0    R5/A1   R6/A2   R7/A3");
     println!("  R8/T0    R9/T1   R10/T2  R11/T3");
     println!("  R12/S0   R13/S1  R14/S2  R15/S3");
     println!();
+    println!("Statements:");
+    println!("  a | b        Two statements on one line, e.g. MOV R4, R5 | ADD R4, R4, R6");
+    println!();
+    println!("Built-in symbols:");
+    println!("  __LINE__     Source line of the statement using it (directives, instructions)");
+    println!("  __FILE__     Its file's name, as a string for .db/.ascii/.asciz");
+    println!();
+    println!("Branch/jump targets (Bcc, J):");
+    println!("  label        Address to reach");
+    println!("  $, $+n, $-n  This instruction's address, plus or minus n bytes");
+    println!("  n            Byte displacement from the next instruction");
+    println!();
     println!("Directives:");
-    println!("  .org <addr>     Set origin address");
-    println!("  .equ <sym> <v>  Define constant");
-    println!("  .db <bytes>     Define bytes");
-    println!("  .dw <words>     Define words");
+    println!("  .org <addr>     Set origin (number, or constant/label defined earlier)");
+    println!("  .include \"f\"    Assemble another source file in place");
+    println!("  .incbin \"f\"     Insert the bytes of a binary file");
+    println!("  .text [addr]    Switch to code section");
+    println!("  .data [addr]    Switch to data section (default: after .text)");
+    println!("  .bss [addr]     Switch to uninitialized section (default: after .data)");
+    println!("  .space <n>      Reserve n bytes (zero-filled outside .bss)");
+    println!("  .align <n>[,f]  Pad to a multiple of n bytes with fill byte f (default 0)");
+    println!("  .equ <sym> <v>  Define constant (as a Bcc/J target: a byte displacement)");
+    println!("  .enum [s[, n]]  Number the names on following lines s, s+n, ... (default 0, 1)");
+    println!("  .endenum        End an .enum block");
+    println!("  .times <n> <s>  Repeat the statement s n times (e.g. .times 8 .db 0); n is");
+    println!("                  0..65536, a number or an .equ defined above");
+    println!("  .if <cond>      Assemble what follows only if cond holds, up to .elif/.else/.endif");
+    println!("  .elif <cond>    Else-if branch of an .if block");
+    println!("  .else, .endif   Last branch and end of an .if block");
+    println!("                  (cond: numbers and symbols defined earlier, compared unsigned with");
+    println!("                  == != < <= > >= and combined with ! && || and parentheses)");
+    println!("  .global <sym>   Export symbol (alias .export)");
+    println!("  .def <flag>     Set an assembler flag (sp_is_general: no SP write warnings)");
+    println!("  .db <bytes>     Define bytes (-128..255 each; wider values are an error)");
+    println!("                  (lo(e)/hi(e): low/high byte of e, a sum such as end-start+1)");
+    println!("  .dw <words>     Define words (-32768..65535 each)");
+    println!("  .dwbe <words>   Define big-endian words");
+    println!("                  (%pcrel(sym) in either: sym minus the word's address)");
+    println!("  .dd <values>    Define 32-bit values (two words, low first; -2147483648..");
+    println!("                  0xFFFFFFFF each, wider is an error; a label's high word is 0)");
+    println!("  .jmptable l, .. Word per label: its address minus the table's, so with the");
+    println!("                  table's address in R5 and 2*index in R6: ADD R6, R5, R6;");
+    println!("                  LW R6, (R6); ADD R6, R5, R6; JR R6");
+    println!("  .vectors b, n, d[, slot, l]...");
+    println!("                  n vector slots from address b, each a J to d unless a");
+    println!("                  slot, label pair names another; slot k is at b + 2k, where SWI k");
+    println!("                  lands when b is 0");
+    println!("  .checksum s, e  Word holding the 16-bit sum of the bytes from s up to e");
+    println!("                  (the word itself counts as 0)");
+    println!("  .pool           Place pending LW/LIX Rd, =value literals here (else at section end)");
     println!("  .ascii \"str\"    Define ASCII string");
     println!("  .asciz \"str\"    Define null-terminated string");
+    println!("                  (strings and 'c' literals are ASCII, one byte per character;");
+    println!("                  other bytes via \\n \\r \\t \\xNN or octal \\NNN escapes)");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn listing_addresses_are_offsets_in_the_binary() {
+        let source = "; demo\n.org 0x100\nstart: ADDI R4, 1\nLIX R5, 0x1234\n.bss\nbuf: .space 4\n.text\nHALT\n";
+        let program = Parser::new(Lexer::new(source).tokenize().unwrap()).parse().unwrap();
+        let mut codegen = CodeGen::new();
+        let binary = codegen.generate(&program).unwrap();
+        let list = listing(&codegen, source, &binary);
+
+        // Every row's bytes are the binary's bytes at that address
+        let rows: Vec<(usize, Vec<u8>)> = list.lines()
+            .filter_map(|row| {
+                let addr = usize::from_str_radix(row.get(..4)?, 16).ok()?;
+                let bytes: Vec<u8> = row.get(6..17)?.split_whitespace()
+                    .map(|b| u8::from_str_radix(b, 16).unwrap())
+                    .collect();
+                (!bytes.is_empty()).then_some((addr, bytes))
+            })
+            .collect();
+        assert_eq!(rows.len(), 3, "{}", list);
+        for (addr, bytes) in &rows {
+            assert_eq!(&binary[*addr..addr + bytes.len()], bytes.as_slice(), "{}", list);
+        }
+        // The first code row is where .org put the code, after the padding
+        assert_eq!(rows[0].0, 0x0100);
+        assert!(binary[..0x0100].iter().all(|&b| b == 0));
+        // Directives and .bss take an address but no bytes
+        assert!(list.contains("0108               buf: .space 4\n"), "{}", list);
+        assert!(list.starts_with("                   ; demo\n"), "{}", list);
+    }
 }
@@ -1,6 +1,8 @@
 //! Parser for Sampo assembly language
 
-use crate::lexer::Token;
+use std::collections::HashMap;
+
+use crate::lexer::{register_names, Token};
 
 #[derive(Debug, Clone)]
 #[allow(dead_code)]
@@ -9,6 +11,10 @@ pub enum Operand {
     Immediate(i32),
     Label(String),
     Indirect(u8, i32),  // Register + offset: (Rs + imm)
+    Literal(Box<Operand>), // =value: an Immediate or Label placed in the literal pool
+    Here(i32),          // $ + offset: relative to the instruction's own address
+    PostInc(u8),        // (Rs)+: word access, then Rs += 2
+    PreDec(u8),         // -(Rs): Rs -= 2, then word access
 }
 
 #[derive(Debug, Clone)]
@@ -26,27 +32,61 @@ pub enum Statement {
 
 #[derive(Debug, Clone)]
 pub enum DirectiveArg {
-    Number(i32),
-    String(String),
+    Number(i64),
+    String(Vec<u8>),
     Ident(String),
+    PcRel(String), // %pcrel(sym): sym minus the address being emitted
+    ByteOf(bool, Vec<(i32, Term)>), // lo(...)/hi(...) (true for hi) of a sum of signed terms
+    Condition(Condition), // The whole argument of .if/.elif
+}
+
+/// One term of a `lo()`/`hi()` sum such as `end - start`
+#[derive(Debug, Clone)]
+pub enum Term {
+    Number(i32),
+    Symbol(String),
+}
+
+/// `.if` condition over numbers and symbols defined earlier
+#[derive(Debug, Clone)]
+pub enum Condition {
+    Number(i32),
+    Symbol(String),
+    Not(Box<Condition>),
+    Compare(String, Box<Condition>, Box<Condition>), // == != < <= > >=
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+/// A number outside a data directive, where 32 bits is plenty; only `.dd`
+/// needs the full unsigned 32-bit range the lexer reads
+fn narrow(n: i64) -> Result<i32, String> {
+    i32::try_from(n).map_err(|_| format!("Number {} out of range", n))
 }
 
 pub struct Program {
     pub statements: Vec<Statement>,
+    pub lines: Vec<usize>, // Source line of each statement
 }
 
 pub struct Parser {
     tokens: Vec<Token>,
     pos: usize,
+    line: usize,
+    constants: HashMap<String, i64>, // Numeric .equ values so far, for .times counts
 }
 
+/// Most repeats `.times` allows; more could not fit in the address space
+const MAX_TIMES: usize = 0x10000;
+
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
-        Parser { tokens, pos: 0 }
+        Parser { tokens, pos: 0, line: 1, constants: HashMap::new() }
     }
 
     pub fn parse(&mut self) -> Result<Program, String> {
         let mut statements = Vec::new();
+        let mut lines = Vec::new();
 
         while !self.is_at_end() {
             self.skip_newlines();
@@ -54,27 +94,74 @@ impl Parser {
                 break;
             }
 
-            if let Some(stmt) = self.parse_statement()? {
-                statements.push(stmt);
+            let line = self.line;
+            if matches!(self.peek(), Token::Directive(name) if name == "times") {
+                let (count, stmt) = self.parse_times()?;
+                for _ in 0..count {
+                    statements.push(stmt.clone());
+                    lines.push(line);
+                }
+                continue;
+            }
+            match self.parse_statement()? {
+                Some(Statement::Directive { name, args }) if name == "enum" => {
+                    for (stmt, line) in self.parse_enum(&args)? {
+                        self.note_constant(&stmt);
+                        statements.push(stmt);
+                        lines.push(line);
+                    }
+                }
+                Some(Statement::Directive { name, .. }) if name == "endenum" => {
+                    return Err(".endenum without .enum".to_string());
+                }
+                Some(stmt) => {
+                    self.note_constant(&stmt);
+                    statements.push(stmt);
+                    lines.push(line);
+                }
+                None => {}
             }
         }
 
-        Ok(Program { statements })
+        Ok(Program { statements, lines })
+    }
+
+    /// Remember `.equ NAME, number` so a later `.times NAME` can use it
+    fn note_constant(&mut self, stmt: &Statement) {
+        let Statement::Directive { name, args } = stmt else { return };
+        if let ("equ", [DirectiveArg::Ident(sym), DirectiveArg::Number(n), ..]) = (name.as_str(), args.as_slice()) {
+            self.constants.insert(sym.clone(), *n);
+        }
     }
 
     fn parse_statement(&mut self) -> Result<Option<Statement>, String> {
         match self.peek() {
             Token::Eof => Ok(None),
-            Token::Newline => {
+            Token::Newline | Token::Separator => {
                 self.advance();
                 Ok(None)
             }
+            Token::Directive(name) if name == "if" || name == "elif" => {
+                let name = name.clone();
+                self.advance();
+                let condition = self.parse_or()?;
+                if !matches!(self.peek(), Token::Newline | Token::Separator | Token::Eof) {
+                    return Err(format!("Unexpected {:?} in .{} condition", self.peek(), name));
+                }
+                Ok(Some(Statement::Directive { name, args: vec![DirectiveArg::Condition(condition)] }))
+            }
             Token::Directive(name) => {
                 let name = name.clone();
                 self.advance();
                 let args = self.parse_directive_args()?;
                 Ok(Some(Statement::Directive { name, args }))
             }
+            Token::Register(r) if matches!(self.tokens.get(self.pos + 1), Some(Token::Colon)) => {
+                Err(format!(
+                    "Label named like register {}; register names cannot be used as labels",
+                    register_names(*r)
+                ))
+            }
             Token::Ident(name) => {
                 let name = name.clone();
                 self.advance();
@@ -96,12 +183,113 @@ impl Parser {
         }
     }
 
+    /// `.times <n> <statement>`: the rest of the line, to be repeated n times;
+    /// n is a number or a numeric `.equ` defined above
+    fn parse_times(&mut self) -> Result<(usize, Statement), String> {
+        self.advance();
+        let count = match self.advance().clone() {
+            Token::Number(n) => n,
+            Token::Ident(sym) => *self.constants.get(&sym)
+                .ok_or_else(|| format!(".times count {} is not a constant defined earlier", sym))?,
+            _ => return Err(".times needs a count".to_string()),
+        };
+        let count = usize::try_from(count).map_err(|_| ".times needs a non-negative count".to_string())?;
+        if count > MAX_TIMES {
+            return Err(format!(".times count too large: {} (at most {})", count, MAX_TIMES));
+        }
+        match self.parse_statement()? {
+            Some(Statement::Label(_)) => Err(".times cannot repeat a label".to_string()),
+            Some(Statement::Directive { name, .. }) if matches!(name.as_str(), "times" | "enum" | "endenum" | "if" | "elif" | "else" | "endif") => {
+                Err(format!(".times cannot repeat .{}", name))
+            }
+            Some(stmt) => Ok((count, stmt)),
+            None => Err(".times needs a statement to repeat".to_string()),
+        }
+    }
+
+    /// Names up to `.endenum`, one or more per line, as `.equ` statements
+    /// numbered from `.enum [start[, step]]` (default 0, 1)
+    fn parse_enum(&mut self, args: &[DirectiveArg]) -> Result<Vec<(Statement, usize)>, String> {
+        let number = |arg: Option<&DirectiveArg>, default| match arg {
+            None => Ok(default),
+            Some(DirectiveArg::Number(n)) => Ok(*n),
+            Some(_) => Err(".enum start and step must be numbers".to_string()),
+        };
+        let mut value = number(args.first(), 0)?;
+        let step = number(args.get(1), 1)?;
+
+        let mut equs = Vec::new();
+        loop {
+            // advance() repeats the last token at the end, so check first
+            if self.is_at_end() {
+                return Err(".enum without .endenum".to_string());
+            }
+            let line = self.line;
+            match self.advance().clone() {
+                Token::Directive(name) if name == "endenum" => return Ok(equs),
+                Token::Ident(name) => {
+                    let args = vec![DirectiveArg::Ident(name), DirectiveArg::Number(value)];
+                    equs.push((Statement::Directive { name: "equ".to_string(), args }, line));
+                    value += step;
+                }
+                Token::Newline | Token::Separator | Token::Comma => {}
+                token => return Err(format!("Expected a name in .enum, got {:?}", token)),
+            }
+        }
+    }
+
+    /// `a || b`, the loosest-binding condition operator
+    fn parse_or(&mut self) -> Result<Condition, String> {
+        let mut left = self.parse_and()?;
+        while matches!(self.peek(), Token::Logic(op) if op == "||") {
+            self.advance();
+            left = Condition::Or(Box::new(left), Box::new(self.parse_and()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Condition, String> {
+        let mut left = self.parse_comparison()?;
+        while matches!(self.peek(), Token::Logic(op) if op == "&&") {
+            self.advance();
+            left = Condition::And(Box::new(left), Box::new(self.parse_comparison()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_comparison(&mut self) -> Result<Condition, String> {
+        let mut left = self.parse_unary()?;
+        while let Token::Logic(op) = self.peek() {
+            if !matches!(op.as_str(), "==" | "!=" | "<" | "<=" | ">" | ">=") {
+                break;
+            }
+            let op = op.clone();
+            self.advance();
+            left = Condition::Compare(op, Box::new(left), Box::new(self.parse_unary()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<Condition, String> {
+        match self.advance().clone() {
+            Token::Logic(op) if op == "!" => Ok(Condition::Not(Box::new(self.parse_unary()?))),
+            Token::LParen => {
+                let inner = self.parse_or()?;
+                self.expect(&Token::RParen)?;
+                Ok(inner)
+            }
+            Token::Number(n) => Ok(Condition::Number(narrow(n)?)),
+            Token::Ident(name) => Ok(Condition::Symbol(name)),
+            token => Err(format!("Expected a number or symbol in condition, got {:?}", token)),
+        }
+    }
+
     fn parse_directive_args(&mut self) -> Result<Vec<DirectiveArg>, String> {
         let mut args = Vec::new();
 
         loop {
             match self.peek() {
-                Token::Newline | Token::Eof => break,
+                Token::Newline | Token::Separator | Token::Eof => break,
                 Token::Number(n) => {
                     let n = *n;
                     self.advance();
@@ -115,7 +303,36 @@ impl Parser {
                 Token::Ident(s) => {
                     let s = s.clone();
                     self.advance();
-                    args.push(DirectiveArg::Ident(s));
+                    let half = s.to_lowercase();
+                    if matches!(half.as_str(), "lo" | "hi") && self.check(&Token::LParen) {
+                        self.advance();
+                        let terms = self.parse_byte_sum()?;
+                        args.push(DirectiveArg::ByteOf(half == "hi", terms));
+                    } else {
+                        args.push(DirectiveArg::Ident(s));
+                    }
+                }
+                Token::Operator(op) => {
+                    let op = op.clone();
+                    self.advance();
+                    if op != "pcrel" {
+                        return Err(format!("Unknown operator: %{}", op));
+                    }
+                    self.expect(&Token::LParen)?;
+                    let sym = match self.advance() {
+                        Token::Ident(s) => s.clone(),
+                        _ => return Err("Expected symbol in %pcrel()".to_string()),
+                    };
+                    self.expect(&Token::RParen)?;
+                    args.push(DirectiveArg::PcRel(sym));
+                }
+                Token::Register(r) => {
+                    // No directive takes a register, so this is a symbol
+                    // (such as an .equ name) spelled like one
+                    return Err(format!(
+                        "Symbol named like register {}; register names cannot be used as symbols",
+                        register_names(*r)
+                    ));
                 }
                 Token::Comma => {
                     self.advance();
@@ -127,12 +344,46 @@ impl Parser {
         Ok(args)
     }
 
+    /// The sum inside `lo(`/`hi(`, up to and including the `)`
+    fn parse_byte_sum(&mut self) -> Result<Vec<(i32, Term)>, String> {
+        let mut terms = Vec::new();
+        let mut sign = 1;
+        loop {
+            match self.advance().clone() {
+                Token::Ident(name) => terms.push((sign, Term::Symbol(name))),
+                Token::Number(n) => terms.push((sign, Term::Number(narrow(n)?))),
+                Token::Minus if sign == 1 => {
+                    sign = -1;
+                    continue;
+                }
+                token => return Err(format!("Expected a symbol or number in lo()/hi(), got {:?}", token)),
+            }
+            sign = 1;
+            match self.peek() {
+                Token::RParen => {
+                    self.advance();
+                    return Ok(terms);
+                }
+                Token::Plus => {
+                    self.advance();
+                }
+                Token::Minus => {
+                    self.advance();
+                    sign = -1;
+                }
+                // `end-2` lexes as `end` and `-2`
+                Token::Number(n) if *n < 0 => {}
+                token => return Err(format!("Expected +, - or ) in lo()/hi(), got {:?}", token)),
+            }
+        }
+    }
+
     fn parse_operands(&mut self) -> Result<Vec<Operand>, String> {
         let mut operands = Vec::new();
 
         loop {
             match self.peek() {
-                Token::Newline | Token::Eof => break,
+                Token::Newline | Token::Separator | Token::Eof => break,
                 Token::Comma => {
                     self.advance();
                     continue;
@@ -143,7 +394,7 @@ impl Parser {
                     operands.push(Operand::Register(r));
                 }
                 Token::Number(n) => {
-                    let n = *n;
+                    let n = narrow(*n)?;
                     self.advance();
 
                     // Check for indirect addressing: imm(Rs)
@@ -176,7 +427,7 @@ impl Parser {
                         let offset = if self.check(&Token::Plus) {
                             self.advance();
                             if let Token::Number(n) = self.peek() {
-                                let n = *n;
+                                let n = narrow(*n)?;
                                 self.advance();
                                 n
                             } else {
@@ -185,7 +436,7 @@ impl Parser {
                         } else if self.check(&Token::Minus) {
                             self.advance();
                             if let Token::Number(n) = self.peek() {
-                                let n = *n;
+                                let n = narrow(*n)?;
                                 self.advance();
                                 -n
                             } else {
@@ -196,7 +447,15 @@ impl Parser {
                         };
 
                         self.expect(&Token::RParen)?;
-                        operands.push(Operand::Indirect(r, offset));
+                        if self.check(&Token::Plus) {
+                            self.advance();
+                            if offset != 0 {
+                                return Err("(Rs)+ takes no offset".to_string());
+                            }
+                            operands.push(Operand::PostInc(r));
+                        } else {
+                            operands.push(Operand::Indirect(r, offset));
+                        }
                     } else {
                         return Err("Expected register in indirect addressing".to_string());
                     }
@@ -204,13 +463,51 @@ impl Parser {
                 Token::Minus => {
                     self.advance();
                     if let Token::Number(n) = self.peek() {
-                        let n = *n;
+                        let n = narrow(*n)?;
                         self.advance();
                         operands.push(Operand::Immediate(-n));
+                    } else if self.check(&Token::LParen) {
+                        // Pre-decrement: -(Rs)
+                        self.advance();
+                        let Token::Register(r) = *self.peek() else {
+                            return Err("Expected register in -(Rs)".to_string());
+                        };
+                        self.advance();
+                        self.expect(&Token::RParen)?;
+                        operands.push(Operand::PreDec(r));
                     } else {
                         return Err("Expected number after minus".to_string());
                     }
                 }
+                Token::Dollar => {
+                    self.advance();
+                    // `$-4` lexes as $ followed by the number -4
+                    let offset = match self.peek().clone() {
+                        Token::Number(n) if n < 0 => {
+                            self.advance();
+                            narrow(n)?
+                        }
+                        Token::Plus | Token::Minus => {
+                            let sign = if self.check(&Token::Minus) { -1 } else { 1 };
+                            self.advance();
+                            match self.advance().clone() {
+                                Token::Number(n) => sign * narrow(n)?,
+                                _ => return Err("Expected number after '$+' or '$-'".to_string()),
+                            }
+                        }
+                        _ => 0,
+                    };
+                    operands.push(Operand::Here(offset));
+                }
+                Token::Equals => {
+                    self.advance();
+                    let value = match self.advance().clone() {
+                        Token::Number(n) => Operand::Immediate(narrow(n)?),
+                        Token::Ident(name) => Operand::Label(name),
+                        _ => return Err("Expected a number or symbol after '='".to_string()),
+                    };
+                    operands.push(Operand::Literal(Box::new(value)));
+                }
                 _ => break,
             }
         }
@@ -224,6 +521,9 @@ impl Parser {
 
     fn advance(&mut self) -> &Token {
         if !self.is_at_end() {
+            if matches!(self.peek(), Token::Newline) {
+                self.line += 1;
+            }
             self.pos += 1;
         }
         self.tokens.get(self.pos - 1).unwrap_or(&Token::Eof)
@@ -247,8 +547,46 @@ impl Parser {
     }
 
     fn skip_newlines(&mut self) {
-        while matches!(self.peek(), Token::Newline) {
+        while matches!(self.peek(), Token::Newline | Token::Separator) {
             self.advance();
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codegen::CodeGen;
+    use crate::lexer::Lexer;
+
+    fn parse(source: &str) -> Result<Program, String> {
+        Parser::new(Lexer::new(source).tokenize().unwrap()).parse()
+    }
+
+    #[test]
+    fn enum_numbers_its_names_like_equ() {
+        let program = parse(".enum 1\nIDLE, RUN\nSTOP\n.endenum").unwrap();
+        let equs: Vec<(String, i64)> = program.statements.iter()
+            .map(|stmt| match stmt {
+                Statement::Directive { name, args } if name == "equ" => match &args[..] {
+                    [DirectiveArg::Ident(sym), DirectiveArg::Number(n)] => (sym.clone(), *n),
+                    _ => panic!("bad .equ args {:?}", args),
+                },
+                _ => panic!("not an .equ: {:?}", stmt),
+            })
+            .collect();
+        assert_eq!(equs, [("IDLE".to_string(), 1), ("RUN".to_string(), 2), ("STOP".to_string(), 3)]);
+        // Each name keeps its own line for diagnostics
+        assert_eq!(program.lines, [2, 2, 3]);
+
+        let program = parse(".enum 1\nIDLE, RUN, STOP\n.endenum\n.dw IDLE, RUN, STOP").unwrap();
+        assert_eq!(CodeGen::new().generate(&program).unwrap(), [1, 0, 2, 0, 3, 0]);
+        let program = parse(".enum 0x10, 4\nA, B\n.endenum\n.db A, B").unwrap();
+        assert_eq!(CodeGen::new().generate(&program).unwrap(), [0x10, 0x14]);
+
+        assert_eq!(parse(".enum\nA\n").err().unwrap(), ".enum without .endenum");
+        assert_eq!(parse("A: .endenum").err().unwrap(), ".endenum without .enum");
+        assert!(parse(".enum\nA, 5\n.endenum").err().unwrap().starts_with("Expected a name in .enum"));
+        assert_eq!(parse(".enum A\n.endenum").err().unwrap(), ".enum start and step must be numbers");
+    }
+}
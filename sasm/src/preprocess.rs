@@ -0,0 +1,151 @@
+//! Source preprocessor: expands `.include` and `.incbin`
+//!
+//! `.include "file"` splices in another source file; `.incbin "file"`
+//! becomes `.db` lines holding the file's bytes. Paths are relative to the
+//! including file. Every expanded line remembers where it came from, so
+//! diagnostics can name the original file and line.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+pub struct Source {
+    pub text: String,
+    pub origins: Vec<(String, usize)>, // File and line of each expanded line
+    pub files: Vec<String>,            // Every file read, in order
+}
+
+/// Describe expanded line `line` (1-based) as `file:line`
+pub fn location(origins: &[(String, usize)], line: usize) -> String {
+    match origins.get(line.wrapping_sub(1)) {
+        Some((file, orig)) => format!("{}:{}", file, orig),
+        None => format!("line {}", line),
+    }
+}
+
+pub fn load(path: &str) -> Result<Source, String> {
+    let mut source = Source {
+        text: String::new(),
+        origins: Vec::new(),
+        files: Vec::new(),
+    };
+    expand(Path::new(path), &mut source, &mut Vec::new())?;
+    Ok(source)
+}
+
+fn expand(path: &Path, source: &mut Source, stack: &mut Vec<PathBuf>) -> Result<(), String> {
+    let name = path.display().to_string();
+    if stack.len() >= MAX_INCLUDE_DEPTH || stack.iter().any(|p| p == path) {
+        return Err(format!("Recursive .include of {}", name));
+    }
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Error reading {}: {}", name, e))?;
+    record_file(source, &name);
+    stack.push(path.to_path_buf());
+
+    for (idx, line) in text.lines().enumerate() {
+        let line_no = idx + 1;
+        let here = |e: String| format!("{}:{}: {}", name, line_no, e);
+        match directive(line) {
+            Some(("include", arg)) => {
+                let file = relative_to(path, &quoted(arg).map_err(here)?);
+                expand(&file, source, stack)?;
+            }
+            Some(("incbin", arg)) => {
+                let file = relative_to(path, &quoted(arg).map_err(here)?);
+                let bytes = fs::read(&file)
+                    .map_err(|e| here(format!("Error reading {}: {}", file.display(), e)))?;
+                record_file(source, &file.display().to_string());
+                for chunk in bytes.chunks(16) {
+                    let values: Vec<String> = chunk.iter().map(|b| b.to_string()).collect();
+                    push_line(source, &format!(".db {}", values.join(", ")), &name, line_no);
+                }
+            }
+            _ => push_line(source, line, &name, line_no),
+        }
+    }
+
+    stack.pop();
+    Ok(())
+}
+
+/// Split a `.include`/`.incbin` line into its directive and argument text
+fn directive(line: &str) -> Option<(&'static str, &str)> {
+    let trimmed = line.trim_start();
+    let rest = trimmed.strip_prefix('.')?;
+    for name in ["include", "incbin"] {
+        if rest.len() > name.len() && rest[..name.len()].eq_ignore_ascii_case(name) {
+            let arg = &rest[name.len()..];
+            if arg.starts_with([' ', '\t', '"']) {
+                return Some((name, arg));
+            }
+        }
+    }
+    None
+}
+
+/// The text between the first pair of double quotes
+fn quoted(arg: &str) -> Result<String, String> {
+    let arg = arg.trim_start();
+    arg.strip_prefix('"')
+        .and_then(|rest| rest.split_once('"'))
+        .map(|(path, _)| path.to_string())
+        .ok_or_else(|| "Expected a quoted file name".to_string())
+}
+
+fn relative_to(including: &Path, file: &str) -> PathBuf {
+    match including.parent() {
+        Some(dir) => dir.join(file),
+        None => PathBuf::from(file),
+    }
+}
+
+fn record_file(source: &mut Source, name: &str) {
+    if !source.files.iter().any(|f| f == name) {
+        source.files.push(name.to_string());
+    }
+}
+
+fn push_line(source: &mut Source, line: &str, file: &str, line_no: usize) {
+    source.text.push_str(line);
+    source.text.push('\n');
+    source.origins.push((file.to_string(), line_no));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh scratch directory for one test's source files
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sasm-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn dependencies_list_every_included_file() {
+        let dir = scratch("deps");
+        fs::write(dir.join("main.s"), ".include \"macros.s\"\n.incbin \"data.bin\"\nHALT\n").unwrap();
+        fs::write(dir.join("macros.s"), ".include \"macros.s\"\n").unwrap();
+        fs::write(dir.join("data.bin"), [1, 2]).unwrap();
+        let main = dir.join("main.s").display().to_string();
+
+        // A file including itself is caught rather than listed twice
+        assert!(load(&main).err().unwrap().starts_with("Recursive .include of"));
+        fs::write(dir.join("macros.s"), "NOP\n").unwrap();
+        let source = load(&main).unwrap();
+        let files: Vec<String> = ["main.s", "macros.s", "data.bin"]
+            .iter()
+            .map(|f| dir.join(f).display().to_string())
+            .collect();
+        assert_eq!(source.files, files);
+        assert_eq!(crate::dependency_rule("out.bin", &source.files), format!("out.bin: {}\n", files.join(" ")));
+
+        fs::remove_file(dir.join("data.bin")).unwrap();
+        assert!(load(&main).err().unwrap().contains("main.s:2: Error reading"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,51 @@
+//! Wall-clock time for the TUI's rate metrics and cursor blink
+//!
+//! The emulated timer counts CPU cycles and never reads a clock, so runs
+//! stay deterministic; only the host-side display depends on real time.
+
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+pub trait Clock {
+    fn now(&self) -> Instant;
+}
+
+/// The host's monotonic clock
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock that only moves when told to, for tests and scripted front ends.
+/// Clones share one time, so a caller can keep a clone to advance the clock
+/// it handed to the `App`.
+#[derive(Clone)]
+pub struct ManualClock {
+    now: Rc<Cell<Instant>>,
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        ManualClock { now: Rc::new(Cell::new(Instant::now())) }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.now.set(self.now.get() + by);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.now.get()
+    }
+}
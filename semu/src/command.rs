@@ -0,0 +1,161 @@
+//! Debugger commands, shared by the interactive prompt (`-i`) and the TUI
+//! command line (`:`)
+
+use std::fs;
+
+use crate::cpu::Cpu;
+use crate::parse_number;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Step,
+    Run,
+    Dump,
+    Mem(Option<u16>), // Start address; PC when omitted
+    MemAccess,
+    Asm(bool),
+    Break(u16), // Toggle
+    Goto(u16),
+    Set(usize, u16),
+    Load(String, Option<u16>), // Raw binary; without an address, a new program
+    Restore, // Back to the state at the last breakpoint stop
+    Quit,
+    Help,
+}
+
+/// Command summaries for the help listings
+pub const HELP: &[(&str, &str)] = &[
+    ("s, step", "Execute one instruction"),
+    ("r, run", "Run until halt, a breakpoint or a TRAP"),
+    ("d, dump", "Dump CPU state"),
+    ("m, mem [addr]", "Dump memory at PC or addr"),
+    ("maccess", "Show the memory access log (--mem-log)"),
+    ("asm on|off", "Show each stepped instruction (default on)"),
+    ("b, break <addr>", "Set or clear a breakpoint"),
+    ("goto <addr>", "Set PC"),
+    ("set <reg> <value>", "Set a register (R0-R15, SP)"),
+    ("load <file>[@addr]", "Load a program, or data at addr"),
+    ("restore", "Rewind to the last breakpoint stop"),
+    ("q, quit", "Exit"),
+];
+
+/// Parse one command line; `None` for an empty line. A leading `:` is
+/// allowed, as typed in the TUI.
+pub fn parse(input: &str) -> Result<Option<Command>, String> {
+    let input = input.trim();
+    let input = input.strip_prefix(':').unwrap_or(input);
+    let words: Vec<&str> = input.split_whitespace().collect();
+    let Some((&verb, args)) = words.split_first() else {
+        return Ok(None);
+    };
+
+    let cmd = match (verb.to_lowercase().as_str(), args) {
+        ("s" | "step", []) => Command::Step,
+        ("r" | "run", []) => Command::Run,
+        ("d" | "dump", []) => Command::Dump,
+        ("m" | "mem", []) => Command::Mem(None),
+        ("m" | "mem", [addr]) => Command::Mem(Some(parse_addr(addr)?)),
+        ("maccess", []) => Command::MemAccess,
+        ("asm", ["on"]) => Command::Asm(true),
+        ("asm", ["off"]) => Command::Asm(false),
+        ("b" | "break", [addr]) => Command::Break(parse_addr(addr)?),
+        ("goto", [addr]) => Command::Goto(parse_addr(addr)?),
+        ("set", [reg, value]) => Command::Set(parse_reg(reg)?, parse_addr(value)?),
+        ("load", [spec]) => match spec.rsplit_once('@') {
+            Some((path, addr)) if !path.is_empty() => Command::Load(path.to_string(), Some(parse_addr(addr)?)),
+            _ => Command::Load(spec.to_string(), None),
+        },
+        ("restore", []) => Command::Restore,
+        ("q" | "quit", []) => Command::Quit,
+        ("h" | "help", []) => Command::Help,
+        _ => return Err(format!("Unknown command: {}", input)),
+    };
+    Ok(Some(cmd))
+}
+
+fn parse_addr(s: &str) -> Result<u16, String> {
+    parse_number(&s.to_lowercase())
+        .and_then(|n| u16::try_from(n).ok())
+        .ok_or_else(|| format!("Bad value '{}'", s))
+}
+
+fn parse_reg(s: &str) -> Result<usize, String> {
+    let upper = s.to_uppercase();
+    if upper == "SP" {
+        return Ok(2);
+    }
+    match upper.strip_prefix('R').and_then(|n| n.parse::<usize>().ok()) {
+        Some(r) if r < 16 => Ok(r),
+        _ => Err(format!("Unknown register '{}'", s)),
+    }
+}
+
+/// Carry out a command that only changes machine state, returning a line
+/// to show the user; `None` for commands each front end handles itself
+pub fn apply(cpu: &mut Cpu, cmd: &Command) -> Option<Result<String, String>> {
+    let result = match cmd {
+        Command::Break(addr) if cpu.toggle_breakpoint(*addr) => Ok(format!("Breakpoint set at 0x{:04X}", addr)),
+        Command::Break(addr) => Ok(format!("Breakpoint cleared at 0x{:04X}", addr)),
+        Command::Goto(addr) => {
+            cpu.set_pc(*addr);
+            Ok(format!("PC = 0x{:04X}", addr))
+        }
+        Command::Set(0, _) => Err("R0 is always zero".to_string()),
+        Command::Set(r, value) => {
+            cpu.set_register(*r, *value);
+            Ok(format!("R{} = 0x{:04X}", r, value))
+        }
+        Command::Load(path, addr) => load(cpu, path, *addr),
+        _ => return None,
+    };
+    Some(result)
+}
+
+fn load(cpu: &mut Cpu, path: &str, addr: Option<u16>) -> Result<String, String> {
+    let data = fs::read(path).map_err(|e| format!("Error reading {}: {}", path, e))?;
+    let start = addr.unwrap_or(0);
+    if start as usize + data.len() > cpu.mem_size() {
+        return Err(format!("{} ({} bytes) does not fit at 0x{:04X}", path, data.len(), start));
+    }
+    match addr {
+        Some(addr) => {
+            for (i, &byte) in data.iter().enumerate() {
+                cpu.write_memory(addr + i as u16, byte);
+            }
+            Ok(format!("Loaded {} bytes at 0x{:04X}", data.len(), addr))
+        }
+        None => {
+            cpu.load_program(&data);
+            cpu.reset();
+            Ok(format!("Loaded {} bytes, PC = 0x{:04X}", data.len(), cpu.get_pc()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::CpuConfig;
+
+    #[test]
+    fn tui_command_lines_parse_like_the_prompt() {
+        assert_eq!(parse(":break 0x1234"), Ok(Some(Command::Break(0x1234))));
+        assert_eq!(parse("b 0x1234"), Ok(Some(Command::Break(0x1234))));
+        assert_eq!(parse(":goto 0x0200"), Ok(Some(Command::Goto(0x0200))));
+        assert_eq!(parse(":set sp 0x8000"), Ok(Some(Command::Set(2, 0x8000))));
+        assert_eq!(parse(":load prog.bin@0x2000"), Ok(Some(Command::Load("prog.bin".to_string(), Some(0x2000)))));
+        assert_eq!(parse(":"), Ok(None));
+        assert_eq!(parse(":break"), Err("Unknown command: break".to_string()));
+        assert_eq!(parse(":break 0x10000"), Err("Bad value '0x10000'".to_string()));
+        assert_eq!(parse(":set R16 1"), Err("Unknown register 'R16'".to_string()));
+
+        let mut cpu = Cpu::with_config(CpuConfig { seed: 0, ..CpuConfig::default() });
+        let toggle = Command::Break(0x1234);
+        assert_eq!(apply(&mut cpu, &toggle), Some(Ok("Breakpoint set at 0x1234".to_string())));
+        assert!(cpu.is_breakpoint(0x1234));
+        assert_eq!(apply(&mut cpu, &toggle), Some(Ok("Breakpoint cleared at 0x1234".to_string())));
+        assert!(!cpu.is_breakpoint(0x1234));
+        assert_eq!(apply(&mut cpu, &Command::Set(0, 1)), Some(Err("R0 is always zero".to_string())));
+        assert_eq!(apply(&mut cpu, &Command::Step), None);
+    }
+}
@@ -1,8 +1,47 @@
 //! Sampo CPU emulation core
 
-use std::io::{self, Write};
-
-const MEM_SIZE: usize = 65536; // 64KB
+use std::collections::{BTreeSet, VecDeque};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use crate::device::IoDevice;
+use crate::rng::{self, Rng};
+use crate::timer::Timer;
+
+const MEM_SIZE: usize = 65536; // 64KB, the full address space
+const OPEN_BUS: u8 = 0xFF; // Read from an address with no RAM behind it
+const RESET_VECTOR: u16 = 0x0000;
+const IRQ_VECTOR: u16 = 0x0004;
+pub const TRAP_VECTOR: u16 = 0x00FE; // TRAP without a debugger; SWI 127 is reserved for it
+const RESET_PC: u16 = 0x0100; // Start address when no program says otherwise
+const BRANCH_PENALTY: u64 = 2; // Extra cycles when control flow changes
+const PORT_LOG_LEN: usize = 8; // Port writes kept for the debugger
+const SERIAL_BUFFER_LIMIT: usize = 64 * 1024; // Default serial output retained
+
+pub const ACIA_BASE: u8 = 0x80; // Default ACIA status port; data is at base + 1
+
+// BIOS (`--bios`): SWI BIOS_SWI with an empty vector; service number in A0
+// (R4), argument in A1 (R5), result in A0. Its vector sits just below the
+// TRAP vector, clear of the reset and IRQ vectors that programs fill in
+pub const BIOS_SWI: u8 = 0x7C;
+pub const BIOS_PUTCHAR: u16 = 0; // Send A1's low byte to the ACIA
+pub const BIOS_EXIT: u16 = 1;    // Halt
+pub const BIOS_GETCHAR: u16 = 2; // A0 = next ACIA input byte, 0xFFFF if none
+
+// ACIA status bits
+const ACIA_RX_READY: u8 = 0x01;
+const ACIA_TX_READY: u8 = 0x02;
+const ACIA_IRQ: u8 = 0x80; // Interrupt requested
+
+// ACIA control bits (written to the status port)
+const ACIA_RX_IRQ_ENABLE: u8 = 0x80;
+
+// Discovery: DISCOVERY_PORT reads a bitmap with bit n set when the device
+// with ID n is attached; DISCOVERY_PORT + 1 + n reads its version, 0 if absent
+const DISCOVERY_PORT: u8 = 0xF0;
+const MAX_DEVICE_ID: u8 = 7;
+const ACIA_VERSION: u8 = 1; // The ACIA is built in, with ID 0
 
 // Flag bits
 pub const FLAG_N: u8 = 0x80; // Negative
@@ -12,58 +51,271 @@ pub const FLAG_V: u8 = 0x10; // Overflow
 pub const FLAG_H: u8 = 0x08; // Half-carry (BCD)
 pub const FLAG_I: u8 = 0x04; // Interrupt enable
 
+/// Error raised while fetching or executing an instruction
+#[derive(Debug, Clone, PartialEq)]
+pub enum CpuError {
+    PcOutOfBounds(u16),
+    UnknownOpcode(u16),
+    UnknownFunc { op: u16, func: u16 },
+    MemoryFault { addr: u16 },
+    UninitializedRead { addr: u16 },
+    MisalignedStack { sp: u16 },
+    ResetReentry { from: u16 }, // Control reached the reset vector after the first instruction
+    DataExecuted { addr: u16 }, // Fetch from a byte marked as data, see `CpuConfig::exec_guard`
+    UnknownBiosService(u16),
+}
+
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CpuError::PcOutOfBounds(pc) => write!(f, "PC out of bounds: 0x{:04X}", pc),
+            CpuError::UnknownOpcode(op) => write!(f, "Unknown opcode: 0x{:X}", op),
+            CpuError::UnknownFunc { op, func } => {
+                let group = match op {
+                    0x6 => "load",
+                    0x7 => "store",
+                    0xA => "shift",
+                    0xB => "muldiv",
+                    0xC => "misc",
+                    0xD => "I/O",
+                    0xE => "system",
+                    0xF => "extended",
+                    _ => "opcode",
+                };
+                write!(f, "Unknown {} func: 0x{:X}", group, func)
+            }
+            CpuError::MemoryFault { addr } => write!(f, "Memory fault at 0x{:04X}", addr),
+            CpuError::UninitializedRead { addr } => {
+                write!(f, "Read of uninitialized memory at 0x{:04X}", addr)
+            }
+            CpuError::MisalignedStack { sp } => write!(f, "Misaligned stack pointer: 0x{:04X}", sp),
+            CpuError::UnknownBiosService(n) => write!(f, "Unknown BIOS service: {}", n),
+            CpuError::ResetReentry { from } => {
+                write!(f, "Returned to the reset vector 0x{:04X} from 0x{:04X}", RESET_VECTOR, from)
+            }
+            CpuError::DataExecuted { addr } => write!(f, "Executing data at 0x{:04X} (a bad jump?)", addr),
+        }
+    }
+}
+
+impl std::error::Error for CpuError {}
+
+/// Machine configuration for `Cpu::with_config`
+#[derive(Clone, Copy, Debug)]
+pub struct CpuConfig {
+    pub mem_size: usize,        // Installed RAM from 0x0000, at most 64KB
+    pub trap_unmapped: bool,    // Fault on accesses above RAM instead of open bus
+    pub poison: bool,           // Fault on reads of bytes never written
+    pub strict_alignment: bool, // Fault on stack pushes and pops with an odd SP
+    pub reset_guard: bool,      // Fault when PC comes back to the reset vector
+    pub exec_guard: bool,       // Fault on fetches from data, see `Cpu::mark_data`
+    pub bios: bool,             // Serve SWI BIOS_SWI when its vector is empty
+    pub acia_base: u8,          // ACIA status port, with data at the next one
+    pub big_endian: bool,       // Data words high byte first; code stays little-endian
+    pub seed: u64,              // Seed for the RNG device
+}
+
+impl Default for CpuConfig {
+    fn default() -> Self {
+        CpuConfig {
+            mem_size: MEM_SIZE,
+            trap_unmapped: false,
+            poison: false,
+            strict_alignment: false,
+            reset_guard: false,
+            exec_guard: false,
+            bios: false,
+            acia_base: ACIA_BASE,
+            big_endian: false,
+            seed: rng::host_seed(),
+        }
+    }
+}
+
+/// One data memory access recorded by the memory log
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MemAccess {
+    pub pc: u16,    // Instruction that made the access
+    pub addr: u16,
+    pub write: bool,
+    pub word: bool, // Word access; otherwise a byte
+    pub value: u16,
+}
+
+/// Branch outcome counts since the last reset
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub struct BranchStats {
+    pub taken: u64,     // Conditional branches taken
+    pub not_taken: u64, // Conditional branches not taken
+    pub jumps: u64,     // Unconditional jumps (J/JR/JALR/JX/JALX)
+}
+
+/// Data memory accesses since the last reset; instruction fetches and
+/// port I/O are not counted
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub struct MemStats {
+    pub byte_reads: u64,
+    pub word_reads: u64,
+    pub byte_writes: u64,
+    pub word_writes: u64,
+}
+
+impl MemStats {
+    pub fn reads(&self) -> u64 {
+        self.byte_reads + self.word_reads
+    }
+
+    pub fn writes(&self) -> u64 {
+        self.byte_writes + self.word_writes
+    }
+}
+
+/// Machine state saved by `Cpu::snapshot`. Debugger settings (breakpoints,
+/// tracing, logs) are not part of it and survive a restore.
+pub struct Snapshot {
+    regs: [u16; 16],
+    regs_alt: [u16; 8],
+    alt_bank: bool,
+    pc: u16,
+    flags: u8,
+    memory: Vec<u8>,
+    written: Option<Vec<u64>>,
+    data: Option<Vec<u64>>,
+    mem_log: VecDeque<MemAccess>,
+    ports: [u8; 256],
+    port_log: VecDeque<(u8, u8)>,
+    acia_ctrl: u8,
+    devices: Vec<Box<dyn IoDevice>>,
+    halted: bool,
+    nmi_pending: bool,
+    cycles: u64,
+    instructions: u64,
+    cycle_latch: u64,
+    branch_stats: BranchStats,
+    mem_stats: MemStats,
+    serial_out: VecDeque<u8>,
+    serial_dropped: u64,
+    serial_in: VecDeque<u8>,
+}
+
+impl Snapshot {
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+}
+
+/// Callback run after each executed instruction, see `Cpu::set_step_hook`
+pub type StepHook = Box<dyn FnMut(&Cpu, u16)>;
+
 pub struct Cpu {
     // Registers
     regs: [u16; 16],
     regs_alt: [u16; 8], // Alternate R4-R11
+    alt_bank: bool,     // EXX has swapped the alternate set in
     pc: u16,
     flags: u8,
+    entry: u16, // PC restored by reset()
+    instr_pc: u16, // Address of the instruction being executed
 
     // Memory
-    memory: Vec<u8>,
+    memory: Vec<u8>,      // Installed RAM; addresses above it are unmapped
+    trap_unmapped: bool,
+    strict_alignment: bool,
+    reset_guard: bool,
+    bios: bool,
+    big_endian: bool, // Byte order of data words, see `data_word`
+    written: Option<Vec<u64>>, // Poison mode: bitmap of bytes written so far
+    data: Option<Vec<u64>>,    // Exec guard: bitmap of bytes that hold data
+    mem_log: VecDeque<MemAccess>, // Most recent data accesses, oldest first
+    mem_log_len: usize,           // Accesses kept; 0 disables the log
 
     // I/O ports
     ports: [u8; 256],
+    port_log: VecDeque<(u8, u8)>, // Recent (port, value) writes, oldest first
+    acia_base: u8,
+    acia_ctrl: u8,
+    devices: Vec<Box<dyn IoDevice>>, // Attached peripherals besides the ACIA
 
     // State
     halted: bool,
+    nmi_pending: bool,
+    trap_stop: bool,             // A debugger takes TRAPs, see `set_trap_stop`
+    trapped: Option<(u8, u16)>, // TRAP code and address awaiting the debugger
     trace: bool,
+    breakpoints: BTreeSet<u16>, // Checked by the front ends, not by step()
+    step_hook: Option<StepHook>,
     cycles: u64,
+    instructions: u64, // Instructions completed since reset
+    cycle_latch: u64,  // Cycle count captured by RDCYC word 0
+    penalty: u64, // Extra cycles charged by the current instruction
+    branch_stats: BranchStats,
+    mem_stats: MemStats,
     quiet: bool, // Suppress direct stdout output (for TUI mode)
 
-    // Serial output buffer
-    serial_out: Vec<u8>,
+    // Serial buffers
+    serial_out: VecDeque<u8>, // Ring: oldest bytes are dropped past serial_limit
+    serial_limit: usize,
+    serial_dropped: u64,
+    serial_log: Option<BufWriter<File>>, // Transcript of every byte sent to the ACIA
+    serial_in: VecDeque<u8>,
+    rx_polls: u64, // ACIA reads finding no input since the last byte in or out
 }
 
 impl Cpu {
-    pub fn new() -> Self {
+    pub fn with_config(config: CpuConfig) -> Self {
         let mut cpu = Cpu {
             regs: [0; 16],
             regs_alt: [0; 8],
-            pc: 0x0100, // Default start address
+            alt_bank: false,
+            pc: RESET_PC,
             flags: 0,
-            memory: vec![0; MEM_SIZE],
+            entry: RESET_PC,
+            instr_pc: RESET_PC,
+            memory: vec![0; config.mem_size.clamp(2, MEM_SIZE)],
+            trap_unmapped: config.trap_unmapped,
+            strict_alignment: config.strict_alignment,
+            reset_guard: config.reset_guard,
+            bios: config.bios,
+            big_endian: config.big_endian,
+            written: config.poison.then(|| vec![0; MEM_SIZE / 64]),
+            data: config.exec_guard.then(|| vec![0; MEM_SIZE / 64]),
+            mem_log: VecDeque::new(),
+            mem_log_len: 0,
             ports: [0; 256],
+            port_log: VecDeque::new(),
+            acia_base: config.acia_base,
+            acia_ctrl: 0,
+            devices: vec![Box::new(Timer::new()), Box::new(Rng::new(config.seed))],
             halted: false,
+            nmi_pending: false,
+            trap_stop: false,
+            trapped: None,
             trace: false,
+            breakpoints: BTreeSet::new(),
+            step_hook: None,
             cycles: 0,
+            instructions: 0,
+            cycle_latch: 0,
+            penalty: 0,
+            branch_stats: BranchStats::default(),
+            mem_stats: MemStats::default(),
             quiet: false,
-            serial_out: Vec::new(),
+            serial_out: VecDeque::new(),
+            serial_limit: SERIAL_BUFFER_LIMIT,
+            serial_dropped: 0,
+            serial_log: None,
+            serial_in: VecDeque::new(),
+            rx_polls: 0,
         };
-
-        // Initialize SP to top of RAM
-        cpu.regs[2] = 0xFFFE;
-
-        // Set serial TX ready bit
-        cpu.ports[0x80] = 0x02;
-
+        cpu.reset();
         cpu
     }
 
     pub fn load_program(&mut self, program: &[u8]) {
         for (i, &byte) in program.iter().enumerate() {
-            if i < MEM_SIZE {
+            if i < self.memory.len() {
                 self.memory[i] = byte;
+                self.mark_written(i as u16);
             }
         }
 
@@ -72,7 +324,8 @@ impl Cpu {
             if i + 1 < program.len() {
                 let word = u16::from_le_bytes([program[i], program[i + 1]]);
                 if word != 0 {
-                    self.pc = i as u16;
+                    self.entry = i as u16;
+                    self.pc = self.entry;
                     break;
                 }
             }
@@ -83,6 +336,15 @@ impl Cpu {
         self.trace = trace;
     }
 
+    /// Call `hook` with the instruction word after each instruction `step`
+    /// completes, before a pending interrupt is taken. The hook only sees
+    /// the CPU immutably, so it cannot step, reset or alter state, and it
+    /// cannot replace itself. Instructions that fail with an error do not
+    /// reach the hook.
+    pub fn set_step_hook(&mut self, hook: StepHook) {
+        self.step_hook = Some(hook);
+    }
+
     pub fn set_quiet(&mut self, quiet: bool) {
         self.quiet = quiet;
     }
@@ -103,32 +365,257 @@ impl Cpu {
         self.get_reg(r)
     }
 
+    pub fn set_register(&mut self, r: usize, val: u16) {
+        self.set_reg(r, val);
+    }
+
+    /// Whether the alternate R4-R11 are live, after an odd number of EXX
+    pub fn alt_bank_active(&self) -> bool {
+        self.alt_bank
+    }
+
+    /// The swapped-out copy of R4-R11 that the next EXX brings back
+    pub fn get_alt_register(&self, r: usize) -> u16 {
+        match r {
+            4..=11 => self.regs_alt[r - 4],
+            _ => 0,
+        }
+    }
+
+    /// Add a breakpoint at `addr`, or remove an existing one; returns
+    /// whether one is now set
+    pub fn toggle_breakpoint(&mut self, addr: u16) -> bool {
+        if self.breakpoints.remove(&addr) {
+            false
+        } else {
+            self.breakpoints.insert(addr)
+        }
+    }
+
+    pub fn is_breakpoint(&self, addr: u16) -> bool {
+        self.breakpoints.contains(&addr)
+    }
+
+    /// Breakpoint addresses in ascending order
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
     pub fn get_cycles(&self) -> u64 {
         self.cycles
     }
 
+    pub fn get_instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    pub fn branch_stats(&self) -> BranchStats {
+        self.branch_stats
+    }
+
+    pub fn mem_stats(&self) -> MemStats {
+        self.mem_stats
+    }
+
     pub fn is_halted(&self) -> bool {
         self.halted
     }
 
+    /// Request a non-maskable break into the debugger. Unlike an IRQ this
+    /// ignores FLAG_I; the frontend stops before the next instruction.
+    pub fn raise_nmi(&mut self) {
+        self.nmi_pending = true;
+    }
+
+    /// Returns true (and acknowledges it) if an NMI is pending
+    pub fn take_nmi(&mut self) -> bool {
+        std::mem::take(&mut self.nmi_pending)
+    }
+
+    /// With a debugger attached, `TRAP n` just records itself for
+    /// `take_trap` and execution continues after it; otherwise it enters
+    /// TRAP_VECTOR like an SWI
+    pub fn set_trap_stop(&mut self, on: bool) {
+        self.trap_stop = on;
+    }
+
+    /// The code and address of a TRAP the debugger should stop for, if the
+    /// last step executed one
+    pub fn take_trap(&mut self) -> Option<(u8, u16)> {
+        self.trapped.take()
+    }
+
+    /// Power-on state: registers, flags, ports, devices and serial buffers
+    /// are cleared and PC returns to the program entry. Memory is kept, so
+    /// the loaded program can be run again.
     pub fn reset(&mut self) {
         self.regs = [0; 16];
         self.regs_alt = [0; 8];
-        self.pc = 0x0100;
+        self.alt_bank = false;
+        self.regs[2] = self.stack_top();
+        self.pc = self.entry;
         self.flags = 0;
+        self.ports = [0; 256];
+        self.port_log.clear();
+        self.mem_log.clear();
+        self.acia_ctrl = 0;
+        for dev in &mut self.devices {
+            dev.reset();
+        }
         self.halted = false;
+        self.nmi_pending = false;
+        self.trapped = None;
         self.cycles = 0;
+        self.instructions = 0;
+        self.cycle_latch = 0;
+        self.branch_stats = BranchStats::default();
+        self.mem_stats = MemStats::default();
         self.serial_out.clear();
-        self.regs[2] = 0xFFFE;
-        self.ports[0x80] = 0x02;
+        self.serial_dropped = 0;
+        self.serial_in.clear();
+        self.rx_polls = 0;
+    }
+
+    /// Save the machine state, to go back to with `restore`
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            regs: self.regs,
+            regs_alt: self.regs_alt,
+            alt_bank: self.alt_bank,
+            pc: self.pc,
+            flags: self.flags,
+            memory: self.memory.clone(),
+            written: self.written.clone(),
+            data: self.data.clone(),
+            mem_log: self.mem_log.clone(),
+            ports: self.ports,
+            port_log: self.port_log.clone(),
+            acia_ctrl: self.acia_ctrl,
+            devices: self.devices.iter().map(|dev| dev.snapshot()).collect(),
+            halted: self.halted,
+            nmi_pending: self.nmi_pending,
+            cycles: self.cycles,
+            instructions: self.instructions,
+            cycle_latch: self.cycle_latch,
+            branch_stats: self.branch_stats,
+            mem_stats: self.mem_stats,
+            serial_out: self.serial_out.clone(),
+            serial_dropped: self.serial_dropped,
+            serial_in: self.serial_in.clone(),
+        }
+    }
+
+    /// Return to a saved state. The snapshot is kept, so the same point can
+    /// be restored again. Bytes already written to a serial log stay there.
+    pub fn restore(&mut self, snap: &Snapshot) {
+        self.regs = snap.regs;
+        self.regs_alt = snap.regs_alt;
+        self.alt_bank = snap.alt_bank;
+        self.pc = snap.pc;
+        self.flags = snap.flags;
+        self.memory.clone_from(&snap.memory);
+        self.written.clone_from(&snap.written);
+        self.data.clone_from(&snap.data);
+        self.mem_log.clone_from(&snap.mem_log);
+        self.ports = snap.ports;
+        self.port_log.clone_from(&snap.port_log);
+        self.acia_ctrl = snap.acia_ctrl;
+        self.devices = snap.devices.iter().map(|dev| dev.snapshot()).collect();
+        self.halted = snap.halted;
+        self.nmi_pending = snap.nmi_pending;
+        self.cycles = snap.cycles;
+        self.instructions = snap.instructions;
+        self.cycle_latch = snap.cycle_latch;
+        self.branch_stats = snap.branch_stats;
+        self.mem_stats = snap.mem_stats;
+        self.serial_out.clone_from(&snap.serial_out);
+        self.serial_dropped = snap.serial_dropped;
+        self.serial_in.clone_from(&snap.serial_in);
+    }
+
+    pub fn mem_size(&self) -> usize {
+        self.memory.len()
+    }
+
+    /// Initial SP: the last word of installed RAM
+    fn stack_top(&self) -> u16 {
+        (self.memory.len() - 2) as u16 & !1
     }
 
     pub fn read_memory(&self, addr: u16) -> u8 {
-        self.memory[addr as usize]
+        self.memory.get(addr as usize).copied().unwrap_or(OPEN_BUS)
+    }
+
+    pub fn write_memory(&mut self, addr: u16, val: u8) {
+        if let Some(cell) = self.memory.get_mut(addr as usize) {
+            *cell = val;
+            self.mark_written(addr);
+        }
     }
 
-    pub fn get_serial_output(&self) -> &[u8] {
-        &self.serial_out
+    fn mark_written(&mut self, addr: u16) {
+        if let Some(written) = &mut self.written {
+            written[addr as usize / 64] |= 1 << (addr % 64);
+        }
+    }
+
+    /// With the exec guard on, mark `len` bytes from `addr` as data that
+    /// must not be executed, such as a `.db` table or a `--load` file.
+    /// Bytes the program stores are marked as it runs.
+    pub fn mark_data(&mut self, addr: u16, len: usize) {
+        if let Some(data) = &mut self.data {
+            for a in (addr as usize..addr as usize + len).take_while(|&a| a < MEM_SIZE) {
+                data[a / 64] |= 1 << (a % 64);
+            }
+        }
+    }
+
+    fn is_data(&self, addr: u16) -> bool {
+        match &self.data {
+            Some(data) => data[addr as usize / 64] & (1 << (addr % 64)) != 0,
+            None => false,
+        }
+    }
+
+    /// False only in poison mode, for bytes nothing has written yet
+    fn is_written(&self, addr: u16) -> bool {
+        match &self.written {
+            Some(written) => written[addr as usize / 64] & (1 << (addr % 64)) != 0,
+            None => true,
+        }
+    }
+
+    /// Serial output not yet cleared, at most the buffer limit's worth
+    pub fn get_serial_output(&self) -> impl Iterator<Item = u8> + '_ {
+        self.serial_out.iter().copied()
+    }
+
+    /// Also append all serial output to `file`
+    pub fn set_serial_log(&mut self, file: File) {
+        self.serial_log = Some(BufWriter::new(file));
+    }
+
+    /// Write out buffered serial log output
+    pub fn flush_serial_log(&mut self) -> io::Result<()> {
+        match &mut self.serial_log {
+            Some(log) => log.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Keep at most `limit` bytes of serial output, dropping the oldest
+    /// beyond that. Output is printed live outside the TUI, so the buffer
+    /// only feeds the TUI terminal and `dump_state`.
+    pub fn set_serial_buffer_limit(&mut self, limit: usize) {
+        self.serial_limit = limit;
+        self.trim_serial_output();
+    }
+
+    fn trim_serial_output(&mut self) {
+        while self.serial_out.len() > self.serial_limit {
+            self.serial_out.pop_front();
+            self.serial_dropped += 1;
+        }
     }
 
     pub fn clear_serial_output(&mut self) {
@@ -136,21 +623,149 @@ impl Cpu {
     }
 
     pub fn send_key(&mut self, key: u8) {
-        // Queue input for serial port
-        self.ports[0x81] = key;
-        self.ports[0x80] |= 0x01; // RX ready
+        // Queue input for the serial port
+        self.serial_in.push_back(key);
+        self.rx_polls = 0;
+    }
+
+    /// ACIA status and data reads (and BIOS reads) that found no input
+    /// since a byte was last queued or sent: a program waiting on input
+    /// it may never get keeps this climbing
+    pub fn rx_polls(&self) -> u64 {
+        self.rx_polls
+    }
+
+    /// Last value written to each plain (non-device) port
+    pub fn ports(&self) -> &[u8; 256] {
+        &self.ports
+    }
+
+    /// The most recent port writes as (port, value), oldest first
+    pub fn recent_port_writes(&self) -> impl Iterator<Item = &(u8, u8)> {
+        self.port_log.iter()
+    }
+
+    /// ACIA status and data ports
+    pub fn acia_ports(&self) -> [u8; 2] {
+        [self.acia_base, self.acia_base.wrapping_add(1)]
+    }
+
+    /// Why the configured ACIA ports cannot be used, if they clash with
+    /// the discovery ports or an attached device
+    pub fn acia_conflict(&self) -> Option<String> {
+        if self.acia_base == u8::MAX {
+            return Some(format!("ACIA at 0x{:02X} has no room for its data port", self.acia_base));
+        }
+        self.acia_ports().into_iter().find_map(|port| {
+            if is_discovery_port(port) {
+                Some(format!("ACIA port 0x{:02X} is a discovery port", port))
+            } else {
+                self.devices.iter()
+                    .find(|dev| dev.owns(port))
+                    .map(|dev| format!("ACIA port 0x{:02X} is used by {}", port, dev.name()))
+            }
+        })
+    }
+
+    /// ACIA status as the program would read it from its status port
+    pub fn acia_status(&self) -> u8 {
+        // TX always ready, RX ready while input is queued
+        let rx = if self.serial_in.is_empty() { 0 } else { ACIA_RX_READY };
+        let irq = if self.acia_irq() { ACIA_IRQ } else { 0 };
+        ACIA_TX_READY | rx | irq
+    }
+
+    /// ACIA interrupt line: held while RX interrupts are enabled and input is queued
+    fn acia_irq(&self) -> bool {
+        self.acia_ctrl & ACIA_RX_IRQ_ENABLE != 0 && !self.serial_in.is_empty()
+    }
+
+    /// Whether any device is requesting an interrupt
+    pub fn irq_pending(&self) -> bool {
+        self.devices.iter().any(|dev| dev.irq()) || self.acia_irq()
+    }
+
+    /// Attached devices, in attach order
+    pub fn devices(&self) -> impl Iterator<Item = &dyn IoDevice> {
+        self.devices.iter().map(|dev| dev.as_ref())
+    }
+
+    /// Attach a device at run time; its ports must all be free
+    pub fn attach_device(&mut self, dev: Box<dyn IoDevice>) -> Result<(), String> {
+        if self.devices.iter().any(|d| d.name() == dev.name()) {
+            return Err(format!("{} is already attached", dev.name()));
+        }
+        if let Some(other) = self.devices.iter().find(|d| d.id() == dev.id()) {
+            return Err(format!("{} has the same discovery ID as {}", dev.name(), other.name()));
+        }
+        let busy = |port: u8| {
+            self.acia_ports().contains(&port) || is_discovery_port(port)
+                || self.devices.iter().any(|d| d.owns(port))
+        };
+        if let Some(port) = (0..=255u8).find(|&p| dev.owns(p) && busy(p)) {
+            return Err(format!("{} conflicts with a device at port 0x{:02X}", dev.name(), port));
+        }
+        self.devices.push(dev);
+        Ok(())
+    }
+
+    /// Keep the last `len` data memory accesses; 0 turns the log off
+    pub fn enable_mem_log(&mut self, len: usize) {
+        self.mem_log_len = len;
+        while self.mem_log.len() > len {
+            self.mem_log.pop_front();
+        }
+    }
+
+    /// Logged memory accesses, oldest first
+    pub fn mem_log(&self) -> impl Iterator<Item = &MemAccess> {
+        self.mem_log.iter()
+    }
+
+    fn log_access(&mut self, addr: u16, write: bool, word: bool, value: u16) {
+        if self.mem_log_len == 0 {
+            return;
+        }
+        if self.mem_log.len() == self.mem_log_len {
+            self.mem_log.pop_front();
+        }
+        self.mem_log.push_back(MemAccess { pc: self.instr_pc, addr, write, word, value });
+    }
+
+    /// Detach a device by name; its ports revert to plain latches
+    pub fn detach_device(&mut self, name: &str) -> Option<Box<dyn IoDevice>> {
+        let idx = self.devices.iter().position(|d| d.name() == name)?;
+        Some(self.devices.remove(idx))
+    }
+
+    /// Reset one device without touching the CPU; false if it is not attached
+    pub fn reset_device(&mut self, name: &str) -> bool {
+        match self.devices.iter_mut().find(|d| d.name() == name) {
+            Some(dev) => {
+                dev.reset();
+                true
+            }
+            None => false,
+        }
     }
 
     pub fn get_sp(&self) -> u16 {
         self.regs[2]
     }
 
-    pub fn step(&mut self) -> Result<bool, String> {
+    pub fn step(&mut self) -> Result<bool, CpuError> {
         if self.halted {
             return Ok(false);
         }
 
+        // A crashed program often ends up at 0x0000 (e.g. RET with a zeroed
+        // stack). Only the very first instruction may legitimately run there.
+        if self.reset_guard && self.pc == RESET_VECTOR && self.instructions > 0 {
+            return Err(CpuError::ResetReentry { from: self.instr_pc });
+        }
+
         // Fetch instruction
+        self.instr_pc = self.pc;
         let instr = self.fetch_word()?;
 
         if self.trace {
@@ -158,15 +773,63 @@ impl Cpu {
         }
 
         // Decode and execute
+        self.penalty = 0;
         self.execute(instr)?;
 
-        self.cycles += 1;
+        // Devices advance by the instruction's cycle cost
+        let cost = 1 + self.penalty;
+        self.cycles += cost;
+        self.instructions += 1;
+        for dev in &mut self.devices {
+            dev.tick(cost);
+        }
+
+        if let Some(mut hook) = self.step_hook.take() {
+            hook(self, instr);
+            self.step_hook = Some(hook);
+        }
+
+        // Take a pending interrupt at the instruction boundary
+        if self.irq_pending() && self.flags & FLAG_I != 0 && !self.halted {
+            self.interrupt(IRQ_VECTOR)?;
+        }
+
         Ok(!self.halted)
     }
 
-    fn fetch_word(&mut self) -> Result<u16, String> {
-        if self.pc as usize + 1 >= MEM_SIZE {
-            return Err("PC out of bounds".to_string());
+    /// Push an interrupt frame and enter a handler with interrupts
+    /// disabled; RETI undoes both
+    fn interrupt(&mut self, vector: u16) -> Result<(), CpuError> {
+        self.push_interrupt_frame()?;
+        self.flags &= !FLAG_I;
+        self.pc = vector;
+        Ok(())
+    }
+
+    /// Push FLAGS (as a word) and then PC, the frame RETI pops, so a
+    /// handler cannot disturb the flags of the code it interrupted
+    fn push_interrupt_frame(&mut self) -> Result<(), CpuError> {
+        let sp = self.stack_pointer()?.wrapping_sub(4);
+        self.set_reg(2, sp);
+        self.write_word(sp.wrapping_add(2), self.flags as u16)?;
+        self.write_word(sp, self.pc)
+    }
+
+    /// SP for a push or pop, which must be even in strict alignment mode
+    fn stack_pointer(&self) -> Result<u16, CpuError> {
+        let sp = self.get_reg(2);
+        if self.strict_alignment && sp & 1 != 0 {
+            return Err(CpuError::MisalignedStack { sp });
+        }
+        Ok(sp)
+    }
+
+    fn fetch_word(&mut self) -> Result<u16, CpuError> {
+        if self.pc as usize + 1 >= self.memory.len() {
+            return Err(CpuError::PcOutOfBounds(self.pc));
+        }
+        if let Some(addr) = [self.pc, self.pc + 1].into_iter().find(|&a| self.is_data(a)) {
+            return Err(CpuError::DataExecuted { addr });
         }
         let lo = self.memory[self.pc as usize];
         let hi = self.memory[self.pc as usize + 1];
@@ -174,7 +837,7 @@ impl Cpu {
         Ok(u16::from_le_bytes([lo, hi]))
     }
 
-    fn execute(&mut self, instr: u16) -> Result<(), String> {
+    fn execute(&mut self, instr: u16) -> Result<(), CpuError> {
         let opcode = (instr >> 12) & 0xF;
         let rd = ((instr >> 8) & 0xF) as usize;
         let rs1 = ((instr >> 4) & 0xF) as usize;
@@ -218,7 +881,9 @@ impl Cpu {
                 self.set_flags_logic(result);
             }
             0x5 => {
-                // ADDI Rd, imm8
+                // ADDI Rd, imm8. The sign-extended immediate carries its
+                // true sign in bit 15, so V is exact signed overflow; C is
+                // the plain carry out (set by most negative immediates)
                 let a = self.get_reg(rd);
                 let b = imm8;
                 let (result, carry) = a.overflowing_add(b);
@@ -239,31 +904,23 @@ impl Cpu {
                 let offset = (instr & 0xFF) as i8 as i16;
                 if self.check_condition(cond) {
                     self.pc = (self.pc as i16).wrapping_add(offset * 2) as u16;
+                    self.branch_stats.taken += 1;
+                    self.penalty = BRANCH_PENALTY;
+                } else {
+                    self.branch_stats.not_taken += 1;
                 }
             }
             0x9 => {
-                // Jump operations
-                // JR is encoded as 9F0R (R in bits 7:4, low nibble = 0)
-                // JALR is encoded as 9DR1 (D in bits 11:8, R in bits 7:4, func = 1)
-                if (instr & 0x0F0F) == 0x0F00 {
-                    // JR Rs (9F0R pattern)
-                    let rs = rs1;
-                    self.pc = self.get_reg(rs);
-                } else if func == 0x1 && rd != 0 {
-                    // JALR Rd, Rs
-                    let ret_addr = self.pc;
-                    self.pc = self.get_reg(rs1);
-                    self.set_reg(rd, ret_addr);
+                // J offset12 (register jumps are misc func 0xE)
+                self.branch_stats.jumps += 1;
+                self.penalty = BRANCH_PENALTY;
+                let offset = (instr & 0x0FFF) as i16;
+                let offset = if offset & 0x800 != 0 {
+                    offset | 0xF000u16 as i16
                 } else {
-                    // J offset12
-                    let offset = (instr & 0x0FFF) as i16;
-                    let offset = if offset & 0x800 != 0 {
-                        offset | 0xF000u16 as i16
-                    } else {
-                        offset
-                    };
-                    self.pc = (self.pc as i16).wrapping_add(offset * 2) as u16;
-                }
+                    offset
+                };
+                self.pc = (self.pc as i16).wrapping_add(offset * 2) as u16;
             }
             0xA => {
                 // Shift operations
@@ -291,14 +948,14 @@ impl Cpu {
                 self.execute_extended(rd, rs1, func, imm16)?;
             }
             _ => {
-                return Err(format!("Unknown opcode: 0x{:X}", opcode));
+                return Err(CpuError::UnknownOpcode(opcode));
             }
         }
 
         Ok(())
     }
 
-    fn execute_load(&mut self, rd: usize, rs1: usize, func: u16) -> Result<(), String> {
+    fn execute_load(&mut self, rd: usize, rs1: usize, func: u16) -> Result<(), CpuError> {
         let base = self.get_reg(rs1);
         let offset: i16 = match func {
             0x0 => 0,
@@ -315,7 +972,15 @@ impl Cpu {
                 self.set_reg(rd, val);
                 return Ok(());
             }
-            _ => return Err(format!("Unknown load func: 0x{:X}", func)),
+            0x9 | 0xA => {
+                // LW Rd, (Rs1)+ / LW Rd, -(Rs1); Rd wins if it is Rs1
+                let addr = if func == 0xA { base.wrapping_sub(2) } else { base };
+                let val = self.read_word(addr)?;
+                self.set_reg(rs1, if func == 0xA { addr } else { base.wrapping_add(2) });
+                self.set_reg(rd, val);
+                return Ok(());
+            }
+            _ => return Err(CpuError::UnknownFunc { op: 0x6, func }),
         };
 
         let addr = (base as i16).wrapping_add(offset) as u16;
@@ -341,7 +1006,7 @@ impl Cpu {
         Ok(())
     }
 
-    fn execute_store(&mut self, rs2: usize, rs1: usize, func: u16) -> Result<(), String> {
+    fn execute_store(&mut self, rs2: usize, rs1: usize, func: u16) -> Result<(), CpuError> {
         let base = self.get_reg(rs1);
         let value = self.get_reg(rs2);
 
@@ -353,7 +1018,14 @@ impl Cpu {
             0x4 => 6,
             0x5 => -2,
             0x6 => -4,
-            _ => return Err(format!("Unknown store func: 0x{:X}", func)),
+            0x7 | 0x8 => {
+                // SW (Rs1)+, Rs2 / SW -(Rs1), Rs2; stores Rs2 as it was
+                let addr = if func == 0x8 { base.wrapping_sub(2) } else { base };
+                self.write_word(addr, value)?;
+                self.set_reg(rs1, if func == 0x8 { addr } else { base.wrapping_add(2) });
+                return Ok(());
+            }
+            _ => return Err(CpuError::UnknownFunc { op: 0x7, func }),
         };
 
         let addr = (base as i16).wrapping_add(offset) as u16;
@@ -367,7 +1039,7 @@ impl Cpu {
         Ok(())
     }
 
-    fn execute_shift(&mut self, rd: usize, rs1: usize, func: u16) -> Result<(), String> {
+    fn execute_shift(&mut self, rd: usize, rs1: usize, func: u16) -> Result<(), CpuError> {
         let val = self.get_reg(rs1);
         let result = match func {
             0x0 => val << 1,                           // SLL 1
@@ -398,14 +1070,14 @@ impl Cpu {
             0xD => val >> 8,                           // SRL 8
             0xE => ((val as i16) >> 8) as u16,         // SRA 8
             0xF => val.rotate_left(8),                 // ROL 8
-            _ => return Err(format!("Unknown shift func: 0x{:X}", func)),
+            _ => return Err(CpuError::UnknownFunc { op: 0xA, func }),
         };
         self.set_reg(rd, result);
         self.set_flags_logic(result);
         Ok(())
     }
 
-    fn execute_muldiv(&mut self, rd: usize, rs1: usize, func: u16) -> Result<(), String> {
+    fn execute_muldiv(&mut self, rd: usize, rs1: usize, func: u16) -> Result<(), CpuError> {
         let a = self.get_reg(rd);
         let b = self.get_reg(rs1);
 
@@ -436,12 +1108,8 @@ impl Cpu {
             }
             0x4 => {
                 // DIVU (unsigned)
-                if b == 0 {
-                    self.set_reg(rd, 0xFFFF);
-                } else {
-                    let result = a / b;
-                    self.set_reg(rd, result);
-                }
+                let result = a.checked_div(b).unwrap_or(0xFFFF);
+                self.set_reg(rd, result);
             }
             0x5 => {
                 // REM (signed)
@@ -486,23 +1154,23 @@ impl Cpu {
                 }
                 self.set_flags_logic(val);
             }
-            _ => return Err(format!("Unknown muldiv func: 0x{:X}", func)),
+            _ => return Err(CpuError::UnknownFunc { op: 0xB, func }),
         }
         Ok(())
     }
 
-    fn execute_misc(&mut self, rd: usize, rs1: usize, func: u16) -> Result<(), String> {
+    fn execute_misc(&mut self, rd: usize, rs1: usize, func: u16) -> Result<(), CpuError> {
         match func {
             0x0 => {
                 // PUSH Rs1
-                let sp = self.get_reg(2).wrapping_sub(2);
+                let sp = self.stack_pointer()?.wrapping_sub(2);
                 self.set_reg(2, sp);
                 let val = self.get_reg(rs1);
                 self.write_word(sp, val)?;
             }
             0x1 => {
                 // POP Rd
-                let sp = self.get_reg(2);
+                let sp = self.stack_pointer()?;
                 let val = self.read_word(sp)?;
                 self.set_reg(rd, val);
                 self.set_reg(2, sp.wrapping_add(2));
@@ -644,6 +1312,7 @@ impl Cpu {
                 for i in 0..8 {
                     std::mem::swap(&mut self.regs[4 + i], &mut self.regs_alt[i]);
                 }
+                self.alt_bank = !self.alt_bank;
             }
             0xC => {
                 // GETF Rd
@@ -653,12 +1322,24 @@ impl Cpu {
                 // SETF Rs1
                 self.flags = self.get_reg(rs1) as u8;
             }
-            _ => return Err(format!("Unknown misc func: 0x{:X}", func)),
+            0xE => {
+                // JALR Rd, Rs1 (JR Rs1 is JALR R0, Rs1)
+                let ret_addr = self.pc;
+                self.pc = self.get_reg(rs1);
+                self.set_reg(rd, ret_addr);
+                self.branch_stats.jumps += 1;
+                self.penalty = BRANCH_PENALTY;
+            }
+            0xF => {
+                // MOV Rd, PC: the address of this instruction, like `$`
+                self.set_reg(rd, self.instr_pc);
+            }
+            _ => return Err(CpuError::UnknownFunc { op: 0xC, func }),
         }
         Ok(())
     }
 
-    fn execute_io(&mut self, rd: usize, rs1: usize, func: u16) -> Result<(), String> {
+    fn execute_io(&mut self, rd: usize, rs1: usize, func: u16) -> Result<(), CpuError> {
         match func {
             0x0 => {
                 // INI Rd, port (immediate port in next nibble)
@@ -684,12 +1365,12 @@ impl Cpu {
                 let val = self.get_reg(rs1) as u8;
                 self.port_write(port, val);
             }
-            _ => return Err(format!("Unknown I/O func: 0x{:X}", func)),
+            _ => return Err(CpuError::UnknownFunc { op: 0xD, func }),
         }
         Ok(())
     }
 
-    fn execute_system(&mut self, func: usize, imm: u8) -> Result<(), String> {
+    fn execute_system(&mut self, func: usize, imm: u8) -> Result<(), CpuError> {
         match func {
             0x0 => {} // NOP
             0x1 => {
@@ -706,19 +1387,27 @@ impl Cpu {
             }
             0x4 => {
                 // RETI
-                // Pop PC from stack
-                let sp = self.get_reg(2);
+                // Pop PC, then the FLAGS saved on entry (I included)
+                let sp = self.stack_pointer()?;
                 let pc = self.read_word(sp)?;
-                self.set_reg(2, sp.wrapping_add(2));
+                let flags = self.read_word(sp.wrapping_add(2))?;
+                self.set_reg(2, sp.wrapping_add(4));
                 self.pc = pc;
-                self.flags |= FLAG_I;
+                self.flags = flags as u8;
             }
             0x5 => {
                 // SWI imm
-                // Push PC, jump to interrupt handler
-                let sp = self.get_reg(2).wrapping_sub(2);
-                self.set_reg(2, sp);
-                self.write_word(sp, self.pc)?;
+                // With --bios, a BIOS_SWI vector holding 0x0000 has no handler
+                let vector = (imm as u16) * 2;
+                if self.bios
+                    && imm == BIOS_SWI
+                    && self.read_memory(vector) == 0
+                    && self.read_memory(vector.wrapping_add(1)) == 0
+                {
+                    return self.bios_call();
+                }
+                // Push FLAGS and PC, jump to interrupt handler
+                self.push_interrupt_frame()?;
                 self.pc = (imm as u16) * 2; // Simple vector table
             }
             0x6 => {
@@ -729,12 +1418,41 @@ impl Cpu {
                 // CCF
                 self.flags ^= FLAG_C;
             }
-            _ => return Err(format!("Unknown system func: 0x{:X}", func)),
+            0xA => {
+                // CSKIP cond: step over the next instruction, 2 or 4 bytes
+                if self.check_condition((imm & 0xF) as u16) {
+                    // Read the next word as fetch would, then step over its
+                    // extension word too if it has one
+                    let next = self.fetch_word()?;
+                    if next >> 12 == 0xF {
+                        self.pc = self.pc.wrapping_add(2);
+                    }
+                }
+            }
+            0xB => {
+                // RDCYC Rd, word: word 0 latches the cycle count before this
+                // instruction and reads bits 15:0; words 1-3 read the rest
+                let word = (imm & 0x3) as u32;
+                if word == 0 {
+                    self.cycle_latch = self.cycles;
+                }
+                self.set_reg((imm >> 4) as usize, (self.cycle_latch >> (16 * word)) as u16);
+            }
+            0xC => {
+                // TRAP imm: a planted breakpoint
+                if self.trap_stop {
+                    self.trapped = Some((imm, self.instr_pc));
+                } else {
+                    self.push_interrupt_frame()?;
+                    self.pc = TRAP_VECTOR;
+                }
+            }
+            _ => return Err(CpuError::UnknownFunc { op: 0xE, func: func as u16 }),
         }
         Ok(())
     }
 
-    fn execute_extended(&mut self, rd: usize, rs1: usize, sub: u16, imm16: u16) -> Result<(), String> {
+    fn execute_extended(&mut self, rd: usize, rs1: usize, sub: u16, imm16: u16) -> Result<(), CpuError> {
         match sub {
             0x0 => {
                 // ADDIX Rd, Rs1, imm16
@@ -784,14 +1502,31 @@ impl Cpu {
                 // LIX Rd, imm16
                 self.set_reg(rd, imm16);
             }
+            0x8 if rs1 != 0 => {
+                // CAS Rd, (Rs1), Rs2 with Rs2 in imm16[3:0]: store Rs2 if
+                // the word still equals Rd, else load it into Rd
+                let addr = self.get_reg(rs1);
+                let current = self.read_word(addr)?;
+                if current == self.get_reg(rd) {
+                    self.write_word(addr, self.get_reg(imm16 as usize & 0xF))?;
+                    self.flags |= FLAG_Z;
+                } else {
+                    self.set_reg(rd, current);
+                    self.flags &= !FLAG_Z;
+                }
+            }
             0x8 => {
                 // JX addr16
                 self.pc = imm16;
+                self.branch_stats.jumps += 1;
+                self.penalty = BRANCH_PENALTY;
             }
             0x9 => {
                 // JALX addr16
                 self.set_reg(rd, self.pc);
                 self.pc = imm16;
+                self.branch_stats.jumps += 1;
+                self.penalty = BRANCH_PENALTY;
             }
             0xA => {
                 // CMPIX Rd, imm16
@@ -799,6 +1534,11 @@ impl Cpu {
                 let (result, borrow) = a.overflowing_sub(imm16);
                 self.set_flags_sub(a, imm16, result, borrow);
             }
+            0xB if imm16 >> 8 != 0 => {
+                // INX only needs the port in imm16[7:0], so a nonzero
+                // imm16[15:8] picks a two-register op instead
+                self.execute_ext_alu(rd, rs1, imm16)?;
+            }
             0xB => {
                 // INX Rd, port8
                 let val = self.port_read(imm16 as u8);
@@ -831,7 +1571,54 @@ impl Cpu {
                 self.set_reg(rd, result);
                 self.set_flags_logic(result);
             }
-            _ => return Err(format!("Unknown extended sub: 0x{:X}", sub)),
+            _ => return Err(CpuError::UnknownFunc { op: 0xF, func: sub }),
+        }
+        Ok(())
+    }
+
+    /// Extended sub 0xB ops on Rd and Rs1 (or a bit number in imm16[3:0]),
+    /// selected by imm16[15:8]
+    fn execute_ext_alu(&mut self, rd: usize, rs1: usize, imm16: u16) -> Result<(), CpuError> {
+        let op = imm16 >> 8;
+        let a = self.get_reg(rd);
+        let b = self.get_reg(rs1);
+
+        match op {
+            0x01..=0x04 => {
+                // ADDS/SUBS clamp to the signed range and report it in V;
+                // ADDSU/SUBSU clamp to 0..0xFFFF and report it in C
+                let (result, clamped) = match op {
+                    0x01 => match (a as i16).checked_add(b as i16) {
+                        Some(r) => (r as u16, false),
+                        None if (b as i16) < 0 => (0x8000, true),
+                        None => (0x7FFF, true),
+                    },
+                    0x02 => match (a as i16).checked_sub(b as i16) {
+                        Some(r) => (r as u16, false),
+                        None if (b as i16) < 0 => (0x7FFF, true),
+                        None => (0x8000, true),
+                    },
+                    0x03 => a.checked_add(b).map_or((0xFFFF, true), |r| (r, false)),
+                    _ => a.checked_sub(b).map_or((0x0000, true), |r| (r, false)),
+                };
+                self.set_reg(rd, result);
+                self.set_flags_logic(result);
+                if clamped {
+                    self.flags |= if op < 0x03 { FLAG_V } else { FLAG_C };
+                }
+            }
+            0x05..=0x07 => {
+                // BSET/BCLR/BTST Rd, bit with the bit number in imm16[3:0].
+                // BTST sets Z when the bit is clear; no other flag changes.
+                let mask = 1u16 << (imm16 & 0xF);
+                match op {
+                    0x05 => self.set_reg(rd, a | mask),
+                    0x06 => self.set_reg(rd, a & !mask),
+                    _ if a & mask == 0 => self.flags |= FLAG_Z,
+                    _ => self.flags &= !FLAG_Z,
+                }
+            }
+            _ => return Err(CpuError::UnknownFunc { op: 0xF, func: op << 8 | 0xB }),
         }
         Ok(())
     }
@@ -852,57 +1639,156 @@ impl Cpu {
     }
 
     // Memory access
-    fn read_byte(&self, addr: u16) -> Result<u8, String> {
-        Ok(self.memory[addr as usize])
+    // Above installed RAM, reads see the open bus and writes are dropped,
+    // unless the configuration asks for a fault
+    /// Byte read without logging
+    fn load(&self, addr: u16) -> Result<u8, CpuError> {
+        match self.memory.get(addr as usize) {
+            Some(_) if !self.is_written(addr) => Err(CpuError::UninitializedRead { addr }),
+            Some(&byte) => Ok(byte),
+            None if self.trap_unmapped => Err(CpuError::MemoryFault { addr }),
+            None => Ok(OPEN_BUS),
+        }
     }
 
-    fn write_byte(&mut self, addr: u16, val: u8) -> Result<(), String> {
-        self.memory[addr as usize] = val;
+    /// Byte write without logging
+    fn store(&mut self, addr: u16, val: u8) -> Result<(), CpuError> {
+        match self.memory.get_mut(addr as usize) {
+            Some(cell) => {
+                *cell = val;
+                self.mark_written(addr);
+                self.mark_data(addr, 1);
+            }
+            None if self.trap_unmapped => return Err(CpuError::MemoryFault { addr }),
+            None => {}
+        }
         Ok(())
     }
 
-    fn read_word(&self, addr: u16) -> Result<u16, String> {
-        let lo = self.memory[addr as usize];
-        let hi = self.memory[addr.wrapping_add(1) as usize];
-        Ok(u16::from_le_bytes([lo, hi]))
+    fn read_byte(&mut self, addr: u16) -> Result<u8, CpuError> {
+        let val = self.load(addr)?;
+        self.mem_stats.byte_reads += 1;
+        self.log_access(addr, false, false, val as u16);
+        Ok(val)
+    }
+
+    fn write_byte(&mut self, addr: u16, val: u8) -> Result<(), CpuError> {
+        self.store(addr, val)?;
+        self.mem_stats.byte_writes += 1;
+        self.log_access(addr, true, false, val as u16);
+        Ok(())
+    }
+
+    /// The word stored as `bytes` (at addr, addr + 1) by loads, stores and
+    /// the stack. Instruction fetch always reads little-endian words, the
+    /// order sasm emits code in, so only data follows `--big-endian`.
+    fn data_word(&self, bytes: [u8; 2]) -> u16 {
+        if self.big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) }
+    }
+
+    fn data_bytes(&self, val: u16) -> [u8; 2] {
+        if self.big_endian { val.to_be_bytes() } else { val.to_le_bytes() }
+    }
+
+    /// Data word at `addr` in the machine's byte order, without logging
+    pub fn peek_word(&self, addr: u16) -> u16 {
+        self.data_word([self.read_memory(addr), self.read_memory(addr.wrapping_add(1))])
+    }
+
+    fn read_word(&mut self, addr: u16) -> Result<u16, CpuError> {
+        let bytes = [self.load(addr)?, self.load(addr.wrapping_add(1))?];
+        let val = self.data_word(bytes);
+        self.mem_stats.word_reads += 1;
+        self.log_access(addr, false, true, val);
+        Ok(val)
     }
 
-    fn write_word(&mut self, addr: u16, val: u16) -> Result<(), String> {
-        let bytes = val.to_le_bytes();
-        self.memory[addr as usize] = bytes[0];
-        self.memory[addr.wrapping_add(1) as usize] = bytes[1];
+    fn write_word(&mut self, addr: u16, val: u16) -> Result<(), CpuError> {
+        let bytes = self.data_bytes(val);
+        self.store(addr, bytes[0])?;
+        self.store(addr.wrapping_add(1), bytes[1])?;
+        self.mem_stats.word_writes += 1;
+        self.log_access(addr, true, true, val);
         Ok(())
     }
 
     // Port I/O
     fn port_read(&mut self, port: u8) -> u8 {
         match port {
-            0x80 => {
-                // ACIA status - always ready
-                0x02 // TX ready
-            }
-            0x81 => {
-                // ACIA data - nothing to read
-                0
-            }
-            _ => self.ports[port as usize],
+            p if p == self.acia_base => {
+                self.note_rx_poll();
+                self.acia_status()
+            }
+            p if p == self.acia_base.wrapping_add(1) => {
+                // ACIA data - next queued input byte
+                self.note_rx_poll();
+                self.serial_in.pop_front().unwrap_or(0)
+            }
+            DISCOVERY_PORT => self.devices.iter().fold(1, |bits, dev| bits | 1 << dev.id()),
+            p if is_discovery_port(p) => match p - DISCOVERY_PORT - 1 {
+                0 => ACIA_VERSION,
+                id => self.devices.iter().find(|dev| dev.id() == id).map_or(0, |dev| dev.version()),
+            },
+            _ => match self.devices.iter_mut().find(|dev| dev.owns(port)) {
+                Some(dev) => dev.read(port),
+                None => self.ports[port as usize],
+            },
         }
     }
 
     fn port_write(&mut self, port: u8, val: u8) {
+        if self.port_log.len() == PORT_LOG_LEN {
+            self.port_log.pop_front();
+        }
+        self.port_log.push_back((port, val));
+
         match port {
-            0x81 => {
-                // ACIA data - output character
-                self.serial_out.push(val);
-                if !self.quiet {
-                    print!("{}", val as char);
-                    io::stdout().flush().ok();
-                }
-            }
-            _ => {
-                self.ports[port as usize] = val;
+            p if p == self.acia_base => self.acia_ctrl = val,
+            p if p == self.acia_base.wrapping_add(1) => self.serial_send(val), // ACIA data - output character
+            p if is_discovery_port(p) => {} // Read-only
+            _ => match self.devices.iter_mut().find(|dev| dev.owns(port)) {
+                Some(dev) => dev.write(port, val),
+                None => self.ports[port as usize] = val,
+            },
+        }
+    }
+
+    fn note_rx_poll(&mut self) {
+        if self.serial_in.is_empty() {
+            self.rx_polls += 1;
+        }
+    }
+
+    fn serial_send(&mut self, val: u8) {
+        self.rx_polls = 0;
+        self.serial_out.push_back(val);
+        self.trim_serial_output();
+        if let Some(log) = &mut self.serial_log {
+            if let Err(e) = log.write_all(&[val]) {
+                eprintln!("Serial log write failed, logging stopped: {}", e);
+                self.serial_log = None;
             }
         }
+        if !self.quiet {
+            print!("{}", val as char);
+            io::stdout().flush().ok();
+        }
+    }
+
+    /// Carry out the BIOS service selected by A0 in place of a guest SWI handler
+    fn bios_call(&mut self) -> Result<(), CpuError> {
+        let service = self.get_reg(4);
+        match service {
+            BIOS_PUTCHAR => self.serial_send(self.get_reg(5) as u8),
+            BIOS_EXIT => self.halted = true,
+            BIOS_GETCHAR => {
+                self.note_rx_poll();
+                let c = self.serial_in.pop_front().map_or(0xFFFF, u16::from);
+                self.set_reg(4, c);
+            }
+            _ => return Err(CpuError::UnknownBiosService(service)),
+        }
+        Ok(())
     }
 
     // Flag operations
@@ -955,7 +1841,7 @@ impl Cpu {
     }
 
     fn set_flags_add(&mut self, a: u16, b: u16, result: u16, carry: bool) {
-        self.flags = 0;
+        self.flags &= FLAG_I; // Interrupt enable is not an arithmetic flag
         if result == 0 {
             self.flags |= FLAG_Z;
         }
@@ -975,7 +1861,7 @@ impl Cpu {
     }
 
     fn set_flags_sub(&mut self, a: u16, b: u16, result: u16, borrow: bool) {
-        self.flags = 0;
+        self.flags &= FLAG_I; // Interrupt enable is not an arithmetic flag
         if result == 0 {
             self.flags |= FLAG_Z;
         }
@@ -1063,13 +1949,24 @@ impl Cpu {
                 base + 3, self.regs[base + 3],
             );
         }
+        let other: Vec<String> = self.regs_alt.iter().map(|r| format!("{:04X}", r)).collect();
+        println!("  Bank: {} (other R4-R11: {})", if self.alt_bank { "alternate" } else { "main" }, other.join(" "));
 
         println!();
         println!("Cycles: {}", self.cycles);
+        let stats = self.branch_stats();
+        println!("Branches: {} taken, {} not taken; {} jumps", stats.taken, stats.not_taken, stats.jumps);
+        let mem = self.mem_stats();
+        println!("Memory: {} reads ({} byte, {} word), {} writes ({} byte, {} word)",
+            mem.reads(), mem.byte_reads, mem.word_reads, mem.writes(), mem.byte_writes, mem.word_writes);
 
         if !self.serial_out.is_empty() {
             println!();
-            println!("Serial output:");
+            if self.serial_dropped > 0 {
+                println!("Serial output (last {} bytes, {} dropped):", self.serial_out.len(), self.serial_dropped);
+            } else {
+                println!("Serial output:");
+            }
             let s: String = self.serial_out.iter().map(|&b| b as char).collect();
             println!("  \"{}\"", s.escape_default());
         }
@@ -1082,10 +1979,808 @@ impl Cpu {
             print!("{:04X}: ", a);
             for j in 0..16 {
                 if i + j < len {
-                    print!("{:02X} ", self.memory[(a.wrapping_add(j as u16)) as usize]);
+                    print!("{:02X} ", self.read_memory(a.wrapping_add(j as u16)));
                 }
             }
             println!();
         }
     }
 }
+
+fn is_discovery_port(port: u8) -> bool {
+    (DISCOVERY_PORT..=DISCOVERY_PORT + 1 + MAX_DEVICE_ID).contains(&port)
+}
+
+/// Machines for the tests of this and the other emulator modules
+#[cfg(test)]
+pub(crate) mod test_util {
+    use super::*;
+
+    /// A machine with `code` at RESET_PC and PC there
+    pub(crate) fn machine_with(config: CpuConfig, code: &[u16]) -> Cpu {
+        let mut cpu = Cpu::with_config(CpuConfig { seed: 0, ..config });
+        cpu.set_quiet(true);
+        poke(&mut cpu, RESET_PC, code);
+        cpu.set_pc(RESET_PC);
+        cpu
+    }
+
+    /// Write instruction words from `addr`, little-endian like fetch
+    pub(crate) fn poke(cpu: &mut Cpu, addr: u16, words: &[u16]) {
+        for (i, &word) in words.iter().enumerate() {
+            let [lo, hi] = word.to_le_bytes();
+            cpu.write_memory(addr + 2 * i as u16, lo);
+            cpu.write_memory(addr + 2 * i as u16 + 1, hi);
+        }
+    }
+
+    pub(crate) fn machine(code: &[u16]) -> Cpu {
+        machine_with(CpuConfig::default(), code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_util::{machine, machine_with, poke};
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn step_hook_sees_every_step() {
+        // ADDI R4, 1; ADDI R4, 2; HALT
+        let mut cpu = machine(&[0x5401, 0x5402, 0xE100]);
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let log = Rc::clone(&seen);
+        cpu.set_step_hook(Box::new(move |cpu, instr| {
+            log.borrow_mut().push((instr, cpu.get_register(4)));
+        }));
+        while cpu.step().unwrap() {}
+        assert_eq!(*seen.borrow(), [(0x5401, 1), (0x5402, 3), (0xE100, 3)]);
+    }
+
+    #[test]
+    fn faults_are_matchable_cpu_errors() {
+        // ADDI R4, 1 fills the last word of a 0x102-byte RAM; the next fetch runs off the end
+        let mut cpu = machine_with(CpuConfig { mem_size: 0x102, ..CpuConfig::default() }, &[0x5401]);
+        assert_eq!(cpu.step(), Ok(true));
+        assert_eq!(cpu.step(), Err(CpuError::PcOutOfBounds(0x0102)));
+        // Every 4-bit opcode decodes, so bad encodings surface as unknown funcs
+        let err = machine(&[0xB458]).step().unwrap_err();
+        assert!(matches!(err, CpuError::UnknownFunc { op: 0xB, func: 0x8 }));
+        assert_eq!(err.to_string(), "Unknown muldiv func: 0x8");
+        let err: Box<dyn std::error::Error> = Box::new(CpuError::UnknownOpcode(0x3));
+        assert_eq!(err.to_string(), "Unknown opcode: 0x3");
+    }
+
+    #[test]
+    fn port_writes_are_logged_newest_last() {
+        // ADDI R4, 0x41; OUT 0x81, R4; IN R6, 0x80; HALT
+        let mut cpu = machine(&[0x5441, 0xF04C, 0x0081, 0xF60B, 0x0080, 0xE100]);
+        while cpu.step().unwrap() {}
+        // Reads are not logged
+        assert_eq!(cpu.recent_port_writes().copied().collect::<Vec<_>>(), [(0x81, 0x41)]);
+
+        // 9x (ADDI R4, 1; OUT 0x30, R4); HALT
+        let mut code = Vec::new();
+        for _ in 0..9 {
+            code.extend([0x5401, 0xF04C, 0x0030]);
+        }
+        code.push(0xE100);
+        let mut cpu = machine(&code);
+        while cpu.step().unwrap() {}
+        // Only the last PORT_LOG_LEN writes are kept
+        let log: Vec<(u8, u8)> = cpu.recent_port_writes().copied().collect();
+        assert_eq!(log, (2..=9).map(|v| (0x30, v)).collect::<Vec<_>>());
+        assert_eq!(cpu.ports()[0x30], 9);
+        cpu.reset();
+        assert_eq!(cpu.recent_port_writes().count(), 0);
+    }
+
+    #[test]
+    fn serial_log_gets_every_output_byte() {
+        let path = std::env::temp_dir().join(format!("semu-{}-serial.log", std::process::id()));
+        // ADDI R4, 'h'; OUT 0x81, R4; ADDI R4, 1; OUT 0x81, R4; HALT
+        let code = [0x5468, 0xF04C, 0x0081, 0x5401, 0xF04C, 0x0081, 0xE100];
+        let mut cpu = machine(&code);
+        cpu.set_serial_log(File::create(&path).unwrap());
+        while cpu.step().unwrap() {}
+        cpu.flush_serial_log().unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"hi");
+
+        // A log that can't be written reports it when flushed
+        let mut cpu = machine(&code);
+        cpu.set_serial_log(File::open(&path).unwrap());
+        while cpu.step().unwrap() {}
+        assert!(cpu.flush_serial_log().is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn mem_log_records_loads_and_stores_in_order() {
+        // LIX R5, 0x0400; LIX R4, 0xABCD; SW (R5), R4; LW R6, (R5); LBU R7, (R5); HALT
+        let code = [0xF507, 0x0400, 0xF407, 0xABCD, 0x7450, 0x6650, 0x6752, 0xE100];
+        let mut cpu = machine(&code);
+        cpu.enable_mem_log(8);
+        while cpu.step().unwrap() {}
+        let access = |pc, write, word, value| MemAccess { pc, addr: 0x0400, write, word, value };
+        // Instruction fetches are not data accesses
+        assert_eq!(cpu.mem_log().copied().collect::<Vec<_>>(), [
+            access(0x0108, true, true, 0xABCD),
+            access(0x010A, false, true, 0xABCD),
+            access(0x010C, false, false, 0xCD),
+        ]);
+        cpu.enable_mem_log(1);
+        assert_eq!(cpu.mem_log().copied().collect::<Vec<_>>(), [access(0x010C, false, false, 0xCD)]);
+
+        // Off by default
+        let mut cpu = machine(&code);
+        while cpu.step().unwrap() {}
+        assert_eq!(cpu.mem_log().count(), 0);
+    }
+
+    #[test]
+    fn jr_and_jalr_jump_through_a_register() {
+        // JR R5
+        let mut cpu = machine(&[0xC05E]);
+        cpu.set_register(5, 0x0200);
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_pc(), 0x0200);
+
+        // JALR R1, R5 links the return address
+        let mut cpu = machine(&[0xC15E]);
+        cpu.set_register(5, 0x0200);
+        cpu.step().unwrap();
+        assert_eq!((cpu.get_pc(), cpu.get_register(1)), (0x0200, 0x0102));
+
+        // JALR R0, R5 discards the link rather than falling into J
+        let mut cpu = machine(&[0xC05E]);
+        cpu.set_register(5, 0x0300);
+        cpu.step().unwrap();
+        assert_eq!((cpu.get_pc(), cpu.get_register(0)), (0x0300, 0));
+    }
+
+    #[test]
+    fn reti_restores_the_flags_an_isr_changed() {
+        // LIX R2, 0x8000; SUB R4, R0, R0; SWI 0x40
+        let mut cpu = machine(&[0xF207, 0x8000, 0x1400, 0xE540]);
+        // ADDI R4, 1 (clears Z); RETI
+        poke(&mut cpu, 0x0080, &[0x5401, 0xE400]);
+        for _ in 0..3 {
+            cpu.step().unwrap();
+        }
+        let flags = cpu.get_flags();
+        assert_ne!(flags & FLAG_Z, 0);
+        assert_eq!(cpu.get_pc(), 0x0080);
+        assert_eq!(cpu.get_sp(), 0x7FFC);
+        assert_eq!((cpu.peek_word(0x7FFC), cpu.peek_word(0x7FFE)), (0x0108, flags as u16));
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_flags() & FLAG_Z, 0);
+        cpu.step().unwrap();
+        assert_eq!((cpu.get_pc(), cpu.get_sp(), cpu.get_flags()), (0x0108, 0x8000, flags));
+    }
+
+    #[test]
+    fn trap_stops_under_a_debugger_and_vectors_without_one() {
+        // LIX R2, 0x8000; TRAP 0
+        let code = [0xF207, 0x8000, 0xEC00];
+        let mut cpu = machine(&code);
+        cpu.set_trap_stop(true);
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.take_trap(), Some((0, 0x0104)));
+        assert_eq!((cpu.get_pc(), cpu.get_sp()), (0x0106, 0x8000));
+
+        let mut cpu = machine(&code);
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.take_trap(), None);
+        assert_eq!((cpu.get_pc(), cpu.get_sp()), (TRAP_VECTOR, 0x7FFC));
+        assert_eq!(cpu.peek_word(0x7FFC), 0x0106);
+        assert_ne!(TRAP_VECTOR, IRQ_VECTOR);
+    }
+
+    #[test]
+    fn cas_swaps_when_the_word_matches() {
+        // CAS R4, (R5), R6
+        let mut cpu = machine(&[0xF458, 0x0006]);
+        cpu.set_register(4, 0x1111);
+        cpu.set_register(5, 0x0400);
+        cpu.set_register(6, 0x2222);
+        cpu.write_memory(0x0400, 0x11);
+        cpu.write_memory(0x0401, 0x11);
+        cpu.step().unwrap();
+        assert_eq!(cpu.peek_word(0x0400), 0x2222);
+        assert_eq!(cpu.get_register(4), 0x1111);
+        assert_ne!(cpu.get_flags() & FLAG_Z, 0);
+    }
+
+    #[test]
+    fn cas_loads_the_word_when_it_differs() {
+        // SUB R0, R0, R0 (sets Z, for CAS to clear); CAS R4, (R5), R6
+        let mut cpu = machine(&[0x1000, 0xF458, 0x0006]);
+        cpu.set_register(4, 0x1111);
+        cpu.set_register(5, 0x0400);
+        cpu.set_register(6, 0x2222);
+        cpu.write_memory(0x0400, 0x33);
+        cpu.write_memory(0x0401, 0x33);
+        cpu.step().unwrap();
+        assert_ne!(cpu.get_flags() & FLAG_Z, 0);
+        cpu.step().unwrap();
+        assert_eq!(cpu.peek_word(0x0400), 0x3333);
+        assert_eq!(cpu.get_register(4), 0x3333);
+        assert_eq!(cpu.get_flags() & FLAG_Z, 0);
+    }
+
+    #[test]
+    fn data_words_round_trip_in_either_byte_order() {
+        // LIX R4, 0x1234; SW (R5), R4; LW R6, (R5). The code itself is
+        // little-endian in both runs: only data words change order.
+        for (big_endian, stored) in [(false, [0x34, 0x12]), (true, [0x12, 0x34])] {
+            let config = CpuConfig { big_endian, ..CpuConfig::default() };
+            let mut cpu = machine_with(config, &[0xF407, 0x1234, 0x7450, 0x6650]);
+            cpu.set_register(5, 0x0400);
+            for _ in 0..3 {
+                cpu.step().unwrap();
+            }
+            assert_eq!(cpu.get_register(4), 0x1234, "big_endian: {}", big_endian);
+            assert_eq!([cpu.read_memory(0x0400), cpu.read_memory(0x0401)], stored);
+            assert_eq!(cpu.get_register(6), 0x1234, "big_endian: {}", big_endian);
+            assert_eq!(cpu.get_pc(), 0x0108);
+        }
+    }
+
+    #[test]
+    fn saturating_ops_clamp_and_flag_it() {
+        // op R4, R5 for ADDS, SUBS, ADDSU, SUBSU: extended sub 0xB, op in imm16[15:8]
+        let saturate = |op: u16, a: u16, b: u16| {
+            let mut cpu = machine(&[0xF45B, op << 8]);
+            cpu.set_register(4, a);
+            cpu.set_register(5, b);
+            cpu.step().unwrap();
+            (cpu.get_register(4), cpu.get_flags() & (FLAG_V | FLAG_C))
+        };
+        assert_eq!(saturate(0x01, 0x7FFF, 1), (0x7FFF, FLAG_V));
+        assert_eq!(saturate(0x01, 0x8000, 0xFFFF), (0x8000, FLAG_V));
+        assert_eq!(saturate(0x01, 0x7FFE, 1), (0x7FFF, 0));
+        assert_eq!(saturate(0x02, 0x8000, 1), (0x8000, FLAG_V));
+        assert_eq!(saturate(0x02, 0x7FFF, 0xFFFF), (0x7FFF, FLAG_V));
+        assert_eq!(saturate(0x03, 0xFFFF, 1), (0xFFFF, FLAG_C));
+        assert_eq!(saturate(0x03, 0xFFFE, 1), (0xFFFF, 0));
+        assert_eq!(saturate(0x04, 0, 1), (0, FLAG_C));
+
+        // The muldiv funcs they once borrowed are free again, and INX still works
+        assert_eq!(machine(&[0xB458]).step(), Err(CpuError::UnknownFunc { op: 0xB, func: 0x8 }));
+        assert_eq!(machine(&[0xF45B, 0xFF00]).step(), Err(CpuError::UnknownFunc { op: 0xF, func: 0xFF0B }));
+        let mut cpu = machine(&[0xF40B, 0x0081]);
+        cpu.send_key(b'x');
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_register(4), b'x' as u16);
+    }
+
+    #[test]
+    fn bit_ops_set_clear_and_test_one_bit() {
+        // BSET R4, 3; BTST R4, 3; BCLR R4, 3; BTST R4, 3
+        let mut cpu = machine(&[0xF40B, 0x0503, 0xF40B, 0x0703, 0xF40B, 0x0603, 0xF40B, 0x0703]);
+        cpu.set_register(4, 0x8001);
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_register(4), 0x8009);
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_flags() & FLAG_Z, 0);
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_register(4), 0x8001);
+        cpu.step().unwrap();
+        assert_ne!(cpu.get_flags() & FLAG_Z, 0);
+        assert_eq!(cpu.get_register(4), 0x8001);
+
+        // Muldiv funcs 0xC-0xE are free again
+        assert_eq!(machine(&[0xB43C]).step(), Err(CpuError::UnknownFunc { op: 0xB, func: 0xC }));
+    }
+
+    #[test]
+    fn cskip_steps_over_short_and_long_instructions() {
+        // SUB R0, R0, R0; CSKIP EQ; ADDI R4, 1; CSKIP EQ; LIX R4, 0x1234;
+        // ADDI R5, 1; CSKIP EQ; ADDI R6, 1
+        let mut cpu = machine(&[0x1000, 0xEA00, 0x5401, 0xEA00, 0xF407, 0x1234, 0x5501, 0xEA00, 0x5601]);
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_pc(), 0x0106);
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_pc(), 0x010C);
+        // ADDI clears Z, so the last CSKIP falls through
+        for _ in 0..3 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.get_pc(), 0x0112);
+        assert_eq!([4, 5, 6].map(|r| cpu.get_register(r)), [0, 1, 1]);
+    }
+
+    #[test]
+    fn auto_increment_and_decrement_update_the_pointer() {
+        // LW R4, (R5)+; SW -(R6), R4; LW R7, -(R5)
+        let mut cpu = machine(&[0x6459, 0x7468, 0x675A]);
+        cpu.set_register(5, 0x0400);
+        cpu.set_register(6, 0x0500);
+        poke(&mut cpu, 0x0400, &[0xBEEF]);
+        cpu.step().unwrap();
+        assert_eq!((cpu.get_register(4), cpu.get_register(5)), (0xBEEF, 0x0402));
+        cpu.step().unwrap();
+        assert_eq!((cpu.peek_word(0x04FE), cpu.get_register(6)), (0xBEEF, 0x04FE));
+        cpu.step().unwrap();
+        assert_eq!((cpu.get_register(7), cpu.get_register(5)), (0xBEEF, 0x0400));
+
+        // LW R5, (R5)+ keeps the loaded word; SW -(R4), R4 stores R4 from
+        // before the decrement
+        let mut cpu = machine(&[0x6559, 0x7448]);
+        cpu.set_register(4, 0x0500);
+        cpu.set_register(5, 0x0400);
+        poke(&mut cpu, 0x0400, &[0xBEEF]);
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_register(5), 0xBEEF);
+        cpu.step().unwrap();
+        assert_eq!((cpu.peek_word(0x04FE), cpu.get_register(4)), (0x0500, 0x04FE));
+    }
+
+    #[test]
+    fn mov_from_pc_reads_the_instructions_own_address() {
+        // NOP; MOV R4, PC
+        let mut cpu = machine(&[0xE000, 0xC40F]);
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_register(4), 0x0102);
+    }
+
+    #[test]
+    fn rdcyc_counts_the_cycles_in_between() {
+        // NOP; NOP; RDCYC R4, 0; RDCYC R5, 1; NOP; RDCYC R6, 0
+        let mut cpu = machine(&[0xE000, 0xE000, 0xEB40, 0xEB51, 0xE000, 0xEB60]);
+        for _ in 0..6 {
+            cpu.step().unwrap();
+        }
+        // Each of these costs one cycle, and word 0 latches the count
+        // before its own instruction
+        assert_eq!([4, 5, 6].map(|r| cpu.get_register(r)), [2, 0, 5]);
+        assert_eq!(cpu.get_cycles(), 6);
+    }
+
+    #[test]
+    fn memory_past_mem_size_is_open_bus_or_a_fault() {
+        // LW R4, (R5); SW (R5), R6; LW R7, (R5)
+        let code = [0x6450, 0x7650, 0x6750];
+        let small = CpuConfig { mem_size: 0x1000, ..CpuConfig::default() };
+        let mut cpu = machine_with(small, &code);
+        cpu.set_register(5, 0x2000);
+        cpu.set_register(6, 0x1234);
+        for _ in 0..3 {
+            cpu.step().unwrap();
+        }
+        assert_eq!((cpu.get_register(4), cpu.get_register(7)), (0xFFFF, 0xFFFF));
+
+        let trapping = CpuConfig { trap_unmapped: true, ..small };
+        let mut cpu = machine_with(trapping, &code);
+        cpu.set_register(5, 0x2000);
+        assert_eq!(cpu.step(), Err(CpuError::MemoryFault { addr: 0x2000 }));
+        cpu.set_pc(0x0102);
+        assert_eq!(cpu.step(), Err(CpuError::MemoryFault { addr: 0x2000 }));
+    }
+
+    #[test]
+    fn exec_guard_faults_on_fetching_data() {
+        // LIX R5, 0x0200; LIX R4, 0xE100 (HALT); SW (R5), R4; JR R5
+        let code = [0xF507, 0x0200, 0xF407, 0xE100, 0x7450, 0xC05E];
+        let guarded = CpuConfig { exec_guard: true, ..CpuConfig::default() };
+        let mut cpu = machine_with(guarded, &code);
+        for _ in 0..4 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.step(), Err(CpuError::DataExecuted { addr: 0x0200 }));
+
+        // Without the guard the stored HALT just runs
+        let mut cpu = machine(&code);
+        for _ in 0..4 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.step(), Ok(false));
+
+        // A region marked up front, like a `.db` table passed to --data
+        let mut cpu = machine_with(guarded, &[0x907F]); // J 0x0200
+        cpu.mark_data(0x0200, 2);
+        cpu.step().unwrap();
+        assert_eq!(cpu.step(), Err(CpuError::DataExecuted { addr: 0x0200 }));
+    }
+
+    #[test]
+    fn restore_rewinds_registers_memory_and_devices() {
+        // ADDI R4, 1; SW (R5), R4; OUTI 0x91, R4 (timer reload);
+        // OUTI 0x90, R4 (timer on); OUTI 0x81, R4 (serial)
+        let mut cpu = machine(&[0x5401, 0x7450, 0xF04C, 0x0091, 0xF04C, 0x0090, 0xF04C, 0x0081]);
+        cpu.set_register(5, 0x0400);
+        let timer = |cpu: &Cpu| cpu.devices().find(|dev| dev.name() == "timer").unwrap().status();
+        let before = (timer(&cpu), cpu.snapshot());
+
+        for _ in 0..5 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.get_register(4), 1);
+        assert_eq!(cpu.peek_word(0x0400), 1);
+        assert_ne!(timer(&cpu), before.0);
+        assert_eq!(cpu.get_serial_output().collect::<Vec<_>>(), [1]);
+
+        // Twice, since the snapshot is kept
+        for _ in 0..2 {
+            cpu.restore(&before.1);
+            assert_eq!((cpu.get_pc(), cpu.get_register(4), cpu.get_cycles()), (0x0100, 0, 0));
+            assert_eq!(cpu.peek_word(0x0400), 0);
+            assert_eq!(timer(&cpu), before.0);
+            assert_eq!(cpu.get_serial_output().count(), 0);
+            cpu.step().unwrap();
+        }
+    }
+
+    #[test]
+    fn poison_faults_on_reads_of_unwritten_memory() {
+        let poison = CpuConfig { poison: true, ..CpuConfig::default() };
+        // LW R4, (R5)
+        let mut cpu = machine_with(poison, &[0x6450]);
+        cpu.set_register(5, 0x0400);
+        assert_eq!(cpu.step(), Err(CpuError::UninitializedRead { addr: 0x0400 }));
+
+        // SW (R5), R4; LW R6, (R5)
+        let mut cpu = machine_with(poison, &[0x7450, 0x6650]);
+        cpu.set_register(4, 0x1234);
+        cpu.set_register(5, 0x0400);
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_register(6), 0x1234);
+    }
+
+    #[test]
+    fn serial_buffer_keeps_the_newest_bytes_up_to_its_limit() {
+        // loop: ADDI R4, 1; OUTI 0x81, R4; J loop
+        let mut cpu = machine(&[0x5401, 0xF04C, 0x0081, 0x9FFC]);
+        cpu.set_serial_buffer_limit(3);
+        for _ in 0..5 * 3 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.get_serial_output().collect::<Vec<_>>(), [3, 4, 5]);
+        cpu.set_serial_buffer_limit(1);
+        assert_eq!(cpu.get_serial_output().collect::<Vec<_>>(), [5]);
+    }
+
+    /// A device at port 0xB0 that reads back the complement of its last write
+    #[derive(Clone)]
+    struct Inverter(u8);
+
+    impl IoDevice for Inverter {
+        fn name(&self) -> &'static str {
+            "inverter"
+        }
+
+        fn id(&self) -> u8 {
+            5
+        }
+
+        fn owns(&self, port: u8) -> bool {
+            port == 0xB0
+        }
+
+        fn read(&mut self, _port: u8) -> u8 {
+            !self.0
+        }
+
+        fn write(&mut self, _port: u8, val: u8) {
+            self.0 = val;
+        }
+
+        fn reset(&mut self) {
+            self.0 = 0;
+        }
+
+        fn status(&self) -> String {
+            format!("holds 0x{:02X}", self.0)
+        }
+
+        fn snapshot(&self) -> Box<dyn IoDevice> {
+            Box::new(self.clone())
+        }
+    }
+
+    #[test]
+    fn detached_device_ports_revert_to_latches() {
+        // OUTI 0xB0, R4; INI R5, 0xB0; INI R6, 0xB0
+        let mut cpu = machine(&[0xF04C, 0x00B0, 0xF50B, 0x00B0, 0xF60B, 0x00B0]);
+        cpu.attach_device(Box::new(Inverter(0))).unwrap();
+        assert!(cpu.attach_device(Box::new(Inverter(0))).is_err());
+        cpu.set_register(4, 0x0F);
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_register(5), 0xF0);
+
+        assert_eq!(cpu.detach_device("inverter").unwrap().status(), "holds 0x0F");
+        assert!(cpu.devices().all(|dev| dev.name() != "inverter"));
+        // The write went to the device, so the latch is still clear
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_register(6), 0x00);
+    }
+
+    #[test]
+    fn discovery_ports_track_attached_devices() {
+        let mut cpu = machine(&[]);
+        // ACIA, timer and RNG are there from power-on
+        assert_eq!(cpu.port_read(DISCOVERY_PORT), 0b0000_0111);
+        cpu.attach_device(Box::new(Inverter(0))).unwrap();
+        assert_eq!(cpu.port_read(DISCOVERY_PORT), 0b0010_0111);
+        assert_eq!(cpu.port_read(DISCOVERY_PORT + 1 + 5), 1);
+        cpu.detach_device("rng").unwrap();
+        assert_eq!(cpu.port_read(DISCOVERY_PORT), 0b0010_0011);
+        assert_eq!(cpu.port_read(DISCOVERY_PORT + 1 + 2), 0);
+        // Writes are dropped rather than latched
+        cpu.port_write(DISCOVERY_PORT, 0xFF);
+        assert_eq!(cpu.port_read(DISCOVERY_PORT), 0b0010_0011);
+        assert_eq!(cpu.ports()[DISCOVERY_PORT as usize], 0);
+    }
+
+    #[test]
+    fn odd_sp_faults_only_in_strict_mode() {
+        // PUSH R4; POP R5
+        let strict = CpuConfig { strict_alignment: true, ..CpuConfig::default() };
+        let mut cpu = machine_with(strict, &[0xC040, 0xC501]);
+        cpu.set_register(2, 0x7FFF);
+        assert_eq!(cpu.step(), Err(CpuError::MisalignedStack { sp: 0x7FFF }));
+        assert_eq!(cpu.get_sp(), 0x7FFF);
+
+        let mut cpu = machine(&[0xC040, 0xC501]);
+        cpu.set_register(2, 0x7FFF);
+        cpu.set_register(4, 0x1234);
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!((cpu.get_register(5), cpu.get_sp()), (0x1234, 0x7FFF));
+    }
+
+    #[test]
+    fn bios_serves_swi_with_an_empty_vector() {
+        // SWI 0x7C (putchar); ADDI R4, 1; SWI 0x7C (exit)
+        let bios = CpuConfig { bios: true, ..CpuConfig::default() };
+        let mut cpu = machine_with(bios, &[0xE57C, 0x5401, 0xE57C]);
+        cpu.set_register(4, BIOS_PUTCHAR);
+        cpu.set_register(5, b'A' as u16);
+        assert_eq!(cpu.step(), Ok(true));
+        assert_eq!(cpu.get_serial_output().collect::<Vec<_>>(), b"A");
+        assert_eq!(cpu.step(), Ok(true));
+        assert_eq!(cpu.step(), Ok(false));
+        assert!(cpu.is_halted());
+
+        // Service 3 does not exist
+        let mut cpu = machine_with(bios, &[0xE57C]);
+        cpu.set_register(4, 3);
+        assert_eq!(cpu.step(), Err(CpuError::UnknownBiosService(3)));
+
+        // Other SWIs still go through their vector (SWI 3 to 0x0006)
+        let mut cpu = machine_with(bios, &[0xE503]);
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_pc(), 0x0006);
+
+        // A handler in the SWI 0x7C vector (here J 0x0100) wins over the BIOS
+        let mut cpu = machine_with(bios, &[0xE57C]);
+        poke(&mut cpu, 0x00F8, &[0x9003]);
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_pc(), 0x00F8);
+        assert_eq!(cpu.get_serial_output().count(), 0);
+    }
+
+    #[test]
+    fn bios_putchar_ignores_filled_reset_and_irq_vectors() {
+        // SWI 0x7C (putchar); ADDI R4, 2; SWI 0x7C (getchar)
+        let bios = CpuConfig { bios: true, ..CpuConfig::default() };
+        let mut cpu = machine_with(bios, &[0xE57C, 0x5402, 0xE57C]);
+        // J 0x0100 at the reset vector, J 0x0100 at the IRQ vector
+        poke(&mut cpu, 0x0000, &[0x907F]);
+        poke(&mut cpu, 0x0004, &[0x907D]);
+        cpu.set_register(4, BIOS_PUTCHAR);
+        cpu.set_register(5, b'A' as u16);
+        cpu.send_key(b'x');
+        for _ in 0..3 {
+            assert_eq!(cpu.step(), Ok(true));
+        }
+        assert_eq!(cpu.get_serial_output().collect::<Vec<_>>(), b"A");
+        assert_eq!((cpu.get_register(4), cpu.get_sp()), (b'x' as u16, cpu.stack_top()));
+    }
+
+    #[test]
+    fn mem_stats_count_data_accesses() {
+        // LW R4, (R5); SW (R5), R4; LBU R6, (R5); SB (R5), R6; PUSH R4; POP R7
+        let mut cpu = machine(&[0x6450, 0x7450, 0x6652, 0x7651, 0xC040, 0xC701]);
+        cpu.set_register(5, 0x0400);
+        for _ in 0..6 {
+            cpu.step().unwrap();
+        }
+        let expected = MemStats { byte_reads: 1, word_reads: 2, byte_writes: 1, word_writes: 2 };
+        assert_eq!(cpu.mem_stats(), expected);
+        assert_eq!((expected.reads(), expected.writes()), (3, 3));
+    }
+
+    #[test]
+    fn exx_toggles_the_reported_bank() {
+        // EXX; EXX
+        let mut cpu = machine(&[0xC00B, 0xC00B]);
+        cpu.set_register(4, 0x1111);
+        assert!(!cpu.alt_bank_active());
+        cpu.step().unwrap();
+        assert!(cpu.alt_bank_active());
+        assert_eq!((cpu.get_register(4), cpu.get_alt_register(4)), (0, 0x1111));
+        cpu.step().unwrap();
+        assert!(!cpu.alt_bank_active());
+        assert_eq!((cpu.get_register(4), cpu.get_alt_register(4)), (0x1111, 0));
+    }
+
+    #[test]
+    fn relocated_acia_serves_its_new_ports() {
+        // OUTI 0x51, R4; INI R5, 0x51; INI R6, 0x50; OUTI 0x81, R4
+        let config = CpuConfig { acia_base: 0x50, ..CpuConfig::default() };
+        let mut cpu = machine_with(config, &[0xF04C, 0x0051, 0xF50B, 0x0051, 0xF60B, 0x0050, 0xF04C, 0x0081]);
+        assert_eq!(cpu.acia_ports(), [0x50, 0x51]);
+        cpu.set_register(4, b'A' as u16);
+        cpu.send_key(b'x');
+        for _ in 0..4 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.get_register(5), b'x' as u16);
+        assert_ne!(cpu.get_register(6) & 0x02, 0); // TX ready
+        // 0x81 is a plain port now, so only the first write was sent
+        assert_eq!(cpu.get_serial_output().collect::<Vec<_>>(), b"A");
+        assert_eq!(cpu.ports()[0x81], b'A');
+    }
+
+    #[test]
+    fn addi_flags_at_the_signed_boundary() {
+        let addi = |start: u16, imm8: u16| {
+            let mut cpu = machine(&[0x5400 | imm8]);
+            cpu.set_register(4, start);
+            cpu.step().unwrap();
+            (cpu.get_register(4), cpu.get_flags() & (FLAG_N | FLAG_Z | FLAG_C | FLAG_V))
+        };
+        // ADDI R4, 1 / ADDI R4, -1
+        assert_eq!(addi(0x7FFF, 0x01), (0x8000, FLAG_N | FLAG_V));
+        assert_eq!(addi(0x8000, 0xFF), (0x7FFF, FLAG_C | FLAG_V));
+        assert_eq!(addi(5, 0xFF), (4, FLAG_C));
+        assert_eq!(addi(0, 0xFF), (0xFFFF, FLAG_N));
+    }
+
+    #[test]
+    fn rx_polls_count_empty_reads_until_input_arrives() {
+        // INI R5, 0x81; INI R5, 0x80; INI R5, 0x81
+        let mut cpu = machine(&[0xF50B, 0x0081, 0xF50B, 0x0080, 0xF50B, 0x0081]);
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.rx_polls(), 2);
+        cpu.send_key(b'x');
+        assert_eq!(cpu.rx_polls(), 0);
+        cpu.step().unwrap();
+        assert_eq!((cpu.rx_polls(), cpu.get_register(5)), (0, b'x' as u16));
+    }
+
+    /// examples/timer.s, assembled, counting `ticks` timer IRQs before HALT
+    fn timer_demo(ticks: u16) -> Cpu {
+        let mut cpu = machine(&[
+            0xF407, 0x0000, 0xF507, 0x0064, 0xF05C, 0x0091, 0xF507, 0x0000, // start:
+            0xF05C, 0x0092, 0xF507, 0x0003, 0xF05C, 0x0090, 0xF607, ticks,
+            0xE300, 0xC462, 0x81FE, 0xE200, 0xE100, // EI; wait: CMP R4, R6; BNE wait; DI; HALT
+            0xC070, 0xC70C, 0x5401, 0xF50B, 0x0090, 0xC07D, 0xC701, 0xE400, // isr:
+        ]);
+        // J start; J isr
+        poke(&mut cpu, 0x0000, &[0x907F]);
+        poke(&mut cpu, 0x0004, &[0x9092]);
+        cpu
+    }
+
+    #[test]
+    fn acia_rx_interrupt_fires_when_enabled_and_input_arrives() {
+        // ADDI R4, -128; OUT 0x80, R4 (RX interrupts on); EI; wait: J wait
+        let mut cpu = machine(&[0x5480, 0xF04C, 0x0080, 0xE300, 0x9FFF]);
+        // isr: IN R5, 0x81; HALT
+        poke(&mut cpu, 0x0004, &[0xF50B, 0x0081, 0xE100]);
+        for _ in 0..10 {
+            assert!(cpu.step().unwrap());
+        }
+        assert!(!cpu.irq_pending());
+        cpu.send_key(b'k');
+        assert!(cpu.irq_pending());
+        assert_eq!(cpu.acia_status() & ACIA_IRQ, ACIA_IRQ);
+        let mut steps = 0;
+        while cpu.step().unwrap() {
+            steps += 1;
+            assert!(steps < 10, "the RX interrupt was not taken");
+        }
+        assert_eq!(cpu.get_register(5), b'k' as u16);
+        // Reading the byte empties the queue and drops the request
+        assert!(!cpu.irq_pending());
+
+        // With the control bit clear the key just waits to be polled
+        let mut cpu = machine(&[0xE300, 0x9FFF]);
+        cpu.send_key(b'k');
+        for _ in 0..10 {
+            assert!(cpu.step().unwrap());
+        }
+        assert!(!cpu.irq_pending());
+        assert_eq!(cpu.acia_status(), ACIA_TX_READY | ACIA_RX_READY);
+    }
+
+    #[test]
+    fn timer_isr_counts_each_tick() {
+        for ticks in [1, 5] {
+            let mut cpu = timer_demo(ticks);
+            let mut steps = 0;
+            while cpu.step().unwrap() {
+                steps += 1;
+                assert!(steps < 10_000, "no HALT after {} ticks", cpu.get_register(4));
+            }
+            assert_eq!(cpu.get_register(4), ticks);
+            // Each tick is 100 cycles after the timer was started
+            assert!(cpu.get_cycles() >= 100 * ticks as u64);
+            assert_eq!(cpu.get_flags() & FLAG_I, 0);
+        }
+    }
+
+    #[test]
+    fn add_and_sub_keep_interrupts_enabled() {
+        // EI; ADD R4, R5, R6; SUB R4, R5, R6; ADDIX R4, R5, 1; SUBIX R4, R5, 1
+        let mut cpu = machine(&[0xE300, 0x0456, 0x1456, 0xF450, 0x0001, 0xF451, 0x0001]);
+        cpu.set_register(5, 0x7FFF);
+        cpu.set_register(6, 0x7FFF);
+        cpu.step().unwrap();
+        for _ in 0..4 {
+            cpu.step().unwrap();
+            assert_ne!(cpu.get_flags() & FLAG_I, 0);
+        }
+        // The arithmetic flags still changed: SUBIX 0x7FFF - 1 clears Z and sets C
+        assert_eq!(cpu.get_flags() & (FLAG_Z | FLAG_C), FLAG_C);
+    }
+
+    #[test]
+    fn reset_keeps_the_program_and_clears_the_machine() {
+        // LIX R4, 0x1234; ADDI R5, 1; SW (R5), R4
+        let code = [0xF407, 0x1234, 0x5501, 0x7450];
+        let mut cpu = machine(&code);
+        for _ in 0..3 {
+            cpu.step().unwrap();
+        }
+        let program: Vec<u8> = (0..8).map(|i| cpu.read_memory(RESET_PC + i)).collect();
+        cpu.reset();
+        assert_eq!((0..8).map(|i| cpu.read_memory(RESET_PC + i)).collect::<Vec<_>>(), program);
+        assert_eq!((cpu.get_register(4), cpu.get_register(5)), (0, 0));
+        assert_eq!((cpu.get_pc(), cpu.get_sp(), cpu.get_flags()), (RESET_PC, cpu.stack_top(), 0));
+        assert_eq!((cpu.get_cycles(), cpu.is_halted()), (0, false));
+        // It runs again from the top
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_register(4), 0x1234);
+    }
+
+    #[test]
+    fn branch_stats_count_a_loops_back_branch() {
+        // LIX R4, 5; loop: ADDI R4, -1; BNE loop; HALT
+        let mut cpu = machine(&[0xF407, 0x0005, 0x54FF, 0x81FE, 0xE100]);
+        while cpu.step().unwrap() {}
+        assert_eq!(cpu.branch_stats(), BranchStats { taken: 4, not_taken: 1, jumps: 0 });
+        // LIX 1 + 5 loops of 2, plus the penalty of each taken branch
+        assert_eq!(cpu.get_cycles(), 1 + 5 * 2 + 1 + 4 * BRANCH_PENALTY);
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_serial_output() {
+        // 8 x (INI R4, 0xA0; OUTI 0x81, R4): echo random bytes
+        let run = |seed: u64| {
+            let mut cpu = Cpu::with_config(CpuConfig { seed, ..CpuConfig::default() });
+            cpu.set_quiet(true);
+            poke(&mut cpu, RESET_PC, &[0xF40B, 0x00A0, 0xF04C, 0x0081].repeat(8));
+            cpu.set_pc(RESET_PC);
+            for _ in 0..16 {
+                cpu.step().unwrap();
+            }
+            cpu.get_serial_output().collect::<Vec<_>>()
+        };
+        assert_eq!(run(42).len(), 8);
+        assert_eq!(run(42), run(42));
+        assert_ne!(run(42), run(43));
+    }
+}
@@ -0,0 +1,59 @@
+//! Port-mapped peripherals
+//!
+//! Devices other than the built-in ACIA implement `IoDevice` and can be
+//! attached, detached and reset while the CPU runs. Ports no device claims
+//! behave as plain latches. Guest code finds attached devices through the
+//! read-only discovery ports (see `Cpu::port_read`).
+
+use crate::rng::{self, Rng};
+use crate::timer::Timer;
+
+pub trait IoDevice {
+    /// Short name used to attach, detach and reset the device
+    fn name(&self) -> &'static str;
+
+    /// Discovery ID, 1-7: the device's bit in the present bitmap (bit 0 is
+    /// the ACIA)
+    fn id(&self) -> u8;
+
+    /// Version reported by the discovery ports
+    fn version(&self) -> u8 {
+        1
+    }
+
+    /// Whether `port` belongs to the device
+    fn owns(&self, port: u8) -> bool;
+
+    fn read(&mut self, port: u8) -> u8;
+
+    fn write(&mut self, port: u8, val: u8);
+
+    /// Return to the power-on state
+    fn reset(&mut self);
+
+    /// Advance by `cycles`
+    fn tick(&mut self, _cycles: u64) {}
+
+    /// Interrupt line
+    fn irq(&self) -> bool {
+        false
+    }
+
+    /// One-line state summary for the debugger
+    fn status(&self) -> String;
+
+    /// A copy in the current state, kept by `Cpu::snapshot`
+    fn snapshot(&self) -> Box<dyn IoDevice>;
+}
+
+/// Names accepted by `create`
+pub const DEVICE_NAMES: &[&str] = &["timer", "rng"];
+
+/// Build a device by name, in its power-on state
+pub fn create(name: &str) -> Option<Box<dyn IoDevice>> {
+    match name {
+        "timer" => Some(Box::new(Timer::new())),
+        "rng" => Some(Box::new(Rng::new(rng::host_seed()))),
+        _ => None,
+    }
+}
@@ -0,0 +1,170 @@
+//! Disassembler shared by the TUI and the interactive prompt
+
+use crate::cpu::Cpu;
+
+/// Branch and CSKIP condition names by code
+const CONDITIONS: [&str; 16] = [
+    "EQ", "NE", "LT", "GE", "LTU", "GEU", "MI", "PL",
+    "VS", "VC", "CS", "CC", "GT", "LE", "HI", "LS",
+];
+
+/// Disassemble a single Sampo instruction
+pub fn disassemble(cpu: &Cpu, addr: u16) -> (String, u16) {
+    let lo = cpu.read_memory(addr);
+    let hi = cpu.read_memory(addr.wrapping_add(1));
+    let instr = u16::from_le_bytes([lo, hi]);
+
+    let opcode = (instr >> 12) & 0xF;
+    let rd = (instr >> 8) & 0xF;
+    let rs1 = (instr >> 4) & 0xF;
+    let rs2 = instr & 0xF;
+    let imm8 = (instr & 0xFF) as i8;
+    let func = instr & 0xF;
+
+    let (mnemonic, size) = match opcode {
+        0x0 => (format!("ADD R{}, R{}, R{}", rd, rs1, rs2), 2),
+        0x1 => (format!("SUB R{}, R{}, R{}", rd, rs1, rs2), 2),
+        0x2 => (format!("AND R{}, R{}, R{}", rd, rs1, rs2), 2),
+        0x3 => (format!("OR R{}, R{}, R{}", rd, rs1, rs2), 2),
+        0x4 => (format!("XOR R{}, R{}, R{}", rd, rs1, rs2), 2),
+        0x5 => (format!("ADDI R{}, {}", rd, imm8), 2),
+        0x6 => {
+            match func {
+                0x0 => (format!("LW R{}, (R{})", rd, rs1), 2),
+                0x1 => (format!("LB R{}, (R{})", rd, rs1), 2),
+                0x2 => (format!("LBU R{}, (R{})", rd, rs1), 2),
+                0x8 => (format!("LUI R{}, 0x{:02X}", rd, rs1), 2),
+                0x9 => (format!("LW R{}, (R{})+", rd, rs1), 2),
+                0xA => (format!("LW R{}, -(R{})", rd, rs1), 2),
+                _ => (format!("LOAD R{}, (R{}) f={}", rd, rs1, func), 2),
+            }
+        }
+        0x7 => {
+            match func {
+                0x0 => (format!("SW R{}, (R{})", rd, rs1), 2),
+                0x1 => (format!("SB R{}, (R{})", rd, rs1), 2),
+                0x7 => (format!("SW (R{})+, R{}", rs1, rd), 2),
+                0x8 => (format!("SW -(R{}), R{}", rs1, rd), 2),
+                _ => (format!("STORE R{}, (R{}) f={}", rd, rs1, func), 2),
+            }
+        }
+        0x8 => {
+            (format!("B{} {:+}", CONDITIONS[rd as usize], imm8 * 2), 2)
+        }
+        0x9 => {
+            let offset = (instr & 0x0FFF) as i16;
+            let offset = if offset & 0x800 != 0 { offset | 0xF000u16 as i16 } else { offset };
+            (format!("J {:+}", offset * 2), 2)
+        }
+        0xA => {
+            let shift_name = match func {
+                0x0 => "SLL1", 0x1 => "SRL1", 0x2 => "SRA1",
+                0x3 => "ROL1", 0x4 => "ROR1", 0x7 => "SWAP",
+                0x8 => "SLL4", 0x9 => "SRL4", 0xA => "SRA4",
+                0xC => "SLL8", 0xD => "SRL8", 0xE => "SRA8",
+                _ => "SHIFT",
+            };
+            (format!("{} R{}, R{}", shift_name, rd, rs1), 2)
+        }
+        0xB => {
+            let op = match func {
+                0x0 => "MUL", 0x1 => "MULH", 0x2 => "MULHU",
+                0x3 => "DIV", 0x4 => "DIVU", 0x5 => "REM",
+                0x6 => "REMU", 0x7 => "DAA",
+                _ => "MULDIV",
+            };
+            (format!("{} R{}, R{}", op, rd, rs1), 2)
+        }
+        0xC => {
+            let op = match func {
+                0x0 => format!("PUSH R{}", rs1),
+                0x1 => format!("POP R{}", rd),
+                0x2 => format!("CMP R{}, R{}", rd, rs1),
+                0x3 => format!("TEST R{}, R{}", rd, rs1),
+                0x4 => format!("MOV R{}, R{}", rd, rs1),
+                0x5 => "LDI".to_string(),
+                0x6 => "LDD".to_string(),
+                0x7 => "LDIR".to_string(),
+                0x8 => "LDDR".to_string(),
+                0x9 => "CPIR".to_string(),
+                0xA => "FILL".to_string(),
+                0xB => "EXX".to_string(),
+                0xC => format!("GETF R{}", rd),
+                0xD => format!("SETF R{}", rs1),
+                0xE if rd == 0 => format!("JR R{}", rs1),
+                0xE => format!("JALR R{}, R{}", rd, rs1),
+                _ => format!("MOV R{}, PC", rd),
+            };
+            (op, 2)
+        }
+        0xD => {
+            let op = match func {
+                0x0 => format!("INI R{}, 0x{:X}", rd, rs1),
+                0x1 => format!("OUTI 0x{:X}, R{}", rs1, rd),
+                0x2 => format!("IN R{}, (R{})", rd, rs1),
+                0x3 => format!("OUT (R{}), R{}", rd, rs1),
+                _ => format!("I/O f={}", func),
+            };
+            (op, 2)
+        }
+        0xE => {
+            let op = match rd {
+                0x0 => "NOP".to_string(),
+                0x1 => "HALT".to_string(),
+                0x2 => "DI".to_string(),
+                0x3 => "EI".to_string(),
+                0x4 => "RETI".to_string(),
+                0x5 => format!("SWI 0x{:02X}", instr & 0xFF),
+                0x6 => "SCF".to_string(),
+                0x7 => "CCF".to_string(),
+                0xA => format!("CSKIP {}", CONDITIONS[(instr & 0xF) as usize]),
+                0xB => format!("RDCYC R{}, {}", rs1, instr & 0x3),
+                0xC => format!("TRAP 0x{:02X}", instr & 0xFF),
+                _ => format!("SYS f={}", rd),
+            };
+            (op, 2)
+        }
+        0xF => {
+            // Extended instruction - need to read imm16
+            let lo2 = cpu.read_memory(addr.wrapping_add(2));
+            let hi2 = cpu.read_memory(addr.wrapping_add(3));
+            let imm16 = u16::from_le_bytes([lo2, hi2]);
+
+            let op = match func {
+                0x0 => format!("ADDIX R{}, R{}, 0x{:04X}", rd, rs1, imm16),
+                0x1 => format!("SUBIX R{}, R{}, 0x{:04X}", rd, rs1, imm16),
+                0x2 => format!("ANDIX R{}, R{}, 0x{:04X}", rd, rs1, imm16),
+                0x3 => format!("ORIX R{}, R{}, 0x{:04X}", rd, rs1, imm16),
+                0x4 => format!("XORIX R{}, R{}, 0x{:04X}", rd, rs1, imm16),
+                0x5 => format!("LWX R{}, 0x{:04X}(R{})", rd, imm16, rs1),
+                0x6 => format!("SWX R{}, 0x{:04X}(R{})", rd, imm16, rs1),
+                0x7 => format!("LIX R{}, 0x{:04X}", rd, imm16),
+                0x8 if rs1 != 0 => format!("CAS R{}, (R{}), R{}", rd, rs1, imm16 & 0xF),
+                0x8 => format!("JX 0x{:04X}", imm16),
+                0x9 => format!("JALX R{}, 0x{:04X}", rd, imm16),
+                0xA => format!("CMPIX R{}, 0x{:04X}", rd, imm16),
+                0xB if imm16 >> 8 != 0 => {
+                    let op = match imm16 >> 8 {
+                        0x01 => "ADDS", 0x02 => "SUBS", 0x03 => "ADDSU", 0x04 => "SUBSU",
+                        0x05 => "BSET", 0x06 => "BCLR", 0x07 => "BTST",
+                        _ => return (format!("EXT sub=11 op={}", imm16 >> 8), 4),
+                    };
+                    if imm16 >> 8 >= 0x05 {
+                        return (format!("{} R{}, {}", op, rd, imm16 & 0xF), 4);
+                    }
+                    format!("{} R{}, R{}", op, rd, rs1)
+                }
+                0xB => format!("INX R{}, 0x{:02X}", rd, imm16 as u8),
+                0xC => format!("OUTX 0x{:02X}, R{}", imm16 as u8, rs1),
+                0xD => format!("SLLX R{}, R{}, {}", rd, rs1, imm16 & 0xF),
+                0xE => format!("SRLX R{}, R{}, {}", rd, rs1, imm16 & 0xF),
+                0xF => format!("SRAX R{}, R{}, {}", rd, rs1, imm16 & 0xF),
+                _ => format!("EXT sub={}", func),
+            };
+            (op, 4)
+        }
+        _ => (format!("??? {:04X}", instr), 2),
+    };
+
+    (mnemonic, size)
+}
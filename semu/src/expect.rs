@@ -0,0 +1,280 @@
+//! State assertions such as `R4=0x0005,flags=Z,[0x8000]=0x12`
+//!
+//! Each comma-separated item checks one register (`R0`..`R15`, `SP`, `PC`),
+//! a memory byte (`[addr]`), or the N/Z/C/V flags (`flags=` followed by the
+//! letters that must be set; the others must be clear). Registers, PC and
+//! memory may also be compared with `==`, `!=`, `<`, `<=`, `>` or `>=`
+//! (unsigned); flags take only `=`/`==` and `!=`.
+//!
+//! `--expect` checks the state after halt; `--break-on` stops a run on the
+//! step that makes a set of checks hold.
+
+use crate::cpu::{Cpu, FLAG_C, FLAG_N, FLAG_V, FLAG_Z};
+use crate::parse_number;
+
+const FLAG_LETTERS: [(char, u8); 4] = [('N', FLAG_N), ('Z', FLAG_Z), ('C', FLAG_C), ('V', FLAG_V)];
+const FLAG_MASK: u8 = FLAG_N | FLAG_Z | FLAG_C | FLAG_V;
+
+/// Operators, longest spelling first so `<=` is not read as `<`
+const OPS: [(&str, Op); 7] = [
+    ("==", Op::Eq), ("!=", Op::Ne), ("<=", Op::Le), (">=", Op::Ge),
+    ("=", Op::Eq), ("<", Op::Lt), (">", Op::Gt),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Target {
+    Reg(usize),
+    Pc,
+    Flags,
+    Mem(u16),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Check {
+    target: Target,
+    op: Op,
+    value: u16,
+}
+
+impl Check {
+    /// Current value of the checked location in `cpu`
+    fn actual(&self, cpu: &Cpu) -> u16 {
+        match self.target {
+            Target::Reg(r) => cpu.get_register(r),
+            Target::Pc => cpu.get_pc(),
+            Target::Flags => (cpu.get_flags() & FLAG_MASK) as u16,
+            Target::Mem(addr) => cpu.read_memory(addr) as u16,
+        }
+    }
+
+    pub fn holds(&self, cpu: &Cpu) -> bool {
+        let actual = self.actual(cpu);
+        match self.op {
+            Op::Eq => actual == self.value,
+            Op::Ne => actual != self.value,
+            Op::Lt => actual < self.value,
+            Op::Le => actual <= self.value,
+            Op::Gt => actual > self.value,
+            Op::Ge => actual >= self.value,
+        }
+    }
+
+    /// Describe a failed check as `what: expected X, got Y`
+    pub fn mismatch(&self, cpu: &Cpu) -> String {
+        let actual = self.actual(cpu);
+        let op = match self.op {
+            Op::Eq => "",
+            Op::Ne => "!= ",
+            Op::Lt => "< ",
+            Op::Le => "<= ",
+            Op::Gt => "> ",
+            Op::Ge => ">= ",
+        };
+        match self.target {
+            Target::Flags => format!("flags: expected {}{}, got {}", op, flag_letters(self.value as u8), flag_letters(actual as u8)),
+            Target::Mem(addr) => format!("[0x{:04X}]: expected {}0x{:02X}, got 0x{:02X}", addr, op, self.value, actual),
+            _ => format!("{}: expected {}0x{:04X}, got 0x{:04X}", self.target(), op, self.value, actual),
+        }
+    }
+
+    fn target(&self) -> String {
+        match self.target {
+            Target::Reg(r) => format!("R{}", r),
+            Target::Pc => "PC".to_string(),
+            Target::Flags => "flags".to_string(),
+            Target::Mem(addr) => format!("[0x{:04X}]", addr),
+        }
+    }
+}
+
+fn flag_letters(flags: u8) -> String {
+    let letters: String = FLAG_LETTERS.iter()
+        .filter(|(_, bit)| flags & bit != 0)
+        .map(|(c, _)| *c)
+        .collect();
+    if letters.is_empty() { "-".to_string() } else { letters }
+}
+
+/// Parse one `target<op>value` item
+pub fn parse_check(item: &str) -> Result<Check, String> {
+    let at = item.find(['=', '!', '<', '>'])
+        .ok_or_else(|| format!("expected <target>=<value> in '{}'", item))?;
+    let (spelling, op) = OPS.iter()
+        .find(|(spelling, _)| item[at..].starts_with(spelling))
+        .ok_or_else(|| format!("unknown operator in '{}'", item))?;
+    let target = item[..at].trim().to_uppercase();
+    let value = item[at + spelling.len()..].trim();
+    let op = *op;
+
+    if target == "FLAGS" {
+        if !matches!(op, Op::Eq | Op::Ne) {
+            return Err("flags can only be compared with = or !=".to_string());
+        }
+        let mut flags = 0;
+        for c in value.to_uppercase().chars().filter(|&c| c != '-') {
+            let (_, bit) = FLAG_LETTERS.iter().find(|(letter, _)| *letter == c)
+                .ok_or_else(|| format!("unknown flag '{}'", c))?;
+            flags |= bit;
+        }
+        return Ok(Check { target: Target::Flags, op, value: flags as u16 });
+    }
+
+    let number = parse_number(value)
+        .ok_or_else(|| format!("invalid value '{}'", value))?;
+    if let Some(addr) = target.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+        let addr = parse_number(&addr.to_lowercase())
+            .filter(|&a| a <= 0xFFFF)
+            .ok_or_else(|| format!("invalid address '{}'", addr))?;
+        let byte = u8::try_from(number).map_err(|_| format!("byte value out of range: {}", value))?;
+        return Ok(Check { target: Target::Mem(addr as u16), op, value: byte as u16 });
+    }
+
+    let word = u16::try_from(number).map_err(|_| format!("value out of range: {}", value))?;
+    let target = match target.as_str() {
+        "PC" => Target::Pc,
+        "SP" => Target::Reg(2),
+        _ => match target.strip_prefix('R').and_then(|n| n.parse::<usize>().ok()) {
+            Some(r) if r < 16 => Target::Reg(r),
+            _ => return Err(format!("unknown target '{}'", target)),
+        },
+    };
+    Ok(Check { target, op, value: word })
+}
+
+/// Parse a comma-separated list of checks
+pub fn parse_checks(spec: &str) -> Result<Vec<Check>, String> {
+    spec.split(',')
+        .filter(|item| !item.trim().is_empty())
+        .map(parse_check)
+        .collect()
+}
+
+/// `--break-on` conditions, each a list of checks that must all hold
+pub struct BreakOn {
+    conditions: Vec<(String, Vec<Check>, bool)>, // Spec, checks, held after the last step
+}
+
+impl Default for BreakOn {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BreakOn {
+    pub fn new() -> Self {
+        BreakOn { conditions: Vec::new() }
+    }
+
+    pub fn add(&mut self, spec: &str, cpu: &Cpu) -> Result<(), String> {
+        let checks = parse_checks(spec)?;
+        if checks.is_empty() {
+            return Err("no conditions".to_string());
+        }
+        let held = checks.iter().all(|c| c.holds(cpu));
+        self.conditions.push((spec.to_string(), checks, held));
+        Ok(())
+    }
+
+    /// Take the current state as the last one seen, after PC or memory
+    /// changed other than by stepping
+    pub fn sync(&mut self, cpu: &Cpu) {
+        for (_, checks, held) in &mut self.conditions {
+            *held = checks.iter().all(|c| c.holds(cpu));
+        }
+    }
+
+    /// After a step: the first condition that has just become true, if any.
+    /// One that already held must turn false before it can fire again.
+    pub fn triggered(&mut self, cpu: &Cpu) -> Option<&str> {
+        let mut fired = None;
+        for (i, (_, checks, held)) in self.conditions.iter_mut().enumerate() {
+            let now = checks.iter().all(|c| c.holds(cpu));
+            if now && !*held && fired.is_none() {
+                fired = Some(i);
+            }
+            *held = now;
+        }
+        fired.map(|i| self.conditions[i].0.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::test_util::machine;
+
+    /// A machine that has run `SUB R4, R0, R0` (Z and C set), with R5 and a byte set
+    fn cpu() -> Cpu {
+        let mut cpu = machine(&[0x1400]);
+        cpu.step().unwrap();
+        cpu.set_register(5, 0x1234);
+        cpu.write_memory(0x8000, 0x12);
+        cpu
+    }
+
+    #[test]
+    fn checks_compare_registers_flags_and_memory() {
+        let cpu = cpu();
+        let checks = parse_checks("R5=0x1234, flags=ZC, [0x8000]=0x12, PC>0x100, SP!=0").unwrap();
+        assert_eq!(checks.len(), 5);
+        assert!(checks.iter().all(|c| c.holds(&cpu)));
+
+        let failed = |spec| {
+            let check = parse_check(spec).unwrap();
+            assert!(!check.holds(&cpu), "{}", spec);
+            check.mismatch(&cpu)
+        };
+        assert_eq!(failed("R5=5"), "R5: expected 0x0005, got 0x1234");
+        assert_eq!(failed("flags=Z"), "flags: expected Z, got ZC");
+        assert_eq!(failed("flags!=CZ"), "flags: expected != ZC, got ZC");
+        assert_eq!(failed("[0x8000]<0x10"), "[0x8000]: expected < 0x10, got 0x12");
+    }
+
+    #[test]
+    fn malformed_checks_are_errors() {
+        let error = |spec| parse_check(spec).unwrap_err();
+        assert_eq!(error("R4=0x10000"), "value out of range: 0x10000");
+        assert_eq!(error("[0x8000]=0x100"), "byte value out of range: 0x100");
+        assert!(error("[0x10000]=1").starts_with("invalid address"));
+        assert_eq!(error("R16=1"), "unknown target 'R16'");
+        assert_eq!(error("flags<Z"), "flags can only be compared with = or !=");
+        assert_eq!(error("flags=Q"), "unknown flag 'Q'");
+        assert_eq!(error("R4"), "expected <target>=<value> in 'R4'");
+        assert_eq!(parse_checks("R4=1,,R5=2").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn break_on_stops_where_the_condition_first_holds() {
+        // loop: ADDI R4, 1; J loop
+        let mut cpu = machine(&[0x5401, 0x9FFE]);
+        let mut break_on = BreakOn::new();
+        break_on.add("R4==0x0005", &cpu).unwrap();
+        assert!(break_on.add(" ", &cpu).is_err());
+
+        let mut steps = 0;
+        let fired = loop {
+            cpu.step().unwrap();
+            steps += 1;
+            if let Some(spec) = break_on.triggered(&cpu) {
+                break spec.to_string();
+            }
+        };
+        assert_eq!(fired, "R4==0x0005");
+        // The ADDI that made R4 5 is the ninth step, with its J still to run
+        assert_eq!((steps, cpu.get_register(4), cpu.get_pc()), (9, 5, 0x0102));
+
+        // Still holding after the J, so it does not fire again
+        cpu.step().unwrap();
+        assert_eq!(break_on.triggered(&cpu), None);
+    }
+}
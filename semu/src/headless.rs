@@ -0,0 +1,94 @@
+//! One-call runs without a front end, for embedders and tests: load a
+//! program, queue its serial input, run it and collect the final state
+
+use crate::cpu::{Cpu, CpuConfig, CpuError};
+use crate::tui::TerminalEmulator;
+
+/// How a `run_program` run ended
+#[derive(Clone, Debug, PartialEq)]
+pub enum Stop {
+    Halted,
+    Trap(u8), // A TRAP instruction, with its code; PC is just past it
+    CycleLimit,
+    Error(CpuError),
+}
+
+/// Machine state after `run_program`
+#[derive(Clone, Debug)]
+pub struct RunResult {
+    pub stop: Stop,
+    pub regs: [u16; 16],
+    pub pc: u16,
+    pub flags: u8,
+    pub cycles: u64,
+    pub serial: Vec<u8>,     // What the program sent to the ACIA, as kept by the serial buffer (the last 64KB)
+    pub screen: Vec<String>, // The TUI terminal's rows after that output, trailing spaces trimmed
+}
+
+/// Run `program` (loaded at 0x0000, as from the command line) with
+/// `input` queued on the ACIA, until it halts, traps, faults, or has used
+/// `max_cycles`. The RNG is seeded with 0 so runs repeat exactly.
+pub fn run_program(program: &[u8], input: &[u8], max_cycles: u64) -> RunResult {
+    let mut cpu = Cpu::with_config(CpuConfig { seed: 0, ..CpuConfig::default() });
+    cpu.set_quiet(true);
+    cpu.set_trap_stop(true);
+    cpu.load_program(program);
+    for &byte in input {
+        cpu.send_key(byte);
+    }
+
+    let stop = loop {
+        if cpu.get_cycles() >= max_cycles {
+            break Stop::CycleLimit;
+        }
+        match cpu.step() {
+            Ok(true) => {
+                if let Some((code, _)) = cpu.take_trap() {
+                    break Stop::Trap(code);
+                }
+            }
+            Ok(false) => break Stop::Halted,
+            Err(e) => break Stop::Error(e),
+        }
+    };
+
+    let serial: Vec<u8> = cpu.get_serial_output().collect();
+    let mut terminal = TerminalEmulator::new();
+    for &byte in &serial {
+        terminal.putchar(byte);
+    }
+    let screen = terminal.screen().map(|row| row.trim_end().to_string()).collect();
+
+    RunResult {
+        stop,
+        regs: std::array::from_fn(|r| cpu.get_register(r)),
+        pc: cpu.get_pc(),
+        flags: cpu.get_flags(),
+        cycles: cpu.get_cycles(),
+        serial,
+        screen,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// examples/hello.s as sasm assembles it, from its `.org 0x0100`
+    const HELLO: &[u8] = &[
+        0x07, 0xf4, 0x1e, 0x01, 0x42, 0x65, 0x02, 0xc5, 0x09, 0x80, 0x0b, 0xf6, 0x80, 0x00,
+        0x66, 0x27, 0xfe, 0x57, 0xfb, 0x81, 0x5c, 0xf0, 0x81, 0x00, 0x01, 0x54, 0xf4, 0x9f,
+        0x00, 0xe1, b'H', b'e', b'l', b'l', b'o', b',', b' ', b'S', b'a', b'm', b'p', b'o',
+        b'!', b'\n', 0x00,
+    ];
+
+    #[test]
+    fn hello_world_prints_over_serial() {
+        let mut program = vec![0; 0x100];
+        program.extend_from_slice(HELLO);
+        let result = run_program(&program, &[], 100_000);
+        assert_eq!(result.stop, Stop::Halted);
+        assert_eq!(result.serial, b"Hello, Sampo!\n");
+        assert_eq!(result.screen[0], "Hello, Sampo!");
+    }
+}
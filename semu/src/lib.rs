@@ -0,0 +1,23 @@
+//! Sampo CPU Emulator (semu)
+//! The emulator core, shared by the `semu` binary and by embedders
+
+pub mod clock;
+pub mod command;
+pub mod cpu;
+pub mod device;
+pub mod disasm;
+pub mod expect;
+pub mod headless;
+pub mod rng;
+pub mod symbols;
+pub mod timer;
+pub mod tui;
+
+/// Parse a decimal or 0x-prefixed hexadecimal number
+pub fn parse_number(s: &str) -> Option<u32> {
+    if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        u32::from_str_radix(hex, 16).ok()
+    } else {
+        s.parse().ok()
+    }
+}
@@ -3,12 +3,15 @@
 
 use std::env;
 use std::fs;
-use std::io::{self, Write};
+use std::io::{self, IsTerminal, Read, Write};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
 
-mod cpu;
-mod tui;
-
-use cpu::Cpu;
+use semu::command::{self, Command};
+use semu::cpu::{Cpu, CpuConfig, Snapshot};
+use semu::expect::{self, BreakOn};
+use semu::symbols::Symbols;
+use semu::{disasm, parse_number, tui};
 
 fn main() {
     let args: Vec<String> = env::args().collect();
@@ -29,6 +32,90 @@ fn main() {
     let interactive = args.iter().any(|a| a == "-i" || a == "--interactive");
     let tui_mode = args.iter().any(|a| a == "--tui");
 
+    let mut config = CpuConfig {
+        trap_unmapped: args.iter().any(|a| a == "--mem-trap"),
+        poison: args.iter().any(|a| a == "--poison"),
+        strict_alignment: args.iter().any(|a| a == "--strict-align"),
+        reset_guard: args.iter().any(|a| a == "--reset-guard"),
+        exec_guard: args.iter().any(|a| a == "--exec-guard") || args.iter().any(|a| a == "--data"),
+        bios: args.iter().any(|a| a == "--bios"),
+        big_endian: args.iter().any(|a| a == "--big-endian"),
+        ..CpuConfig::default()
+    };
+    if let Some(&size) = option_values(&args, "--mem-size").last() {
+        match parse_number(size).filter(|&s| (2..=0x10000).contains(&s)) {
+            Some(s) => config.mem_size = s as usize,
+            None => {
+                eprintln!("Invalid --mem-size '{}': expected 2 to 0x10000 bytes", size);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if let Some(&base) = option_values(&args, "--acia").last() {
+        match parse_number(base).and_then(|b| u8::try_from(b).ok()) {
+            Some(b) => config.acia_base = b,
+            None => {
+                eprintln!("Invalid --acia '{}': expected a port 0x00 to 0xFF", base);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if let Some(&seed) = option_values(&args, "--seed").last() {
+        match parse_number(seed) {
+            Some(s) => config.seed = s as u64,
+            None => {
+                eprintln!("Invalid --seed '{}'", seed);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let mut extra_loads = Vec::new();
+    for spec in option_values(&args, "--load") {
+        match parse_load_spec(spec) {
+            Ok(load) => extra_loads.push(load),
+            Err(e) => {
+                eprintln!("Invalid --load '{}': {}", spec, e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let mut data_regions = Vec::new();
+    for spec in option_values(&args, "--data") {
+        match parse_region(spec) {
+            Ok(region) => data_regions.push(region),
+            Err(e) => {
+                eprintln!("Invalid --data '{}': {}", spec, e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let mut mem_dumps = Vec::new();
+    for spec in option_values(&args, "--dump-mem") {
+        match parse_dump_spec(spec) {
+            Ok(dump) => mem_dumps.push(dump),
+            Err(e) => {
+                eprintln!("Invalid --dump-mem '{}': {}", spec, e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let mut checks = Vec::new();
+    for spec in option_values(&args, "--expect") {
+        match expect::parse_checks(spec) {
+            Ok(mut c) => checks.append(&mut c),
+            Err(e) => {
+                eprintln!("Invalid --expect '{}': {}", spec, e);
+                std::process::exit(1);
+            }
+        }
+    }
+
     // Load program
     let program = match fs::read(input_file) {
         Ok(p) => p,
@@ -39,14 +126,94 @@ fn main() {
     };
 
     // Create and initialize CPU
-    let mut cpu = Cpu::new();
+    if program.len() > config.mem_size {
+        eprintln!("{} ({} bytes) does not fit in {} bytes of RAM", input_file, program.len(), config.mem_size);
+        std::process::exit(1);
+    }
+    let mut cpu = Cpu::with_config(config);
+    if let Some(conflict) = cpu.acia_conflict() {
+        eprintln!("Invalid --acia: {}", conflict);
+        std::process::exit(1);
+    }
     cpu.load_program(&program);
     cpu.set_trace(trace);
+    if let Some(&limit) = option_values(&args, "--serial-buffer").last() {
+        match parse_number(limit) {
+            Some(n) => cpu.set_serial_buffer_limit(n as usize),
+            None => {
+                eprintln!("Invalid --serial-buffer '{}'", limit);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if let Some(&len) = option_values(&args, "--mem-log").last() {
+        match parse_number(len) {
+            Some(n) => cpu.enable_mem_log(n as usize),
+            None => {
+                eprintln!("Invalid --mem-log '{}'", len);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if let Some(&path) = option_values(&args, "--serial-log").last() {
+        match fs::OpenOptions::new().create(true).append(true).open(path) {
+            Ok(file) => cpu.set_serial_log(file),
+            Err(e) => {
+                eprintln!("Error opening {}: {}", path, e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    for (path, addr) in &extra_loads {
+        let data = match fs::read(path) {
+            Ok(d) => d,
+            Err(e) => {
+                eprintln!("Error reading {}: {}", path, e);
+                std::process::exit(1);
+            }
+        };
+        if let Err(e) = load_at(&mut cpu, *addr, &data) {
+            eprintln!("{} {}", path, e);
+            std::process::exit(1);
+        }
+    }
+    for &(addr, len) in &data_regions {
+        cpu.mark_data(addr, len);
+    }
+    for dump in &mem_dumps {
+        if !dump.fits(cpu.mem_size()) {
+            eprintln!("{} ({} bytes) does not fit at 0x{:04X}", dump.path, dump.len, dump.addr);
+            std::process::exit(1);
+        }
+    }
+
+    let mut break_on = BreakOn::new();
+    for spec in option_values(&args, "--break-on") {
+        if let Err(e) = break_on.add(spec, &cpu) {
+            eprintln!("Invalid --break-on '{}': {}", spec, e);
+            std::process::exit(1);
+        }
+    }
 
     if tui_mode {
+        let symbols = match option_values(&args, "--symbols").last() {
+            Some(path) => match Symbols::load(path) {
+                Ok(symbols) => symbols,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            },
+            None => Symbols::default(),
+        };
+
         // Run TUI mode
-        if let Err(e) = tui::run_tui(&mut cpu) {
+        if let Err(e) = tui::run_tui(&mut cpu, symbols) {
             eprintln!("TUI error: {}", e);
+            finish_serial_log(&mut cpu);
             std::process::exit(1);
         }
     } else {
@@ -55,17 +222,200 @@ fn main() {
         println!();
 
         if interactive {
-            run_interactive(&mut cpu);
+            run_interactive(&mut cpu, &mut break_on);
         } else {
-            run(&mut cpu);
+            let host_input = args.iter().any(|a| a == "--stdin").then(spawn_stdin_reader);
+            run(&mut cpu, &mut break_on, host_input.as_ref());
         }
     }
+    finish_serial_log(&mut cpu);
+
+    for dump in &mem_dumps {
+        if let Err(e) = write_mem_dump(&cpu, dump) {
+            eprintln!("Error writing {}: {}", dump.path, e);
+            std::process::exit(1);
+        }
+    }
+
+    if !checks.is_empty() {
+        let failures: Vec<String> = checks.iter()
+            .filter(|check| !check.holds(&cpu))
+            .map(|check| check.mismatch(&cpu))
+            .collect();
+        if !failures.is_empty() {
+            for failure in &failures {
+                eprintln!("FAIL {}", failure);
+            }
+            std::process::exit(1);
+        }
+        println!("All {} expectations met", checks.len());
+    }
+}
+
+/// Print the `--mem-log` ring, oldest access first
+fn print_mem_log(cpu: &Cpu) {
+    let mut any = false;
+    for access in cpu.mem_log() {
+        any = true;
+        let (dir, value) = match (access.write, access.word) {
+            (false, true) => ("R ", format!("{:04X}", access.value)),
+            (true, true) => ("W ", format!("{:04X}", access.value)),
+            (false, false) => ("RB", format!("  {:02X}", access.value)),
+            (true, false) => ("WB", format!("  {:02X}", access.value)),
+        };
+        println!("  PC {:04X}  {} [{:04X}] {}", access.pc, dir, access.addr, value);
+    }
+    if !any {
+        println!("  (no memory accesses logged; use --mem-log <n>)");
+    }
+}
+
+/// Flush `--serial-log`, reporting a failure without ending the run
+fn finish_serial_log(cpu: &mut Cpu) {
+    if let Err(e) = cpu.flush_serial_log() {
+        eprintln!("Error writing serial log: {}", e);
+    }
+}
+
+/// Memory region to save to a host file after the run
+struct MemDump {
+    addr: u16,
+    len: usize,
+    path: String,
 }
 
-fn run(cpu: &mut Cpu) {
+impl MemDump {
+    /// Whether the region lies within `mem_size` bytes of RAM, without wrapping
+    fn fits(&self, mem_size: usize) -> bool {
+        self.addr as usize + self.len <= mem_size
+    }
+}
+
+/// Collect the values following each occurrence of a repeatable option
+fn option_values<'a>(args: &'a [String], name: &str) -> Vec<&'a str> {
+    args.windows(2)
+        .filter(|w| w[0] == name)
+        .map(|w| w[1].as_str())
+        .collect()
+}
+
+/// Parse `<file>@<addr>`
+fn parse_load_spec(spec: &str) -> Result<(String, u16), String> {
+    let (path, addr) = spec.rsplit_once('@')
+        .filter(|(p, _)| !p.is_empty())
+        .ok_or("expected <file>@<addr>")?;
+    let addr = parse_number(addr)
+        .filter(|&a| a <= 0xFFFF)
+        .ok_or_else(|| format!("bad address '{}'", addr))?;
+    Ok((path.to_string(), addr as u16))
+}
+
+/// Parse `<addr>:<len>:<file>`
+fn parse_dump_spec(spec: &str) -> Result<MemDump, String> {
+    let (region, path) = spec.match_indices(':').nth(1)
+        .map(|(at, _)| (&spec[..at], &spec[at + 1..]))
+        .filter(|(_, p)| !p.is_empty())
+        .ok_or("expected <addr>:<len>:<file>")?;
+    let (addr, len) = parse_region(region)?;
+    Ok(MemDump { addr, len, path: path.to_string() })
+}
+
+/// Parse `<addr>:<len>`
+fn parse_region(spec: &str) -> Result<(u16, usize), String> {
+    let (addr, len) = spec.split_once(':').ok_or("expected <addr>:<len>")?;
+    let addr = parse_number(addr)
+        .filter(|&a| a <= 0xFFFF)
+        .ok_or_else(|| format!("bad address '{}'", addr))?;
+    let len = parse_number(len)
+        .filter(|&l| l <= 0x10000)
+        .ok_or_else(|| format!("bad length '{}'", len))?;
+    Ok((addr as u16, len as usize))
+}
+
+/// Copy a `--load` blob into RAM at `addr` and mark it as data
+fn load_at(cpu: &mut Cpu, addr: u16, data: &[u8]) -> Result<(), String> {
+    if addr as usize + data.len() > cpu.mem_size() {
+        return Err(format!("({} bytes) does not fit at 0x{:04X}", data.len(), addr));
+    }
+    for (i, &byte) in data.iter().enumerate() {
+        cpu.write_memory(addr + i as u16, byte);
+    }
+    cpu.mark_data(addr, data.len());
+    Ok(())
+}
+
+/// Save a region checked to lie within RAM
+fn write_mem_dump(cpu: &Cpu, dump: &MemDump) -> io::Result<()> {
+    let bytes: Vec<u8> = (0..dump.len)
+        .map(|i| cpu.read_memory((dump.addr as usize + i) as u16))
+        .collect();
+    fs::write(&dump.path, bytes)
+}
+
+/// ACIA reads finding no input, after stdin has ended, before `run` gives up
+const EOF_POLL_LIMIT: u64 = 100_000;
+
+/// Read host stdin on its own thread so the run loop never blocks on it.
+/// A terminal stays in cooked mode (line editing and Ctrl+C still work) and
+/// its Enter is sent as CR, as the TUI does; piped bytes pass unchanged.
+/// At EOF the thread ends and the guest sees no further input.
+fn spawn_stdin_reader() -> Receiver<u8> {
+    let (tx, rx) = mpsc::channel();
+    let terminal = io::stdin().is_terminal();
+    thread::spawn(move || {
+        for byte in io::stdin().lock().bytes() {
+            let Ok(byte) = byte else { break };
+            let byte = if terminal && byte == b'\n' { b'\r' } else { byte };
+            if tx.send(byte).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+/// Run until halt, or stop (returning false) when PC reaches a breakpoint,
+/// a `--break-on` condition becomes true or the debugger takes a TRAP.
+/// Bytes from `input` are queued for the ACIA between steps; once `input`
+/// has ended, a guest that keeps polling the ACIA for more is stopped
+/// rather than left spinning forever.
+fn run(cpu: &mut Cpu, break_on: &mut BreakOn, input: Option<&Receiver<u8>>) -> bool {
+    let mut input_ended = false;
     loop {
+        if let Some(input) = input {
+            loop {
+                match input.try_recv() {
+                    Ok(byte) => cpu.send_key(byte),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        input_ended = true;
+                        break;
+                    }
+                }
+            }
+        }
+        if input_ended && cpu.rx_polls() >= EOF_POLL_LIMIT {
+            println!("\nInput ended; guest still waiting for it at 0x{:04X}", cpu.get_pc());
+            break;
+        }
         match cpu.step() {
-            Ok(true) => {} // Continue
+            Ok(true) => {
+                if let Some((code, at)) = cpu.take_trap() {
+                    println!("\nTRAP 0x{:02X} at 0x{:04X}", code, at);
+                    cpu.dump_short();
+                    return false;
+                }
+                if cpu.is_breakpoint(cpu.get_pc()) {
+                    println!("\nBreakpoint at 0x{:04X}", cpu.get_pc());
+                    cpu.dump_short();
+                    return false;
+                }
+                if let Some(spec) = break_on.triggered(cpu) {
+                    println!("\nBreak on {} at 0x{:04X}", spec, cpu.get_pc());
+                    cpu.dump_short();
+                    return false;
+                }
+            }
             Ok(false) => {
                 println!("\nCPU halted at 0x{:04X}", cpu.get_pc());
                 break;
@@ -73,31 +423,60 @@ fn run(cpu: &mut Cpu) {
             Err(e) => {
                 eprintln!("\nError at 0x{:04X}: {}", cpu.get_pc(), e);
                 cpu.dump_state();
+                print_mem_log(cpu);
+                finish_serial_log(cpu);
                 std::process::exit(1);
             }
         }
     }
     cpu.dump_state();
+    true
 }
 
-fn run_interactive(cpu: &mut Cpu) {
+fn run_interactive(cpu: &mut Cpu, break_on: &mut BreakOn) {
     let stdin = io::stdin();
     let mut input = String::new();
+    let mut show_asm = true;
+    let mut checkpoint: Option<Snapshot> = None; // Taken at each breakpoint stop
+    cpu.set_trap_stop(true);
 
     loop {
         print!("semu> ");
         io::stdout().flush().unwrap();
 
         input.clear();
-        if stdin.read_line(&mut input).is_err() {
+        // End of input quits like `q`
+        if !matches!(stdin.read_line(&mut input), Ok(n) if n > 0) {
             break;
         }
 
-        let cmd = input.trim();
+        let cmd = match command::parse(&input) {
+            Ok(Some(cmd)) => cmd,
+            Ok(None) => continue,
+            Err(e) => {
+                println!("{}", e);
+                continue;
+            }
+        };
+        if let Some(result) = command::apply(cpu, &cmd) {
+            match result {
+                Ok(msg) => println!("{}", msg),
+                Err(e) => println!("{}", e),
+            }
+            continue;
+        }
         match cmd {
-            "s" | "step" => {
+            Command::Step => {
+                if show_asm {
+                    println!("{}", step_listing(cpu));
+                }
                 match cpu.step() {
-                    Ok(true) => cpu.dump_short(),
+                    Ok(true) => {
+                        if let Some((code, at)) = cpu.take_trap() {
+                            println!("TRAP 0x{:02X} at 0x{:04X}", code, at);
+                        }
+                        cpu.dump_short();
+                    }
                     Ok(false) => {
                         println!("CPU halted");
                         break;
@@ -107,36 +486,52 @@ fn run_interactive(cpu: &mut Cpu) {
                     }
                 }
             }
-            "r" | "run" => {
-                run(cpu);
-                break;
+            Command::Run => {
+                if run(cpu, break_on, None) {
+                    break;
+                }
+                // A breakpoint stop returns to the prompt, with a checkpoint
+                // to come back to after exploring further
+                checkpoint = Some(cpu.snapshot());
             }
-            "d" | "dump" => {
+            Command::Restore => match &checkpoint {
+                Some(snap) => {
+                    cpu.restore(snap);
+                    break_on.sync(cpu);
+                    println!("Restored the checkpoint at 0x{:04X}", snap.pc());
+                    cpu.dump_short();
+                }
+                None => println!("No checkpoint: nothing has stopped at a breakpoint yet"),
+            },
+            Command::Dump => {
                 cpu.dump_state();
             }
-            "m" | "mem" => {
-                cpu.dump_memory(cpu.get_pc(), 32);
+            Command::Mem(addr) => {
+                cpu.dump_memory(addr.unwrap_or(cpu.get_pc()), 32);
             }
-            "q" | "quit" => {
+            Command::MemAccess => print_mem_log(cpu),
+            Command::Asm(on) => show_asm = on,
+            Command::Quit => {
                 break;
             }
-            "h" | "help" => {
+            Command::Help => {
                 println!("Commands:");
-                println!("  s, step  - Execute one instruction");
-                println!("  r, run   - Run until halt");
-                println!("  d, dump  - Dump CPU state");
-                println!("  m, mem   - Dump memory at PC");
-                println!("  q, quit  - Exit");
-            }
-            _ => {
-                if !cmd.is_empty() {
-                    println!("Unknown command: {}", cmd);
+                for (usage, text) in command::HELP {
+                    println!("  {:<20} - {}", usage, text);
                 }
             }
+            _ => {}
         }
     }
 }
 
+/// The instruction about to be stepped, as `addr: disassembly`
+fn step_listing(cpu: &Cpu) -> String {
+    let pc = cpu.get_pc();
+    let (text, _) = disasm::disassemble(cpu, pc);
+    format!("{:04X}: {}", pc, text)
+}
+
 fn print_help() {
     println!("Sampo Emulator (semu) v0.1.0");
     println!();
@@ -146,15 +541,129 @@ fn print_help() {
     println!("  -t, --trace       Trace execution");
     println!("  -i, --interactive Interactive CLI debugger");
     println!("      --tui         TUI mode with graphical interface");
+    println!("      --mem-size <bytes>");
+    println!("                    Installed RAM (default 0x10000); reads above it return 0xFF");
+    println!("      --mem-trap    Fault on accesses above installed RAM instead");
+    println!("      --poison      Fault on reads of memory nothing has written");
+    println!("      --strict-align");
+    println!("                    Fault on PUSH/POP/SWI/RETI and interrupts with an odd SP");
+    println!("      --reset-guard Stop when PC returns to 0x0000 after the first instruction");
+    println!("                    (a RET with a zeroed stack, for instance)");
+    println!("      --exec-guard  Fault on fetching an instruction from data: bytes the program");
+    println!("                    stored, --load files and --data regions");
+    println!("      --data <addr>:<len>");
+    println!("                    Mark a region (e.g. a .db table) as data; implies --exec-guard");
+    println!("                    (repeatable)");
+    println!("      --bios        Serve SWI 0x7C when its vector word is 0x0000, by service in A0:");
+    println!("                    0 sends A1's low byte to the ACIA, 1 halts, 2 reads a byte into A0");
+    println!("                    (0xFFFF if none)");
+    println!("      --big-endian  Load and store words high byte first (code is still fetched");
+    println!("                    little-endian, as sasm emits it; use .dwbe for data words)");
+    println!("      --acia <port> ACIA status port (default 0x80); data is the next port");
+    println!("      --seed <n>    Seed the RNG device (port 0xA0) for a reproducible run;");
+    println!("                    without it the seed comes from the host clock");
+    println!("      --mem-log <n> Keep the last n data memory accesses (REPL: maccess)");
+    println!("      --stdin       Feed host stdin to the ACIA while running (not with -i/--tui)");
+    println!("      --serial-log <file>");
+    println!("                    Append all serial output to a file");
+    println!("      --serial-buffer <bytes>");
+    println!("                    Serial output kept for the final dump (default 65536)");
+    println!("      --load <file>@<addr>");
+    println!("                    Load a data file at an address before running (repeatable)");
+    println!("      --dump-mem <addr>:<len>:<file>");
+    println!("                    Write a memory region to a file after halt (repeatable)");
+    println!("      --expect <spec>");
+    println!("                    After halt, check state and exit 1 on mismatch,");
+    println!("                    e.g. \"R4=0x0005,SP=0xFFFE,flags=Z,[0x8000]=0x12\"");
+    println!("      --break-on <spec>");
+    println!("                    Stop on the step that makes all of the checks hold,");
+    println!("                    e.g. \"R4==0x0005\" or \"[0x8000]!=0,R5>=10\" (repeatable)");
+    println!("      --symbols <file>");
+    println!("                    Symbol map from sasm -m, listed in the TUI sidebar (F1)");
     println!("  -h, --help        Show this help message");
     println!();
     println!("TUI Controls:");
     println!("  F5          Run continuously");
     println!("  F6          Step one instruction");
     println!("  F7          Pause execution");
+    println!("  Ctrl+C      Break (NMI) into the debugger");
     println!("  F8          Reset CPU");
     println!("  F9/F10      Memory view up/down (16 bytes)");
     println!("  PgUp/PgDn   Memory view up/down (256 bytes)");
     println!("  Alt+=/Alt+- Adjust emulation speed");
+    println!("  F1          Toggle the breakpoint/symbol sidebar (Up/Down select,");
+    println!("              Enter shows the entry in the disassembly)");
+    println!("  F2          Set speed (cycles/frame, or MHz e.g. 2.5mhz)");
+    println!("  F3          Cycle values between hex, decimal and signed decimal");
+    println!("  F4          Device command: attach|detach|reset <name> (e.g. reset timer)");
+    println!("  :           Debugger command line (paused), e.g. :break 0x1234, :set R4 5");
+    println!("  /           Search terminal scrollback (paused; n/N next/prev, Esc live)");
     println!("  F12         Quit");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A machine that has run `code` from 0x0100 to its HALT
+    fn run_code(code: &[u16]) -> Cpu {
+        let mut cpu = Cpu::with_config(CpuConfig { seed: 0, ..CpuConfig::default() });
+        cpu.set_quiet(true);
+        let bytes: Vec<u8> = code.iter().flat_map(|word| word.to_le_bytes()).collect();
+        load_at(&mut cpu, 0x0100, &bytes).unwrap();
+        cpu.set_pc(0x0100);
+        while cpu.step().unwrap() {}
+        cpu
+    }
+
+    /// A path in the temp directory for this test process
+    fn temp_path(name: &str) -> String {
+        let path = env::temp_dir().join(format!("semu-{}-{}", std::process::id(), name));
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn dump_mem_saves_a_buffer_the_program_filled() {
+        // LIX R5, 0x0400; LIX R4, 0xABCD; SW (R5), R4; SW 2(R5), R5; HALT
+        let cpu = run_code(&[0xF507, 0x0400, 0xF407, 0xABCD, 0x7450, 0x7552, 0xE100]);
+        let path = temp_path("dump.bin");
+        let dump = parse_dump_spec(&format!("0x0400:4:{}", path)).unwrap();
+        write_mem_dump(&cpu, &dump).unwrap();
+        let saved = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(saved, [0xCD, 0xAB, 0x00, 0x04]);
+
+        assert!(dump.fits(0x10000));
+        assert!(parse_dump_spec("0xFFFC:4:x.bin").unwrap().fits(0x10000));
+        assert!(!parse_dump_spec("0xFFFF:4:x.bin").unwrap().fits(0x10000));
+        assert!(!dump.fits(0x0402));
+        assert!(parse_dump_spec("0x0400:4:").is_err());
+        assert!(parse_dump_spec("0x10000:4:x.bin").is_err());
+    }
+
+    #[test]
+    fn stepping_lists_the_instruction_about_to_run() {
+        // ADDI R4, 5; HALT
+        let mut cpu = Cpu::with_config(CpuConfig { seed: 0, ..CpuConfig::default() });
+        load_at(&mut cpu, 0x0100, &[0x05, 0x54, 0x00, 0xE1]).unwrap();
+        cpu.set_pc(0x0100);
+        assert_eq!(step_listing(&cpu), "0100: ADDI R4, 5");
+        cpu.step().unwrap();
+        assert_eq!(step_listing(&cpu), "0102: HALT");
+        assert_eq!(command::parse("asm off"), Ok(Some(Command::Asm(false))));
+        assert_eq!(command::parse("asm maybe"), Err("Unknown command: asm maybe".to_string()));
+    }
+
+    #[test]
+    fn load_places_a_blob_at_its_address() {
+        let mut cpu = Cpu::with_config(CpuConfig { seed: 0, ..CpuConfig::default() });
+        let (path, addr) = parse_load_spec("table.bin@0x2000").unwrap();
+        assert_eq!((path.as_str(), addr), ("table.bin", 0x2000));
+        load_at(&mut cpu, addr, &[1, 2, 3]).unwrap();
+        assert_eq!([0x1FFF, 0x2000, 0x2001, 0x2002, 0x2003].map(|a| cpu.read_memory(a)), [0, 1, 2, 3, 0]);
+
+        let err = load_at(&mut cpu, 0xFFFE, &[1, 2, 3]).unwrap_err();
+        assert_eq!(err, "(3 bytes) does not fit at 0xFFFE");
+        assert_eq!(cpu.read_memory(0xFFFE), 0);
+    }
+}
@@ -0,0 +1,77 @@
+//! Random number generator
+//!
+//! Each read of `RNG_PORT` returns the next pseudo-random byte. The
+//! sequence is fixed by the seed and restarts on reset, so a run with a
+//! given `--seed` is reproducible.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::device::IoDevice;
+
+pub const RNG_PORT: u8 = 0xA0;
+
+#[derive(Clone)]
+pub struct Rng {
+    seed: u64,
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = Rng { seed, state: 0 };
+        rng.reset();
+        rng
+    }
+
+    /// xorshift64*
+    fn next(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+}
+
+/// Seed for runs without `--seed`
+pub fn host_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+impl IoDevice for Rng {
+    fn name(&self) -> &'static str {
+        "rng"
+    }
+
+    fn id(&self) -> u8 {
+        2
+    }
+
+    fn owns(&self, port: u8) -> bool {
+        port == RNG_PORT
+    }
+
+    fn read(&mut self, _port: u8) -> u8 {
+        (self.next() >> 56) as u8
+    }
+
+    fn write(&mut self, _port: u8, _val: u8) {}
+
+    fn reset(&mut self) {
+        // xorshift must not start from zero
+        self.state = self.seed ^ 0x9E37_79B9_7F4A_7C15;
+        if self.state == 0 {
+            self.state = 1;
+        }
+    }
+
+    fn status(&self) -> String {
+        format!("seed {}", self.seed)
+    }
+
+    fn snapshot(&self) -> Box<dyn IoDevice> {
+        Box::new(self.clone())
+    }
+}
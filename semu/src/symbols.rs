@@ -0,0 +1,44 @@
+//! Symbol maps written by `sasm -m`: one `ADDR G|L name` line per symbol,
+//! with the address in hex
+
+use std::fs;
+
+#[derive(Default)]
+pub struct Symbols {
+    entries: Vec<(u16, String)>, // Sorted by address, then name
+}
+
+impl Symbols {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("Error reading {}: {}", path, e))?;
+        Self::parse(&text).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut entries = Vec::new();
+        for (n, line) in text.lines().enumerate() {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words[..] {
+                [] => {}
+                [addr, "G" | "L", name] => {
+                    let addr = u16::from_str_radix(addr, 16)
+                        .map_err(|_| format!("line {}: bad address '{}'", n + 1, addr))?;
+                    entries.push((addr, name.to_string()));
+                }
+                _ => return Err(format!("line {}: expected <addr> G|L <name>", n + 1)),
+            }
+        }
+        entries.sort();
+        Ok(Symbols { entries })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(u16, String)> {
+        self.entries.iter()
+    }
+
+    /// First symbol at exactly `addr`
+    pub fn name_at(&self, addr: u16) -> Option<&str> {
+        let i = self.entries.partition_point(|(a, _)| *a < addr);
+        self.entries.get(i).filter(|(a, _)| *a == addr).map(|(_, name)| name.as_str())
+    }
+}
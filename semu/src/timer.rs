@@ -0,0 +1,117 @@
+//! Programmable interval timer
+//!
+//! Ports (at `TIMER_BASE`):
+//! - +0 control/status: write bit 0 = enable, bit 1 = IRQ enable;
+//!   read returns the control bits with bit 7 set if the timer expired,
+//!   and acknowledges the expiry (clearing the IRQ)
+//! - +1 reload low byte, +2 reload high byte (period in cycles);
+//!   reads return the current count
+
+use crate::device::IoDevice;
+
+pub const TIMER_BASE: u8 = 0x90;
+
+const CTRL_ENABLE: u8 = 0x01;
+const CTRL_IRQ: u8 = 0x02;
+const STATUS_EXPIRED: u8 = 0x80;
+
+#[derive(Clone)]
+pub struct Timer {
+    ctrl: u8,
+    reload: u16,
+    count: u16,
+    expired: bool,
+}
+
+impl Default for Timer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Timer {
+    pub fn new() -> Self {
+        Timer {
+            ctrl: 0,
+            reload: 0,
+            count: 0,
+            expired: false,
+        }
+    }
+
+    /// Control/status byte without acknowledging an expiry
+    fn status_byte(&self) -> u8 {
+        self.ctrl | if self.expired { STATUS_EXPIRED } else { 0 }
+    }
+}
+
+impl IoDevice for Timer {
+    fn name(&self) -> &'static str {
+        "timer"
+    }
+
+    fn id(&self) -> u8 {
+        1
+    }
+
+    fn owns(&self, port: u8) -> bool {
+        (TIMER_BASE..TIMER_BASE + 3).contains(&port)
+    }
+
+    fn read(&mut self, port: u8) -> u8 {
+        match port - TIMER_BASE {
+            0 => {
+                let status = self.status_byte();
+                self.expired = false;
+                status
+            }
+            1 => self.count as u8,
+            _ => (self.count >> 8) as u8,
+        }
+    }
+
+    fn write(&mut self, port: u8, val: u8) {
+        match port - TIMER_BASE {
+            0 => {
+                // Starting the timer loads the period
+                if self.ctrl & CTRL_ENABLE == 0 && val & CTRL_ENABLE != 0 {
+                    self.count = self.reload;
+                }
+                self.ctrl = val & (CTRL_ENABLE | CTRL_IRQ);
+            }
+            1 => self.reload = (self.reload & 0xFF00) | val as u16,
+            _ => self.reload = (self.reload & 0x00FF) | ((val as u16) << 8),
+        }
+    }
+
+    fn reset(&mut self) {
+        *self = Timer::new();
+    }
+
+    /// Advance by `cycles`, reloading each time the count runs out
+    fn tick(&mut self, cycles: u64) {
+        if self.ctrl & CTRL_ENABLE == 0 || self.reload == 0 {
+            return;
+        }
+        let mut remaining = cycles;
+        while remaining >= self.count as u64 {
+            remaining -= self.count as u64;
+            self.count = self.reload;
+            self.expired = true;
+        }
+        self.count -= remaining as u16;
+    }
+
+    /// Interrupt line: held until the expiry is acknowledged
+    fn irq(&self) -> bool {
+        self.expired && self.ctrl & CTRL_IRQ != 0
+    }
+
+    fn status(&self) -> String {
+        format!("ctrl {:02X}  count {:04X}  reload {:04X}", self.status_byte(), self.count, self.reload)
+    }
+
+    fn snapshot(&self) -> Box<dyn IoDevice> {
+        Box::new(self.clone())
+    }
+}
@@ -6,6 +6,7 @@
 //! - Disassembly view
 //! - Terminal emulator with VT220 support
 //! - Stack view
+//! - Breakpoint and symbol sidebar
 //! - Interactive debugging controls
 
 use std::collections::VecDeque;
@@ -22,22 +23,35 @@ use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, Paragraph},
+    widgets::{Block, Borders, Clear, Paragraph},
     Frame, Terminal,
 };
 use sysinfo::System;
 
-use crate::cpu::{Cpu, FLAG_C, FLAG_I, FLAG_N, FLAG_V, FLAG_Z};
+use crate::clock::{Clock, SystemClock};
+use crate::command::{self, Command};
+use crate::cpu::{Cpu, CpuError, Snapshot, FLAG_C, FLAG_I, FLAG_N, FLAG_V, FLAG_Z};
+use crate::device::{self, DEVICE_NAMES};
+use crate::disasm::disassemble;
+use crate::rng::RNG_PORT;
+use crate::symbols::Symbols;
+use crate::timer::TIMER_BASE;
 
 // Terminal emulator constants
 const TERM_COLS: usize = 80;
 const TERM_ROWS: usize = 24;
+const SCROLLBACK_LINES: usize = 1000;
 
 // Execution constants
 const TICK_RATE_MS: u64 = 16; // ~60 FPS
 const DEFAULT_CYCLES_PER_FRAME: usize = 50000;
+const MIN_CYCLES_PER_FRAME: usize = 1000;
+const MAX_CYCLES_PER_FRAME: usize = 500000;
 const OUTPUT_CHARS_PER_FRAME: usize = 120;
 
+// Layout constants
+const SIDEBAR_WIDTH: u16 = 24;
+
 /// VT220 Terminal Emulator
 pub struct TerminalEmulator {
     buffer: [[char; TERM_COLS]; TERM_ROWS],
@@ -46,6 +60,11 @@ pub struct TerminalEmulator {
     cursor_visible: bool,
     escape_state: EscapeState,
     escape_buffer: String,
+    tab_stops: [bool; TERM_COLS],
+    g0_graphics: bool, // G0 designated as DEC special graphics
+    g1_graphics: bool, // G1 designated as DEC special graphics
+    shift_out: bool,   // G1 invoked into GL (SO)
+    scrollback: VecDeque<[char; TERM_COLS]>, // Lines scrolled off the top, oldest first
 }
 
 #[derive(Clone, Copy, PartialEq)]
@@ -53,6 +72,55 @@ enum EscapeState {
     Normal,
     Escape,
     Csi,
+    CharsetG0,
+    CharsetG1,
+}
+
+/// Default tab stops: every 8 columns
+fn default_tab_stops() -> [bool; TERM_COLS] {
+    let mut stops = [false; TERM_COLS];
+    for col in (8..TERM_COLS).step_by(8) {
+        stops[col] = true;
+    }
+    stops
+}
+
+/// Map a character from the DEC special graphics set to Unicode
+fn dec_graphics_char(c: u8) -> char {
+    match c {
+        b'`' => '◆',
+        b'a' => '▒',
+        b'f' => '°',
+        b'g' => '±',
+        b'j' => '┘',
+        b'k' => '┐',
+        b'l' => '┌',
+        b'm' => '└',
+        b'n' => '┼',
+        b'o' => '⎺',
+        b'p' => '⎻',
+        b'q' => '─',
+        b'r' => '⎼',
+        b's' => '⎽',
+        b't' => '├',
+        b'u' => '┤',
+        b'v' => '┴',
+        b'w' => '┬',
+        b'x' => '│',
+        b'y' => '≤',
+        b'z' => '≥',
+        b'{' => 'π',
+        b'|' => '≠',
+        b'}' => '£',
+        b'~' => '·',
+        _ => c as char,
+    }
+}
+
+impl Default for TerminalEmulator {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TerminalEmulator {
@@ -64,6 +132,11 @@ impl TerminalEmulator {
             cursor_visible: true,
             escape_state: EscapeState::Normal,
             escape_buffer: String::new(),
+            tab_stops: default_tab_stops(),
+            g0_graphics: false,
+            g1_graphics: false,
+            shift_out: false,
+            scrollback: VecDeque::new(),
         }
     }
 
@@ -72,9 +145,21 @@ impl TerminalEmulator {
             EscapeState::Normal => self.handle_normal(c),
             EscapeState::Escape => self.handle_escape(c),
             EscapeState::Csi => self.handle_csi(c),
+            EscapeState::CharsetG0 => {
+                self.g0_graphics = c == b'0';
+                self.escape_state = EscapeState::Normal;
+            }
+            EscapeState::CharsetG1 => {
+                self.g1_graphics = c == b'0';
+                self.escape_state = EscapeState::Normal;
+            }
         }
     }
 
+    fn graphics_active(&self) -> bool {
+        if self.shift_out { self.g1_graphics } else { self.g0_graphics }
+    }
+
     fn handle_normal(&mut self, c: u8) {
         match c {
             0x1B => {
@@ -93,34 +178,38 @@ impl TerminalEmulator {
                     self.cursor_row = TERM_ROWS - 1;
                 }
             }
-            0x08 => {
+            0x08 if self.cursor_col > 0 => {
                 // Backspace
-                if self.cursor_col > 0 {
-                    self.cursor_col -= 1;
-                }
+                self.cursor_col -= 1;
             }
             0x09 => {
-                // Tab
-                self.cursor_col = (self.cursor_col + 8) & !7;
-                if self.cursor_col >= TERM_COLS {
-                    self.cursor_col = TERM_COLS - 1;
-                }
+                // Tab - advance to the next tab stop, or the last column
+                self.cursor_col = ((self.cursor_col + 1)..TERM_COLS)
+                    .find(|&col| self.tab_stops[col])
+                    .unwrap_or(TERM_COLS - 1);
             }
             0x07 => {
                 // Bell - ignore
             }
-            0x20..=0x7E => {
+            0x0E => {
+                // Shift out - invoke G1
+                self.shift_out = true;
+            }
+            0x0F => {
+                // Shift in - invoke G0
+                self.shift_out = false;
+            }
+            0x20..=0x7E if self.cursor_col < TERM_COLS && self.cursor_row < TERM_ROWS => {
                 // Printable character
-                if self.cursor_col < TERM_COLS && self.cursor_row < TERM_ROWS {
-                    self.buffer[self.cursor_row][self.cursor_col] = c as char;
-                    self.cursor_col += 1;
-                    if self.cursor_col >= TERM_COLS {
-                        self.cursor_col = 0;
-                        self.cursor_row += 1;
-                        if self.cursor_row >= TERM_ROWS {
-                            self.scroll_up();
-                            self.cursor_row = TERM_ROWS - 1;
-                        }
+                let ch = if self.graphics_active() { dec_graphics_char(c) } else { c as char };
+                self.buffer[self.cursor_row][self.cursor_col] = ch;
+                self.cursor_col += 1;
+                if self.cursor_col >= TERM_COLS {
+                    self.cursor_col = 0;
+                    self.cursor_row += 1;
+                    if self.cursor_row >= TERM_ROWS {
+                        self.scroll_up();
+                        self.cursor_row = TERM_ROWS - 1;
                     }
                 }
             }
@@ -133,6 +222,17 @@ impl TerminalEmulator {
             b'[' => {
                 self.escape_state = EscapeState::Csi;
             }
+            b'(' => {
+                self.escape_state = EscapeState::CharsetG0;
+            }
+            b')' => {
+                self.escape_state = EscapeState::CharsetG1;
+            }
+            b'H' => {
+                // HTS - set tab stop at cursor
+                self.tab_stops[self.cursor_col] = true;
+                self.escape_state = EscapeState::Normal;
+            }
             _ => {
                 self.escape_state = EscapeState::Normal;
             }
@@ -140,7 +240,7 @@ impl TerminalEmulator {
     }
 
     fn handle_csi(&mut self, c: u8) {
-        if c >= 0x40 && c <= 0x7E {
+        if (0x40..=0x7E).contains(&c) {
             // End of CSI sequence
             self.escape_buffer.push(c as char);
             self.execute_csi();
@@ -159,7 +259,7 @@ impl TerminalEmulator {
                 .split(';')
                 .filter_map(|s| s.parse().ok())
                 .collect();
-            self.cursor_row = params.get(0).copied().unwrap_or(1).saturating_sub(1).min(TERM_ROWS - 1);
+            self.cursor_row = params.first().copied().unwrap_or(1).saturating_sub(1).min(TERM_ROWS - 1);
             self.cursor_col = params.get(1).copied().unwrap_or(1).saturating_sub(1).min(TERM_COLS - 1);
         } else if seq.ends_with('J') {
             // Erase display
@@ -195,6 +295,14 @@ impl TerminalEmulator {
             // Cursor back
             let n: usize = seq[..seq.len()-1].parse().unwrap_or(1);
             self.cursor_col = self.cursor_col.saturating_sub(n);
+        } else if seq.ends_with('g') {
+            // TBC - clear tab stops
+            let param: usize = seq[..seq.len()-1].parse().unwrap_or(0);
+            match param {
+                0 => self.tab_stops[self.cursor_col] = false,
+                3 => self.tab_stops = [false; TERM_COLS],
+                _ => {}
+            }
         } else if seq == "?25h" {
             self.cursor_visible = true;
         } else if seq == "?25l" {
@@ -203,6 +311,10 @@ impl TerminalEmulator {
     }
 
     fn scroll_up(&mut self) {
+        if self.scrollback.len() == SCROLLBACK_LINES {
+            self.scrollback.pop_front();
+        }
+        self.scrollback.push_back(self.buffer[0]);
         for row in 1..TERM_ROWS {
             self.buffer[row - 1] = self.buffer[row];
         }
@@ -249,10 +361,39 @@ impl TerminalEmulator {
         }
     }
 
-    pub fn get_lines(&self) -> Vec<String> {
-        self.buffer.iter()
-            .map(|row| row.iter().collect::<String>())
-            .collect()
+    /// Number of lines in scrollback plus the screen
+    pub fn history_len(&self) -> usize {
+        self.scrollback.len() + TERM_ROWS
+    }
+
+    /// A line of the history: scrollback first, then the screen
+    pub fn history_line(&self, idx: usize) -> String {
+        match idx.checked_sub(self.scrollback.len()) {
+            Some(row) => self.buffer[row].iter().collect(),
+            None => self.scrollback[idx].iter().collect(),
+        }
+    }
+
+    /// The visible screen, top row first
+    pub fn screen(&self) -> impl Iterator<Item = String> + '_ {
+        self.buffer.iter().map(|row| row.iter().collect())
+    }
+
+    /// Find `needle` starting at history line `start` and moving towards
+    /// older (backward) or newer lines. Returns the line and column.
+    pub fn search(&self, needle: &str, start: usize, backward: bool) -> Option<(usize, usize)> {
+        if needle.is_empty() || start >= self.history_len() {
+            return None;
+        }
+        let find = |idx: usize| {
+            let line = self.history_line(idx);
+            line.find(needle).map(|byte| (idx, line[..byte].chars().count()))
+        };
+        if backward {
+            (0..=start).rev().find_map(find)
+        } else {
+            (start..self.history_len()).find_map(find)
+        }
     }
 
     pub fn cursor_position(&self) -> (usize, usize) {
@@ -272,57 +413,238 @@ pub enum RunState {
     Halted,
 }
 
+/// Scrollback search state
+pub struct TermSearch {
+    pub query: String,
+    pub editing: bool,               // Query is still being typed
+    pub hit: Option<(usize, usize)>, // History line and column of the current match
+}
+
+/// How register, stack and memory values are displayed
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum NumberMode {
+    Hex,
+    Unsigned,
+    Signed,
+}
+
+impl NumberMode {
+    fn next(self) -> Self {
+        match self {
+            NumberMode::Hex => NumberMode::Unsigned,
+            NumberMode::Unsigned => NumberMode::Signed,
+            NumberMode::Signed => NumberMode::Hex,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            NumberMode::Hex => "hex",
+            NumberMode::Unsigned => "dec",
+            NumberMode::Signed => "signed",
+        }
+    }
+}
+
+/// Format a 16-bit value for display, padded to a constant width per mode
+pub fn format_word(val: u16, mode: NumberMode) -> String {
+    match mode {
+        NumberMode::Hex => format!("{:04X}", val),
+        NumberMode::Unsigned => format!("{:>5}", val),
+        NumberMode::Signed => format!("{:>6}", val as i16),
+    }
+}
+
+/// Value an overlay prompt is asking for
+#[derive(Clone, Copy, PartialEq)]
+pub enum PromptKind {
+    Speed,
+    Device,
+    Command,
+}
+
+/// Single-line input box drawn over the UI
+pub struct Prompt {
+    pub kind: PromptKind,
+    pub input: String,
+    pub error: Option<String>,
+}
+
+impl Prompt {
+    pub fn new(kind: PromptKind) -> Self {
+        Self { kind, input: String::new(), error: None }
+    }
+
+    fn title(&self) -> &'static str {
+        match self.kind {
+            PromptKind::Speed => " Speed: cycles/frame or MHz (e.g. 2.5mhz) ",
+            PromptKind::Device => " Device: attach|detach|reset <name> ",
+            PromptKind::Command => " Command (help lists them) ",
+        }
+    }
+}
+
+/// Parse a typed speed, either cycles per frame or a rate in MHz, and
+/// clamp it to the supported range
+pub fn parse_speed(input: &str) -> Result<usize, String> {
+    let input = input.trim().to_lowercase();
+    let cycles = if let Some(mhz) = input.strip_suffix("mhz") {
+        let mhz: f64 = mhz.trim().parse().map_err(|_| format!("Invalid MHz value: {}", mhz.trim()))?;
+        mhz * 1_000_000.0 * TICK_RATE_MS as f64 / 1000.0
+    } else {
+        input.parse::<u64>().map_err(|_| format!("Invalid cycle count: {}", input))? as f64
+    };
+    if !cycles.is_finite() || cycles <= 0.0 {
+        return Err("Speed must be positive".to_string());
+    }
+    Ok((cycles.round() as usize).clamp(MIN_CYCLES_PER_FRAME, MAX_CYCLES_PER_FRAME))
+}
+
+/// Apply a typed `attach|detach|reset <name>` device command
+pub fn device_command(cpu: &mut Cpu, input: &str) -> Result<(), String> {
+    let mut words = input.split_whitespace();
+    let (Some(verb), Some(name), None) = (words.next(), words.next(), words.next()) else {
+        return Err("Expected attach|detach|reset <name>".to_string());
+    };
+    let name = name.to_lowercase();
+    match verb.to_lowercase().as_str() {
+        "attach" => {
+            let dev = device::create(&name).ok_or_else(|| {
+                format!("Unknown device {} (known: {})", name, DEVICE_NAMES.join(", "))
+            })?;
+            cpu.attach_device(dev)
+        }
+        "detach" => cpu.detach_device(&name).map(|_| ())
+            .ok_or_else(|| format!("{} is not attached", name)),
+        "reset" if cpu.reset_device(&name) => Ok(()),
+        "reset" => Err(format!("{} is not attached", name)),
+        _ => Err(format!("Unknown command: {}", verb)),
+    }
+}
+
+/// One selectable sidebar row: a breakpoint or a symbol
+pub struct SidebarEntry {
+    pub addr: u16,
+    pub name: String, // Symbol at the address, if any
+    pub breakpoint: bool,
+}
+
+/// Breakpoints first, then every symbol. Rebuilt for each frame and key,
+/// so it follows breakpoints set or cleared with `:break`.
+pub fn sidebar_entries(cpu: &Cpu, symbols: &Symbols) -> Vec<SidebarEntry> {
+    let breakpoints = cpu.breakpoints().map(|addr| SidebarEntry {
+        addr,
+        name: symbols.name_at(addr).unwrap_or("").to_string(),
+        breakpoint: true,
+    });
+    let symbols = symbols.iter().map(|(addr, name)| SidebarEntry {
+        addr: *addr,
+        name: name.clone(),
+        breakpoint: false,
+    });
+    breakpoints.chain(symbols).collect()
+}
+
 /// Application state
 pub struct App {
     pub run_state: RunState,
     pub cycles_per_frame: usize,
     pub memory_view_addr: u16,
+    pub disasm_addr: Option<u16>, // Disassembly view centered here instead of on PC
+    pub sidebar: bool,
+    pub sidebar_selected: usize,
+    pub symbols: Symbols,
+    pub number_mode: NumberMode,
     pub terminal: TerminalEmulator,
+    pub term_scroll: usize, // Lines scrolled back into history (0 = live view)
+    pub search: Option<TermSearch>,
+    pub prompt: Option<Prompt>,
+    pub error: Option<String>, // Last CPU error, shown until dismissed
+    pub message: Option<String>, // Command result, shown in the status bar until the next key
     pub output_buffer: VecDeque<u8>,
     #[allow(dead_code)]
     pub input_buffer: VecDeque<u8>,
     pub cursor_blink: bool,
     pub last_blink: Instant,
     pub effective_mhz: f64,
+    pub cpi: f64,  // Average cycles per instruction over the last interval
+    pub mips: f64, // Instructions per second, in millions
     pub host_cpu_percent: f32,
     pub host_memory_mb: u64,
     pub last_metrics_update: Instant,
     #[allow(dead_code)]
     pub cycles_this_second: u64,
     pub last_cycle_count: u64,
+    pub last_instruction_count: u64,
     pub system: System,
     pub start_pc: u16,
+    pub checkpoint: Option<Snapshot>, // State at the last breakpoint stop, for `:restore`
+    pub clock: Box<dyn Clock>,        // Time for the metrics and cursor blink
 }
 
 impl App {
-    pub fn new(start_pc: u16) -> Self {
+    pub fn new(start_pc: u16, symbols: Symbols) -> Self {
+        Self::with_clock(start_pc, symbols, Box::new(SystemClock))
+    }
+
+    pub fn with_clock(start_pc: u16, symbols: Symbols, clock: Box<dyn Clock>) -> Self {
+        let now = clock.now();
         Self {
             run_state: RunState::Paused,
             cycles_per_frame: DEFAULT_CYCLES_PER_FRAME,
             memory_view_addr: 0x0100,
+            disasm_addr: None,
+            sidebar: false,
+            sidebar_selected: 0,
+            symbols,
+            number_mode: NumberMode::Hex,
             terminal: TerminalEmulator::new(),
+            term_scroll: 0,
+            search: None,
+            prompt: None,
+            error: None,
+            message: None,
             output_buffer: VecDeque::new(),
             input_buffer: VecDeque::new(),
             cursor_blink: true,
-            last_blink: Instant::now(),
+            last_blink: now,
             effective_mhz: 0.0,
+            cpi: 0.0,
+            mips: 0.0,
             host_cpu_percent: 0.0,
             host_memory_mb: 0,
-            last_metrics_update: Instant::now(),
+            last_metrics_update: now,
             cycles_this_second: 0,
             last_cycle_count: 0,
+            last_instruction_count: 0,
             system: System::new_all(),
             start_pc,
+            checkpoint: None,
+            clock,
         }
     }
 
+    /// Stop on an error from the instruction at `pc`, keeping its description
+    pub fn record_error(&mut self, cpu: &Cpu, pc: u16, err: CpuError) {
+        let word = u16::from_le_bytes([cpu.read_memory(pc), cpu.read_memory(pc.wrapping_add(1))]);
+        self.error = Some(format!("{}\nat PC 0x{:04X}, instruction word 0x{:04X}", err, pc, word));
+        self.run_state = RunState::Paused;
+    }
+
     pub fn update_metrics(&mut self, cpu: &Cpu) {
-        let now = Instant::now();
-        if now.duration_since(self.last_metrics_update) >= Duration::from_millis(500) {
+        let now = self.clock.now();
+        let elapsed = now.duration_since(self.last_metrics_update);
+        if elapsed >= Duration::from_millis(500) {
+            // Counts restart on reset, so a smaller count is a fresh start
             let cycles_now = cpu.get_cycles();
-            let delta = cycles_now - self.last_cycle_count;
-            self.effective_mhz = (delta as f64) / 500_000.0; // MHz
+            let instructions_now = cpu.get_instructions();
+            (self.effective_mhz, self.cpi, self.mips) = rates(
+                cycles_now.saturating_sub(self.last_cycle_count),
+                instructions_now.saturating_sub(self.last_instruction_count),
+                elapsed.as_secs_f64(),
+            );
             self.last_cycle_count = cycles_now;
+            self.last_instruction_count = instructions_now;
 
             self.system.refresh_cpu_all();
             self.system.refresh_memory();
@@ -354,159 +676,8 @@ impl App {
     }
 }
 
-/// Disassemble a single Sampo instruction
-pub fn disassemble(cpu: &Cpu, addr: u16) -> (String, u16) {
-    let lo = cpu.read_memory(addr);
-    let hi = cpu.read_memory(addr.wrapping_add(1));
-    let instr = u16::from_le_bytes([lo, hi]);
-
-    let opcode = (instr >> 12) & 0xF;
-    let rd = (instr >> 8) & 0xF;
-    let rs1 = (instr >> 4) & 0xF;
-    let rs2 = instr & 0xF;
-    let imm8 = (instr & 0xFF) as i8;
-    let func = instr & 0xF;
-
-    let (mnemonic, size) = match opcode {
-        0x0 => (format!("ADD R{}, R{}, R{}", rd, rs1, rs2), 2),
-        0x1 => (format!("SUB R{}, R{}, R{}", rd, rs1, rs2), 2),
-        0x2 => (format!("AND R{}, R{}, R{}", rd, rs1, rs2), 2),
-        0x3 => (format!("OR R{}, R{}, R{}", rd, rs1, rs2), 2),
-        0x4 => (format!("XOR R{}, R{}, R{}", rd, rs1, rs2), 2),
-        0x5 => (format!("ADDI R{}, {}", rd, imm8), 2),
-        0x6 => {
-            match func {
-                0x0 => (format!("LW R{}, (R{})", rd, rs1), 2),
-                0x1 => (format!("LB R{}, (R{})", rd, rs1), 2),
-                0x2 => (format!("LBU R{}, (R{})", rd, rs1), 2),
-                0x8 => (format!("LUI R{}, 0x{:02X}", rd, rs1 << 4), 2),
-                _ => (format!("LOAD R{}, (R{}) f={}", rd, rs1, func), 2),
-            }
-        }
-        0x7 => {
-            match func {
-                0x0 => (format!("SW R{}, (R{})", rd, rs1), 2),
-                0x1 => (format!("SB R{}, (R{})", rd, rs1), 2),
-                _ => (format!("STORE R{}, (R{}) f={}", rd, rs1, func), 2),
-            }
-        }
-        0x8 => {
-            let cond_str = match rd {
-                0x0 => "EQ", 0x1 => "NE", 0x2 => "LT", 0x3 => "GE",
-                0x4 => "LTU", 0x5 => "GEU", 0x6 => "MI", 0x7 => "PL",
-                _ => "??",
-            };
-            (format!("B{} {:+}", cond_str, imm8 * 2), 2)
-        }
-        0x9 => {
-            if (instr & 0x0F0F) == 0x0F00 {
-                (format!("JR R{}", rs1), 2)
-            } else if func == 0x1 && rd != 0 {
-                (format!("JALR R{}, R{}", rd, rs1), 2)
-            } else {
-                let offset = (instr & 0x0FFF) as i16;
-                let offset = if offset & 0x800 != 0 { offset | 0xF000u16 as i16 } else { offset };
-                (format!("J {:+}", offset * 2), 2)
-            }
-        }
-        0xA => {
-            let shift_name = match func {
-                0x0 => "SLL1", 0x1 => "SRL1", 0x2 => "SRA1",
-                0x3 => "ROL1", 0x4 => "ROR1", 0x7 => "SWAP",
-                0x8 => "SLL4", 0x9 => "SRL4", 0xA => "SRA4",
-                0xC => "SLL8", 0xD => "SRL8", 0xE => "SRA8",
-                _ => "SHIFT",
-            };
-            (format!("{} R{}, R{}", shift_name, rd, rs1), 2)
-        }
-        0xB => {
-            let op = match func {
-                0x0 => "MUL", 0x1 => "MULH", 0x2 => "MULHU",
-                0x3 => "DIV", 0x4 => "DIVU", 0x5 => "REM",
-                0x6 => "REMU", 0x7 => "DAA",
-                _ => "MULDIV",
-            };
-            (format!("{} R{}, R{}", op, rd, rs1), 2)
-        }
-        0xC => {
-            let op = match func {
-                0x0 => format!("PUSH R{}", rs1),
-                0x1 => format!("POP R{}", rd),
-                0x2 => format!("CMP R{}, R{}", rd, rs1),
-                0x3 => format!("TEST R{}, R{}", rd, rs1),
-                0x4 => format!("MOV R{}, R{}", rd, rs1),
-                0x5 => "LDI".to_string(),
-                0x6 => "LDD".to_string(),
-                0x7 => "LDIR".to_string(),
-                0x8 => "LDDR".to_string(),
-                0x9 => "CPIR".to_string(),
-                0xA => "FILL".to_string(),
-                0xB => "EXX".to_string(),
-                0xC => format!("GETF R{}", rd),
-                0xD => format!("SETF R{}", rs1),
-                _ => format!("MISC f={}", func),
-            };
-            (op, 2)
-        }
-        0xD => {
-            let op = match func {
-                0x0 => format!("INI R{}, 0x{:X}", rd, rs1),
-                0x1 => format!("OUTI 0x{:X}, R{}", rs1, rd),
-                0x2 => format!("IN R{}, (R{})", rd, rs1),
-                0x3 => format!("OUT (R{}), R{}", rd, rs1),
-                _ => format!("I/O f={}", func),
-            };
-            (op, 2)
-        }
-        0xE => {
-            let op = match rd {
-                0x0 => "NOP".to_string(),
-                0x1 => "HALT".to_string(),
-                0x2 => "DI".to_string(),
-                0x3 => "EI".to_string(),
-                0x4 => "RETI".to_string(),
-                0x5 => format!("SWI 0x{:02X}", instr & 0xFF),
-                0x6 => "SCF".to_string(),
-                0x7 => "CCF".to_string(),
-                _ => format!("SYS f={}", rd),
-            };
-            (op, 2)
-        }
-        0xF => {
-            // Extended instruction - need to read imm16
-            let lo2 = cpu.read_memory(addr.wrapping_add(2));
-            let hi2 = cpu.read_memory(addr.wrapping_add(3));
-            let imm16 = u16::from_le_bytes([lo2, hi2]);
-
-            let op = match func {
-                0x0 => format!("ADDIX R{}, R{}, 0x{:04X}", rd, rs1, imm16),
-                0x1 => format!("SUBIX R{}, R{}, 0x{:04X}", rd, rs1, imm16),
-                0x2 => format!("ANDIX R{}, R{}, 0x{:04X}", rd, rs1, imm16),
-                0x3 => format!("ORIX R{}, R{}, 0x{:04X}", rd, rs1, imm16),
-                0x4 => format!("XORIX R{}, R{}, 0x{:04X}", rd, rs1, imm16),
-                0x5 => format!("LWX R{}, 0x{:04X}(R{})", rd, imm16, rs1),
-                0x6 => format!("SWX R{}, 0x{:04X}(R{})", rd, imm16, rs1),
-                0x7 => format!("LIX R{}, 0x{:04X}", rd, imm16),
-                0x8 => format!("JX 0x{:04X}", imm16),
-                0x9 => format!("JALX R{}, 0x{:04X}", rd, imm16),
-                0xA => format!("CMPIX R{}, 0x{:04X}", rd, imm16),
-                0xB => format!("INX R{}, 0x{:02X}", rd, imm16 as u8),
-                0xC => format!("OUTX 0x{:02X}, R{}", imm16 as u8, rs1),
-                0xD => format!("SLLX R{}, R{}, {}", rd, rs1, imm16 & 0xF),
-                0xE => format!("SRLX R{}, R{}, {}", rd, rs1, imm16 & 0xF),
-                0xF => format!("SRAX R{}, R{}, {}", rd, rs1, imm16 & 0xF),
-                _ => format!("EXT sub={}", func),
-            };
-            (op, 4)
-        }
-        _ => (format!("??? {:04X}", instr), 2),
-    };
-
-    (mnemonic, size)
-}
-
 /// Render the registers panel
-fn render_registers(f: &mut Frame, area: Rect, cpu: &Cpu) {
+fn render_registers(f: &mut Frame, area: Rect, cpu: &Cpu, mode: NumberMode) {
     let flags = cpu.get_flags();
 
     let mut lines = vec![
@@ -528,6 +699,13 @@ fn render_registers(f: &mut Frame, area: Rect, cpu: &Cpu) {
                 ),
                 Style::default().fg(Color::Cyan)
             ),
+            Span::raw("  "),
+            Span::styled("Bank ", Style::default().fg(Color::DarkGray)),
+            if cpu.alt_bank_active() {
+                Span::styled("alt", Style::default().fg(Color::Magenta).add_modifier(Modifier::BOLD))
+            } else {
+                Span::styled("main", Style::default().fg(Color::White))
+            },
         ]),
     ];
 
@@ -559,13 +737,20 @@ fn render_registers(f: &mut Frame, area: Rect, cpu: &Cpu) {
                 spans.push(Span::raw(" "));
             }
             spans.push(Span::styled(format!("{:7}", name), Style::default().fg(Color::DarkGray)));
-            spans.push(Span::styled(format!("{:04X}", cpu.get_register(r)), Style::default().fg(Color::White)));
+            spans.push(Span::styled(format_word(cpu.get_register(r), mode), Style::default().fg(Color::White)));
         }
         lines.push(Line::from(spans));
     }
 
+    // The bank EXX would swap back in, always in hex to fit one row
+    let other: Vec<String> = (4..=11).map(|r| format!("{:04X}", cpu.get_alt_register(r))).collect();
+    lines.push(Line::from(vec![
+        Span::styled("EXX R4-11 ", Style::default().fg(Color::DarkGray)),
+        Span::styled(other.join(" "), Style::default().fg(Color::DarkGray)),
+    ]));
+
     let block = Block::default()
-        .title(" Registers ")
+        .title(format!(" Registers ({}) ", mode.label()))
         .borders(Borders::ALL)
         .border_style(Style::default().fg(Color::Cyan));
 
@@ -574,12 +759,12 @@ fn render_registers(f: &mut Frame, area: Rect, cpu: &Cpu) {
 }
 
 /// Render the disassembly panel
-fn render_disassembly(f: &mut Frame, area: Rect, cpu: &Cpu) {
+fn render_disassembly(f: &mut Frame, area: Rect, cpu: &Cpu, view_addr: Option<u16>) {
     let pc = cpu.get_pc();
     let mut lines = vec![];
 
-    // Show instructions before and after PC
-    let mut addr = pc.saturating_sub(8);
+    // Show instructions before and after PC, or the address jumped to
+    let mut addr = view_addr.unwrap_or(pc).saturating_sub(8);
     let visible_lines = area.height.saturating_sub(2) as usize;
 
     for _ in 0..visible_lines {
@@ -592,7 +777,11 @@ fn render_disassembly(f: &mut Frame, area: Rect, cpu: &Cpu) {
         }
 
         let is_current = addr == pc;
-        let marker = if is_current { ">" } else { " " };
+        let marker = match (is_current, cpu.is_breakpoint(addr)) {
+            (true, _) => ">",
+            (false, true) => "*",
+            (false, false) => " ",
+        };
 
         let style = if is_current {
             Style::default().fg(Color::White).add_modifier(Modifier::BOLD)
@@ -620,7 +809,7 @@ fn render_disassembly(f: &mut Frame, area: Rect, cpu: &Cpu) {
 }
 
 /// Render the memory viewer
-fn render_memory(f: &mut Frame, area: Rect, cpu: &Cpu, view_addr: u16) {
+fn render_memory(f: &mut Frame, area: Rect, cpu: &Cpu, view_addr: u16, mode: NumberMode) {
     let mut lines = vec![];
     let visible_lines = area.height.saturating_sub(2) as usize;
 
@@ -634,8 +823,20 @@ fn render_memory(f: &mut Frame, area: Rect, cpu: &Cpu, view_addr: u16) {
         let mut ascii = String::new();
         for col in 0..16 {
             let byte = cpu.read_memory(addr.wrapping_add(col));
-            hex_spans.push(Span::styled(format!("{:02X} ", byte), Style::default().fg(Color::White)));
-            ascii.push(if byte >= 0x20 && byte < 0x7F { byte as char } else { '.' });
+            ascii.push(if (0x20..0x7F).contains(&byte) { byte as char } else { '.' });
+        }
+
+        if mode == NumberMode::Hex {
+            for col in 0..16 {
+                let byte = cpu.read_memory(addr.wrapping_add(col));
+                hex_spans.push(Span::styled(format!("{:02X} ", byte), Style::default().fg(Color::White)));
+            }
+        } else {
+            // Decimal views show words in the machine's byte order
+            for col in (0..16).step_by(2) {
+                let word = format_word(cpu.peek_word(addr.wrapping_add(col)), mode);
+                hex_spans.push(Span::styled(format!("{} ", word), Style::default().fg(Color::White)));
+            }
         }
 
         hex_spans.push(Span::styled(ascii, Style::default().fg(Color::Yellow)));
@@ -652,23 +853,21 @@ fn render_memory(f: &mut Frame, area: Rect, cpu: &Cpu, view_addr: u16) {
 }
 
 /// Render the stack panel
-fn render_stack(f: &mut Frame, area: Rect, cpu: &Cpu) {
+fn render_stack(f: &mut Frame, area: Rect, cpu: &Cpu, mode: NumberMode) {
     let sp = cpu.get_sp();
     let mut lines = vec![];
     let visible_lines = area.height.saturating_sub(2) as usize;
 
     for i in 0..visible_lines {
         let addr = sp.wrapping_add((i * 2) as u16);
-        let lo = cpu.read_memory(addr);
-        let hi = cpu.read_memory(addr.wrapping_add(1));
-        let val = u16::from_le_bytes([lo, hi]);
+        let val = cpu.peek_word(addr);
 
         let marker = if i == 0 { ">" } else { " " };
 
         lines.push(Line::from(vec![
             Span::styled(marker, Style::default().fg(Color::Green)),
             Span::styled(format!("{:04X}: ", addr), Style::default().fg(Color::DarkGray)),
-            Span::styled(format!("{:04X}", val), Style::default().fg(Color::White)),
+            Span::styled(format_word(val, mode), Style::default().fg(Color::White)),
         ]));
     }
 
@@ -681,38 +880,113 @@ fn render_stack(f: &mut Frame, area: Rect, cpu: &Cpu) {
     f.render_widget(paragraph, area);
 }
 
+/// Name of a port with a known device behind it
+fn port_name(cpu: &Cpu, port: u8) -> Option<&'static str> {
+    let [acia_status, acia_data] = cpu.acia_ports();
+    match port {
+        p if p == acia_status => Some("ACIA_STATUS"),
+        p if p == acia_data => Some("ACIA_DATA"),
+        p if p == TIMER_BASE => Some("TIMER_CTRL"),
+        p if p == TIMER_BASE + 1 => Some("TIMER_LO"),
+        p if p == TIMER_BASE + 2 => Some("TIMER_HI"),
+        RNG_PORT => Some("RNG"),
+        _ => None,
+    }
+}
+
+/// Render device status and the most recent port writes
+fn render_ports(f: &mut Frame, area: Rect, cpu: &Cpu) {
+    let label = Style::default().fg(Color::DarkGray);
+    let value = Style::default().fg(Color::White);
+
+    let mut lines = vec![
+        Line::from(vec![
+            Span::styled(format!("{:6}", "acia"), label),
+            Span::styled(format!("status {:02X}", cpu.acia_status()), value),
+        ]),
+    ];
+    for dev in cpu.devices() {
+        lines.push(Line::from(vec![
+            Span::styled(format!("{:6}", dev.name()), label),
+            Span::styled(dev.status(), value),
+        ]));
+    }
+
+    // Other ports hold the last value written to them
+    let mut latched = vec![Span::styled("Ports ", label)];
+    for (port, &val) in cpu.ports().iter().enumerate().filter(|&(_, &v)| v != 0) {
+        latched.push(Span::styled(format!("{:02X}", port), Style::default().fg(Color::Yellow)));
+        latched.push(Span::styled(format!("={:02X} ", val), value));
+    }
+    lines.push(Line::from(latched));
+
+    // Newest write first
+    let visible = (area.height.saturating_sub(2) as usize).saturating_sub(lines.len());
+    let writes: Vec<&(u8, u8)> = cpu.recent_port_writes().collect();
+    for &&(port, val) in writes.iter().rev().take(visible) {
+        let name = port_name(cpu, port).unwrap_or("");
+        lines.push(Line::from(vec![
+            Span::styled("OUT ", label),
+            Span::styled(format!("{:02X} ", port), Style::default().fg(Color::Yellow)),
+            Span::styled(format!("{:12}", name), Style::default().fg(Color::Cyan)),
+            Span::styled(format!("{:02X}", val), value),
+        ]));
+    }
+
+    let block = Block::default()
+        .title(" I/O ")
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Cyan));
+
+    let paragraph = Paragraph::new(lines).block(block);
+    f.render_widget(paragraph, area);
+}
+
 /// Render the terminal emulator panel
 fn render_terminal(f: &mut Frame, area: Rect, app: &App) {
-    let term_lines = app.terminal.get_lines();
     let (cursor_row, cursor_col) = app.terminal.cursor_position();
+    let top = app.terminal.history_len() - TERM_ROWS - app.term_scroll;
+    let hit = app.search.as_ref().and_then(|s| s.hit.map(|h| (h, s.query.chars().count())));
 
     let mut lines: Vec<Line> = vec![];
 
-    for (row_idx, row) in term_lines.iter().enumerate() {
-        if row_idx == cursor_row && app.terminal.is_cursor_visible() && app.cursor_blink {
-            // Insert cursor
-            let chars: Vec<char> = row.chars().collect();
-            if cursor_col < chars.len() {
+    for row_idx in 0..TERM_ROWS {
+        let row = app.terminal.history_line(top + row_idx);
+        let chars: Vec<char> = row.chars().collect();
+        // Highlight a search match, or the cursor in the live view
+        let mark = match hit {
+            Some(((line, col), len)) if line == top + row_idx => Some((col, len, Color::Yellow)),
+            _ if app.term_scroll == 0 && row_idx == cursor_row
+                && app.terminal.is_cursor_visible() && app.cursor_blink => Some((cursor_col, 1, Color::White)),
+            _ => None,
+        };
+        match mark {
+            Some((col, len, color)) if col < chars.len() => {
+                let end = (col + len).min(chars.len());
                 let mut spans = vec![];
-                spans.push(Span::raw(chars[..cursor_col].iter().collect::<String>()));
+                spans.push(Span::raw(chars[..col].iter().collect::<String>()));
                 spans.push(Span::styled(
-                    chars[cursor_col].to_string(),
-                    Style::default().bg(Color::White).fg(Color::Black),
+                    chars[col..end].iter().collect::<String>(),
+                    Style::default().bg(color).fg(Color::Black),
                 ));
-                if cursor_col + 1 < chars.len() {
-                    spans.push(Span::raw(chars[cursor_col + 1..].iter().collect::<String>()));
+                if end < chars.len() {
+                    spans.push(Span::raw(chars[end..].iter().collect::<String>()));
                 }
                 lines.push(Line::from(spans));
-            } else {
-                lines.push(Line::from(row.clone()));
             }
-        } else {
-            lines.push(Line::from(row.clone()));
+            _ => lines.push(Line::from(row)),
         }
     }
 
+    let title = match &app.search {
+        Some(search) if search.editing => format!(" Terminal /{}_ ", search.query),
+        Some(search) if search.hit.is_none() => format!(" Terminal /{} (no match) ", search.query),
+        Some(search) => format!(" Terminal /{} n/N:Next/Prev Esc:Live ", search.query),
+        None if app.term_scroll > 0 => format!(" Terminal (-{} lines) ", app.term_scroll),
+        None => " Terminal ".to_string(),
+    };
     let block = Block::default()
-        .title(" Terminal ")
+        .title(title)
         .borders(Borders::ALL)
         .border_style(Style::default().fg(Color::Cyan));
 
@@ -720,6 +994,16 @@ fn render_terminal(f: &mut Frame, area: Rect, app: &App) {
     f.render_widget(paragraph, area);
 }
 
+/// Clock rate in MHz, cycles per instruction and millions of instructions
+/// per second for `cycles` and `instructions` run in `secs`
+fn rates(cycles: u64, instructions: u64, secs: f64) -> (f64, f64, f64) {
+    if secs <= 0.0 {
+        return (0.0, 0.0, 0.0);
+    }
+    let cpi = if instructions == 0 { 0.0 } else { cycles as f64 / instructions as f64 };
+    (cycles as f64 / secs / 1e6, cpi, instructions as f64 / secs / 1e6)
+}
+
 /// Render the status bar
 fn render_status(f: &mut Frame, area: Rect, app: &App, cpu: &Cpu) {
     let state_span = match app.run_state {
@@ -733,6 +1017,8 @@ fn render_status(f: &mut Frame, area: Rect, app: &App, cpu: &Cpu) {
         Span::raw(" "),
         Span::styled(format!("{:.2} MHz", app.effective_mhz), Style::default().fg(Color::Cyan)),
         Span::raw("  "),
+        Span::styled(format!("CPI:{:.2} {:.2} MIPS", app.cpi, app.mips), Style::default().fg(Color::Cyan)),
+        Span::raw("  "),
         Span::styled(format!("CPU:{:.0}%", app.host_cpu_percent), Style::default().fg(Color::Gray)),
         Span::raw("  "),
         Span::styled(format!("Mem:{}MB", app.host_memory_mb), Style::default().fg(Color::Gray)),
@@ -740,9 +1026,17 @@ fn render_status(f: &mut Frame, area: Rect, app: &App, cpu: &Cpu) {
         Span::styled(format!("Cycles:{}", cpu.get_cycles()), Style::default().fg(Color::DarkGray)),
         Span::raw("  "),
         Span::styled(
-            "F5:Run F6:Step F7:Pause F8:Reset F12:Quit",
+            format!("RD/WR:{}/{}", cpu.mem_stats().reads(), cpu.mem_stats().writes()),
             Style::default().fg(Color::DarkGray)
         ),
+        Span::raw("  "),
+        match &app.message {
+            Some(message) => Span::styled(message.clone(), Style::default().fg(Color::Yellow)),
+            None => Span::styled(
+                "F1:Side F2:Speed F3:Base F4:Dev F5:Run F6:Step F7:Pause ^C:Break F8:Reset F12:Quit",
+                Style::default().fg(Color::DarkGray)
+            ),
+        },
     ]);
 
     let paragraph = Paragraph::new(vec![line]);
@@ -760,6 +1054,20 @@ fn ui(f: &mut Frame, app: &App, cpu: &Cpu) {
         ])
         .split(f.area());
 
+    // Optional sidebar on the far left
+    let mut content_area = main_chunks[0];
+    if app.sidebar {
+        let sidebar_chunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([
+                Constraint::Length(SIDEBAR_WIDTH),
+                Constraint::Min(0),
+            ])
+            .split(content_area);
+        render_sidebar(f, sidebar_chunks[0], app, cpu);
+        content_area = sidebar_chunks[1];
+    }
+
     // Content area: left (registers + memory) | right (disasm + terminal)
     let content_chunks = Layout::default()
         .direction(Direction::Horizontal)
@@ -767,13 +1075,14 @@ fn ui(f: &mut Frame, app: &App, cpu: &Cpu) {
             Constraint::Percentage(40),
             Constraint::Percentage(60),
         ])
-        .split(main_chunks[0]);
+        .split(content_area);
 
-    // Left panel: registers on top, memory below
+    // Left panel: registers and I/O on top, memory below
     let left_chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
-            Constraint::Length(7),
+            Constraint::Length(8),
+            Constraint::Length(9),
             Constraint::Min(10),
         ])
         .split(content_chunks[0]);
@@ -797,18 +1106,107 @@ fn ui(f: &mut Frame, app: &App, cpu: &Cpu) {
         .split(right_chunks[0]);
 
     // Render all panels
-    render_registers(f, left_chunks[0], cpu);
-    render_memory(f, left_chunks[1], cpu, app.memory_view_addr);
-    render_disassembly(f, upper_right_chunks[0], cpu);
-    render_stack(f, upper_right_chunks[1], cpu);
+    render_registers(f, left_chunks[0], cpu, app.number_mode);
+    render_ports(f, left_chunks[1], cpu);
+    render_memory(f, left_chunks[2], cpu, app.memory_view_addr, app.number_mode);
+    render_disassembly(f, upper_right_chunks[0], cpu, app.disasm_addr);
+    render_stack(f, upper_right_chunks[1], cpu, app.number_mode);
     render_terminal(f, right_chunks[1], app);
     render_status(f, main_chunks[1], app, cpu);
+    if let Some(error) = &app.error {
+        render_error(f, error);
+    }
+    if let Some(prompt) = &app.prompt {
+        render_prompt(f, prompt);
+    }
+}
+
+/// Render the breakpoint and symbol sidebar, scrolled to the selection
+fn render_sidebar(f: &mut Frame, area: Rect, app: &App, cpu: &Cpu) {
+    let entries = sidebar_entries(cpu, &app.symbols);
+    let visible_lines = area.height.saturating_sub(2) as usize;
+    let selected = app.sidebar_selected.min(entries.len().saturating_sub(1));
+    let first = (selected + 1).saturating_sub(visible_lines);
+
+    let mut lines: Vec<Line> = entries.iter().enumerate().skip(first).take(visible_lines)
+        .map(|(i, entry)| {
+            let marker = if entry.breakpoint { "*" } else { " " };
+            let mut style = Style::default().fg(if entry.breakpoint { Color::Red } else { Color::Gray });
+            if i == selected {
+                style = style.add_modifier(Modifier::REVERSED);
+            }
+            Line::from(Span::styled(format!("{}{:04X} {}", marker, entry.addr, entry.name), style))
+        })
+        .collect();
+    if entries.is_empty() {
+        lines.push(Line::from(Span::styled("No breakpoints or symbols", Style::default().fg(Color::DarkGray))));
+    }
+
+    let block = Block::default()
+        .title(" Breaks/Symbols ")
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Cyan));
+
+    f.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+/// Render a CPU error in a red box centered on the screen
+fn render_error(f: &mut Frame, error: &str) {
+    let screen = f.area();
+    let width = 60.min(screen.width);
+    let height = 6.min(screen.height);
+    let area = Rect::new(
+        screen.x + (screen.width - width) / 2,
+        screen.y + (screen.height - height) / 2,
+        width,
+        height,
+    );
+
+    let mut lines: Vec<Line> = error.lines()
+        .map(|l| Line::from(Span::styled(l.to_string(), Style::default().fg(Color::Red).add_modifier(Modifier::BOLD))))
+        .collect();
+    lines.push(Line::from(Span::styled("Esc to dismiss, F8 to reset", Style::default().fg(Color::DarkGray))));
+
+    let block = Block::default()
+        .title(" CPU Error ")
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Red));
+
+    f.render_widget(Clear, area);
+    f.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+/// Render an overlay prompt centered on the screen
+fn render_prompt(f: &mut Frame, prompt: &Prompt) {
+    let screen = f.area();
+    let width = 50.min(screen.width);
+    let height = 4.min(screen.height);
+    let area = Rect::new(
+        screen.x + (screen.width - width) / 2,
+        screen.y + (screen.height - height) / 2,
+        width,
+        height,
+    );
+
+    let mut lines = vec![Line::from(format!("> {}_", prompt.input))];
+    if let Some(error) = &prompt.error {
+        lines.push(Line::from(Span::styled(error.clone(), Style::default().fg(Color::Red))));
+    }
+
+    let block = Block::default()
+        .title(prompt.title())
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Yellow));
+
+    f.render_widget(Clear, area);
+    f.render_widget(Paragraph::new(lines).block(block), area);
 }
 
 /// Run the TUI emulator
-pub fn run_tui(cpu: &mut Cpu) -> io::Result<()> {
+pub fn run_tui(cpu: &mut Cpu, symbols: Symbols) -> io::Result<()> {
     // Suppress direct stdout output in TUI mode
     cpu.set_quiet(true);
+    cpu.set_trap_stop(true);
 
     // Setup terminal
     enable_raw_mode()?;
@@ -817,7 +1215,7 @@ pub fn run_tui(cpu: &mut Cpu) -> io::Result<()> {
     let mut terminal = Terminal::new(backend)?;
 
     let start_pc = cpu.get_pc();
-    let mut app = App::new(start_pc);
+    let mut app = App::new(start_pc, symbols);
 
     let tick_rate = Duration::from_millis(TICK_RATE_MS);
 
@@ -841,30 +1239,7 @@ pub fn run_tui(cpu: &mut Cpu) -> io::Result<()> {
         }
 
         // Execute CPU cycles if running
-        if app.run_state == RunState::Running && !cpu.is_halted() {
-            for _ in 0..app.cycles_per_frame {
-                match cpu.step() {
-                    Ok(true) => {
-                        // Check for serial output
-                        let output = cpu.get_serial_output();
-                        if !output.is_empty() {
-                            for &b in output {
-                                app.output_buffer.push_back(b);
-                            }
-                            cpu.clear_serial_output();
-                        }
-                    }
-                    Ok(false) => {
-                        app.run_state = RunState::Halted;
-                        break;
-                    }
-                    Err(_) => {
-                        app.run_state = RunState::Halted;
-                        break;
-                    }
-                }
-            }
-        }
+        run_frame(&mut app, cpu);
 
         // Flush output to terminal emulator
         app.flush_output();
@@ -880,50 +1255,112 @@ pub fn run_tui(cpu: &mut Cpu) -> io::Result<()> {
     Ok(())
 }
 
+/// Run up to one frame's worth of instructions, stopping early on a
+/// pending NMI, a TRAP, a breakpoint, HALT or an error
+fn run_frame(app: &mut App, cpu: &mut Cpu) {
+    if app.run_state == RunState::Running && !cpu.is_halted() {
+        for _ in 0..app.cycles_per_frame {
+            // NMI breaks in at an instruction boundary regardless of FLAG_I
+            if cpu.take_nmi() {
+                app.run_state = RunState::Paused;
+                break;
+            }
+            let pc = cpu.get_pc();
+            match cpu.step() {
+                Ok(true) => {
+                    // Check for serial output
+                    app.output_buffer.extend(cpu.get_serial_output());
+                    cpu.clear_serial_output();
+                    if let Some((code, at)) = cpu.take_trap() {
+                        app.message = Some(format!("TRAP 0x{:02X} at 0x{:04X}", code, at));
+                        app.checkpoint = Some(cpu.snapshot());
+                        app.run_state = RunState::Paused;
+                        break;
+                    }
+                    if cpu.is_breakpoint(cpu.get_pc()) {
+                        app.message = Some(format!("Breakpoint at 0x{:04X}", cpu.get_pc()));
+                        app.checkpoint = Some(cpu.snapshot());
+                        app.run_state = RunState::Paused;
+                        break;
+                    }
+                }
+                Ok(false) => {
+                    app.run_state = RunState::Halted;
+                    break;
+                }
+                Err(e) => {
+                    app.record_error(cpu, pc, e);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Run, returning the terminal to the live view
+fn start_running(app: &mut App) {
+    app.error = None;
+    app.disasm_addr = None;
+    app.run_state = RunState::Running;
+    app.search = None;
+    app.term_scroll = 0;
+}
+
+fn step_instruction(app: &mut App, cpu: &mut Cpu) {
+    app.error = None;
+    app.disasm_addr = None;
+    let pc = cpu.get_pc();
+    match cpu.step() {
+        Ok(true) => {
+            app.output_buffer.extend(cpu.get_serial_output());
+            cpu.clear_serial_output();
+            if let Some((code, at)) = cpu.take_trap() {
+                app.message = Some(format!("TRAP 0x{:02X} at 0x{:04X}", code, at));
+            }
+        }
+        Ok(false) => {
+            app.run_state = RunState::Halted;
+        }
+        Err(e) => {
+            app.record_error(cpu, pc, e);
+        }
+    }
+    app.run_state = RunState::Paused;
+}
+
 /// Handle keyboard input
 /// Returns (continue, needs_clear)
 fn handle_key(app: &mut App, cpu: &mut Cpu, key: KeyEvent) -> io::Result<(bool, bool)> {
+    if app.prompt.is_some() {
+        return Ok((handle_prompt_key(app, cpu, key), false));
+    }
+    if app.search.as_ref().is_some_and(|s| s.editing) {
+        handle_search_key(app, key);
+        return Ok((true, false));
+    }
+
+    app.message = None;
     let mut needs_clear = false;
     match key.code {
-        KeyCode::F(5) => {
-            // Run
-            if !cpu.is_halted() {
-                app.run_state = RunState::Running;
-            }
-        }
-        KeyCode::F(6) => {
-            // Step
-            if !cpu.is_halted() {
-                match cpu.step() {
-                    Ok(true) => {
-                        let output = cpu.get_serial_output();
-                        if !output.is_empty() {
-                            for &b in output {
-                                app.output_buffer.push_back(b);
-                            }
-                            cpu.clear_serial_output();
-                        }
-                    }
-                    Ok(false) => {
-                        app.run_state = RunState::Halted;
-                    }
-                    Err(_) => {
-                        app.run_state = RunState::Halted;
-                    }
-                }
-                app.run_state = RunState::Paused;
-            }
+        KeyCode::Esc if app.error.is_some() => {
+            app.error = None;
         }
+        KeyCode::F(5) if !cpu.is_halted() => start_running(app),
+        KeyCode::F(6) if !cpu.is_halted() => step_instruction(app, cpu),
         KeyCode::F(7) => {
             // Pause
             app.run_state = RunState::Paused;
         }
         KeyCode::F(8) => {
             // Reset
+            app.error = None;
             cpu.reset();
             cpu.set_pc(app.start_pc);
+            app.disasm_addr = None;
             app.run_state = RunState::Paused;
             app.terminal = TerminalEmulator::new();
+            app.search = None;
+            app.term_scroll = 0;
             app.output_buffer.clear();
             needs_clear = true;
         }
@@ -944,71 +1381,428 @@ fn handle_key(app: &mut App, cpu: &mut Cpu, key: KeyEvent) -> io::Result<(bool,
         KeyCode::F(12) => {
             return Ok((false, false)); // Quit
         }
+        KeyCode::F(1) => {
+            app.sidebar = !app.sidebar;
+        }
+        KeyCode::F(2) => {
+            app.prompt = Some(Prompt::new(PromptKind::Speed));
+        }
+        KeyCode::F(3) => {
+            app.number_mode = app.number_mode.next();
+        }
+        KeyCode::F(4) => {
+            app.prompt = Some(Prompt::new(PromptKind::Device));
+        }
         KeyCode::Char('=') if key.modifiers.contains(KeyModifiers::ALT) => {
             // Increase speed
-            app.cycles_per_frame = (app.cycles_per_frame + 10000).min(500000);
+            app.cycles_per_frame = (app.cycles_per_frame + 10000).min(MAX_CYCLES_PER_FRAME);
         }
         KeyCode::Char('-') if key.modifiers.contains(KeyModifiers::ALT) => {
             // Decrease speed
-            app.cycles_per_frame = app.cycles_per_frame.saturating_sub(10000).max(1000);
+            app.cycles_per_frame = app.cycles_per_frame.saturating_sub(10000).max(MIN_CYCLES_PER_FRAME);
+        }
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) && app.run_state == RunState::Running => {
+            // Break into the debugger
+            cpu.raise_nmi();
+        }
+        KeyCode::Up if app.sidebar && app.run_state != RunState::Running => {
+            app.sidebar_selected = app.sidebar_selected.saturating_sub(1);
         }
-        KeyCode::Char(c) => {
+        KeyCode::Down if app.sidebar && app.run_state != RunState::Running => {
+            let len = sidebar_entries(cpu, &app.symbols).len();
+            app.sidebar_selected = (app.sidebar_selected + 1).min(len.saturating_sub(1));
+        }
+        KeyCode::Enter if app.sidebar && app.run_state != RunState::Running => {
+            // Show the selected address in the disassembly
+            let entries = sidebar_entries(cpu, &app.symbols);
+            if let Some(entry) = entries.get(app.sidebar_selected.min(entries.len().saturating_sub(1))) {
+                app.disasm_addr = Some(entry.addr);
+            }
+        }
+        KeyCode::Char(':') if app.run_state != RunState::Running => {
+            app.prompt = Some(Prompt::new(PromptKind::Command));
+        }
+        KeyCode::Char('/') if app.run_state != RunState::Running => {
+            // Search the terminal scrollback
+            app.search = Some(TermSearch { query: String::new(), editing: true, hit: None });
+        }
+        KeyCode::Char('n') if app.run_state != RunState::Running && app.search.is_some() => {
+            search_terminal(app, true);
+        }
+        KeyCode::Char('N') if app.run_state != RunState::Running && app.search.is_some() => {
+            search_terminal(app, false);
+        }
+        KeyCode::Esc if app.run_state != RunState::Running => {
+            // Leave search and return to the live view
+            app.search = None;
+            app.term_scroll = 0;
+        }
+        KeyCode::Char(c) if app.run_state == RunState::Running => {
             // Send character to CPU
-            if app.run_state == RunState::Running {
-                if key.modifiers.contains(KeyModifiers::CONTROL) {
-                    // Ctrl + key
-                    let ctrl_char = (c as u8).wrapping_sub(b'a' - 1);
-                    cpu.send_key(ctrl_char);
-                } else {
-                    cpu.send_key(c as u8);
-                }
+            if key.modifiers.contains(KeyModifiers::CONTROL) {
+                // Ctrl + key
+                let ctrl_char = (c as u8).wrapping_sub(b'a' - 1);
+                cpu.send_key(ctrl_char);
+            } else {
+                cpu.send_key(c as u8);
             }
         }
+        KeyCode::Enter if app.run_state == RunState::Running => {
+            cpu.send_key(0x0D);
+        }
+        KeyCode::Backspace if app.run_state == RunState::Running => {
+            cpu.send_key(0x08);
+        }
+        KeyCode::Esc if app.run_state == RunState::Running => {
+            cpu.send_key(0x1B);
+        }
+        KeyCode::Up if app.run_state == RunState::Running => {
+            cpu.send_key(0x1B);
+            cpu.send_key(b'[');
+            cpu.send_key(b'A');
+        }
+        KeyCode::Down if app.run_state == RunState::Running => {
+            cpu.send_key(0x1B);
+            cpu.send_key(b'[');
+            cpu.send_key(b'B');
+        }
+        KeyCode::Right if app.run_state == RunState::Running => {
+            cpu.send_key(0x1B);
+            cpu.send_key(b'[');
+            cpu.send_key(b'C');
+        }
+        KeyCode::Left if app.run_state == RunState::Running => {
+            cpu.send_key(0x1B);
+            cpu.send_key(b'[');
+            cpu.send_key(b'D');
+        }
+        _ => {}
+    }
+
+    Ok((true, needs_clear))
+}
+
+/// Edit the scrollback search query; Enter searches from the newest line
+fn handle_search_key(app: &mut App, key: KeyEvent) {
+    let Some(search) = app.search.as_mut() else {
+        return;
+    };
+    match key.code {
+        KeyCode::Char(c) => search.query.push(c),
+        KeyCode::Backspace => {
+            search.query.pop();
+        }
         KeyCode::Enter => {
-            if app.run_state == RunState::Running {
-                cpu.send_key(0x0D);
-            }
+            search.editing = false;
+            search_terminal(app, true);
         }
+        KeyCode::Esc => app.search = None,
+        _ => {}
+    }
+}
+
+/// Move to the next match towards older (backward) or newer lines and
+/// scroll the terminal so it is visible
+fn search_terminal(app: &mut App, backward: bool) {
+    let Some(search) = app.search.as_mut() else {
+        return;
+    };
+    let total = app.terminal.history_len();
+    let start = match search.hit {
+        Some((line, _)) if backward => match line.checked_sub(1) {
+            Some(prev) => prev,
+            None => return,
+        },
+        Some((line, _)) => line + 1,
+        None => total - 1,
+    };
+    if let Some(hit) = app.terminal.search(&search.query, start, backward) {
+        search.hit = Some(hit);
+        // Put the match on the top row unless it is already on the live screen
+        app.term_scroll = (total - TERM_ROWS).saturating_sub(hit.0);
+    }
+}
+
+/// Edit the overlay prompt; Enter applies the value, Esc cancels.
+/// Returns false when a command asks to quit.
+fn handle_prompt_key(app: &mut App, cpu: &mut Cpu, key: KeyEvent) -> bool {
+    let Some(prompt) = app.prompt.as_mut() else {
+        return true;
+    };
+    match key.code {
+        KeyCode::Char(c) => prompt.input.push(c),
         KeyCode::Backspace => {
-            if app.run_state == RunState::Running {
-                cpu.send_key(0x08);
-            }
+            prompt.input.pop();
         }
-        KeyCode::Esc => {
-            if app.run_state == RunState::Running {
-                cpu.send_key(0x1B);
+        KeyCode::Esc => app.prompt = None,
+        KeyCode::Enter => {
+            let input = prompt.input.clone();
+            let result = match prompt.kind {
+                PromptKind::Speed => parse_speed(&input).map(|cycles| app.cycles_per_frame = cycles),
+                PromptKind::Device => device_command(cpu, &input),
+                PromptKind::Command => match command::parse(&input) {
+                    Ok(Some(Command::Quit)) => return false,
+                    Ok(Some(cmd)) => run_command(app, cpu, cmd),
+                    Ok(None) => Ok(()),
+                    Err(e) => Err(e),
+                },
+            };
+            match result {
+                Ok(()) => app.prompt = None,
+                Err(e) => {
+                    if let Some(prompt) = app.prompt.as_mut() {
+                        prompt.error = Some(e);
+                    }
+                }
             }
         }
-        KeyCode::Up => {
-            if app.run_state == RunState::Running {
-                cpu.send_key(0x1B);
-                cpu.send_key(b'[');
-                cpu.send_key(b'A');
-            }
+        _ => {}
+    }
+    true
+}
+
+/// Carry out a `:` command line, leaving any result in the status bar
+fn run_command(app: &mut App, cpu: &mut Cpu, cmd: Command) -> Result<(), String> {
+    if let Some(result) = command::apply(cpu, &cmd) {
+        if let Command::Load(_, None) = cmd {
+            // A new program: F8 restarts it
+            app.start_pc = cpu.get_pc();
         }
-        KeyCode::Down => {
-            if app.run_state == RunState::Running {
-                cpu.send_key(0x1B);
-                cpu.send_key(b'[');
-                cpu.send_key(b'B');
-            }
+        app.message = Some(result?);
+        return Ok(());
+    }
+    match cmd {
+        Command::Step if !cpu.is_halted() => step_instruction(app, cpu),
+        Command::Run if !cpu.is_halted() => start_running(app),
+        Command::Step | Command::Run => return Err("CPU is halted (F8 resets)".to_string()),
+        Command::Mem(addr) => app.memory_view_addr = addr.unwrap_or(cpu.get_pc()),
+        Command::Restore => {
+            let snap = app.checkpoint.as_ref()
+                .ok_or("No checkpoint: nothing has stopped at a breakpoint yet")?;
+            // The terminal keeps what was already printed
+            cpu.restore(snap);
+            app.error = None;
+            app.disasm_addr = None;
+            app.run_state = RunState::Paused;
+            app.message = Some(format!("Restored the checkpoint at 0x{:04X}", snap.pc()));
         }
-        KeyCode::Right => {
-            if app.run_state == RunState::Running {
-                cpu.send_key(0x1B);
-                cpu.send_key(b'[');
-                cpu.send_key(b'C');
-            }
+        Command::Help => {
+            let names: Vec<&str> = command::HELP.iter().map(|(usage, _)| *usage).collect();
+            app.message = Some(names.join(" | "));
         }
-        KeyCode::Left => {
-            if app.run_state == RunState::Running {
-                cpu.send_key(0x1B);
-                cpu.send_key(b'[');
-                cpu.send_key(b'D');
-            }
+        _ => return Err("Not available in the TUI; the panels show it".to_string()),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::cpu::test_util::machine;
+    use crate::cpu::CpuConfig;
+
+    /// An app and CPU with `code` at RESET_PC, on a manual clock
+    fn tui_machine(code: &[u16]) -> (App, Cpu) {
+        let cpu = machine(code);
+        let app = App::with_clock(cpu.get_pc(), Symbols::default(), Box::new(ManualClock::new()));
+        (app, cpu)
+    }
+
+    fn ctrl(c: char) -> KeyEvent {
+        KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL)
+    }
+
+    #[test]
+    fn ctrl_c_breaks_in_at_an_instruction_boundary() {
+        // DI; loop: ADDI R4, 1; J loop
+        let (mut app, mut cpu) = tui_machine(&[0xE200, 0x5401, 0x9FFE]);
+        app.cycles_per_frame = 7;
+        start_running(&mut app);
+        run_frame(&mut app, &mut cpu);
+        assert!(app.run_state == RunState::Running);
+        let before = (cpu.get_pc(), cpu.get_register(4), cpu.get_instructions());
+
+        // Interrupts are off, but the NMI still stops the next frame before it steps
+        handle_key(&mut app, &mut cpu, ctrl('c')).unwrap();
+        run_frame(&mut app, &mut cpu);
+        assert!(app.run_state == RunState::Paused);
+        assert_eq!((cpu.get_pc(), cpu.get_register(4), cpu.get_instructions()), before);
+
+        // While paused Ctrl+C raises nothing, so running again is not cut short
+        handle_key(&mut app, &mut cpu, ctrl('c')).unwrap();
+        start_running(&mut app);
+        run_frame(&mut app, &mut cpu);
+        assert!(app.run_state == RunState::Running);
+        assert_eq!(cpu.get_instructions(), before.2 + 7);
+    }
+
+    #[test]
+    fn illegal_instruction_pauses_with_the_error_shown() {
+        // ADDI R4, 1; then muldiv func 8, which doesn't exist
+        let (mut app, mut cpu) = tui_machine(&[0x5401, 0xB458]);
+        start_running(&mut app);
+        run_frame(&mut app, &mut cpu);
+        assert!(app.run_state == RunState::Paused);
+        assert_eq!(
+            app.error.as_deref(),
+            Some("Unknown muldiv func: 0x8\nat PC 0x0102, instruction word 0xB458")
+        );
+        assert_eq!(cpu.get_register(4), 1);
+        // Esc dismisses it; a clean run never sets one
+        handle_key(&mut app, &mut cpu, KeyEvent::from(KeyCode::Esc)).unwrap();
+        assert_eq!(app.error, None);
+        let (mut app, mut cpu) = tui_machine(&[0x5401, 0xE100]);
+        start_running(&mut app);
+        run_frame(&mut app, &mut cpu);
+        assert!(app.run_state == RunState::Halted);
+        assert_eq!(app.error, None);
+    }
+
+    fn terminal(bytes: &[u8]) -> TerminalEmulator {
+        let mut term = TerminalEmulator::new();
+        for &b in bytes {
+            term.putchar(b);
         }
-        _ => {}
+        term
     }
 
-    Ok((true, needs_clear))
+    #[test]
+    fn tab_stops_can_be_set_and_cleared() {
+        assert_eq!(terminal(b"ab\t").cursor_position(), (0, 8));
+        // ESC H at column 3 adds a stop there
+        assert_eq!(terminal(b"abc\x1bH\r\t").cursor_position(), (0, 3));
+        // CSI g clears the stop under the cursor, CSI 3 g clears them all
+        assert_eq!(terminal(b"\t\x1b[g\r\t").cursor_position(), (0, 16));
+        assert_eq!(terminal(b"\x1b[3g\t").cursor_position(), (0, TERM_COLS - 1));
+    }
+
+    #[test]
+    fn search_finds_text_scrolled_off_the_screen() {
+        let output: String = (0..30).map(|n| format!("line {}\r\n", n)).collect();
+        let (mut app, _) = tui_machine(&[]);
+        app.terminal = terminal(output.as_bytes());
+        // Lines 0-6 have scrolled off; line 7 is the top of the screen
+        assert_eq!(app.terminal.history_len(), 7 + TERM_ROWS);
+        assert!(app.terminal.history_line(3).starts_with("line 3 "));
+        assert_eq!(app.terminal.search("line 3", 30, true), Some((3, 0)));
+        assert_eq!(app.terminal.search("line 3", 4, false), None);
+        assert_eq!(app.terminal.search("", 30, true), None);
+
+        // Enter searches back from the newest line and scrolls the hit to the top
+        app.search = Some(TermSearch { query: "line 3".to_string(), editing: true, hit: None });
+        handle_search_key(&mut app, KeyEvent::from(KeyCode::Enter));
+        assert_eq!(app.search.as_ref().unwrap().hit, Some((3, 0)));
+        assert_eq!(app.term_scroll, 4);
+        // There is nothing older, so the hit and scroll stay put
+        search_terminal(&mut app, true);
+        assert_eq!(app.search.as_ref().unwrap().hit, Some((3, 0)));
+        assert_eq!(app.term_scroll, 4);
+    }
+
+    #[test]
+    fn dec_graphics_draws_lines() {
+        let term = terminal(b"\x1b(0lqk\x1b(Bq");
+        assert!(term.screen().next().unwrap().starts_with("┌─┐q"));
+        // G1 only shows through SO; other charsets stay ASCII
+        let term = terminal(b"\x1b)0q\x0eq\x0fq\x1b(Aq");
+        assert!(term.screen().next().unwrap().starts_with("q─qq"));
+    }
+
+    #[test]
+    fn typed_speeds_are_cycles_or_mhz_and_clamped() {
+        assert_eq!(parse_speed(" 20000 "), Ok(20000));
+        // A frame is 16ms, so 1 MHz is 16000 cycles per frame
+        assert_eq!(parse_speed("2.5 MHz"), Ok(40000));
+        assert_eq!(parse_speed("10"), Ok(MIN_CYCLES_PER_FRAME));
+        assert_eq!(parse_speed("1000mhz"), Ok(MAX_CYCLES_PER_FRAME));
+        assert_eq!(parse_speed("fast"), Err("Invalid cycle count: fast".to_string()));
+        assert_eq!(parse_speed("x mhz"), Err("Invalid MHz value: x".to_string()));
+        assert_eq!(parse_speed("0"), Err("Speed must be positive".to_string()));
+        assert_eq!(parse_speed("-1 MHz"), Err("Speed must be positive".to_string()));
+    }
+
+    #[test]
+    fn f3_cycles_how_words_are_shown() {
+        let (mut app, mut cpu) = tui_machine(&[]);
+        let mut shown = Vec::new();
+        for _ in 0..4 {
+            shown.push(format_word(0xFFFF, app.number_mode).trim().to_string());
+            handle_key(&mut app, &mut cpu, KeyEvent::from(KeyCode::F(3))).unwrap();
+        }
+        assert_eq!(shown, ["FFFF", "65535", "-1", "FFFF"]);
+        // Columns keep their width whatever the value
+        assert_eq!(format_word(7, NumberMode::Unsigned), "    7");
+        assert_eq!(format_word(0x8000, NumberMode::Signed), "-32768");
+    }
+
+    #[test]
+    fn sidebar_follows_breakpoints_as_they_change() {
+        let (mut app, mut cpu) = tui_machine(&[]);
+        app.symbols = Symbols::parse("0100 G main\n0120 L loop\n").unwrap();
+        let rows = |app: &App, cpu: &Cpu| -> Vec<(u16, String, bool)> {
+            sidebar_entries(cpu, &app.symbols).into_iter().map(|e| (e.addr, e.name, e.breakpoint)).collect()
+        };
+        assert_eq!(rows(&app, &cpu), [(0x0100, "main".to_string(), false), (0x0120, "loop".to_string(), false)]);
+
+        cpu.toggle_breakpoint(0x0200);
+        cpu.toggle_breakpoint(0x0120);
+        assert_eq!(rows(&app, &cpu)[..2], [(0x0120, "loop".to_string(), true), (0x0200, String::new(), true)]);
+        cpu.toggle_breakpoint(0x0200);
+        assert_eq!(rows(&app, &cpu).len(), 3);
+
+        // Down past the end stops on the last row; Enter shows it in the disassembly
+        app.sidebar = true;
+        for _ in 0..5 {
+            handle_key(&mut app, &mut cpu, KeyEvent::from(KeyCode::Down)).unwrap();
+        }
+        handle_key(&mut app, &mut cpu, KeyEvent::from(KeyCode::Enter)).unwrap();
+        assert_eq!(app.disasm_addr, Some(0x0120));
+        assert_eq!(Symbols::parse("0100 main").err(), Some("line 1: expected <addr> G|L <name>".to_string()));
+    }
+
+    #[test]
+    fn rates_divide_counts_by_the_time_taken() {
+        assert_eq!(rates(3_000_000, 1_000_000, 0.5), (6.0, 3.0, 2.0));
+        // Nothing ran, or no time passed: no rates rather than NaN or infinity
+        assert_eq!(rates(0, 0, 0.5), (0.0, 0.0, 0.0));
+        assert_eq!(rates(100, 50, 0.0), (0.0, 0.0, 0.0));
+
+        // ADDI R4, 1 takes one cycle; the taken J back also pays the branch penalty
+        let (mut app, mut cpu) = tui_machine(&[0x5401, 0x9FFE]);
+        app.cycles_per_frame = 100;
+        start_running(&mut app);
+        run_frame(&mut app, &mut cpu);
+        // 50 of each: 50 * 1 + 50 * (1 + BRANCH_PENALTY) cycles
+        assert_eq!((cpu.get_instructions(), cpu.get_cycles()), (100, 200));
+        assert_eq!(rates(cpu.get_cycles(), cpu.get_instructions(), 0.001), (0.2, 2.0, 0.1));
+        cpu.reset();
+        assert_eq!(cpu.get_instructions(), 0);
+    }
+
+    #[test]
+    fn metrics_follow_the_injected_clock() {
+        let clock = ManualClock::new();
+        let mut app = App::with_clock(0x0100, Symbols::default(), Box::new(clock.clone()));
+        let mut cpu = Cpu::with_config(CpuConfig { seed: 0, ..CpuConfig::default() });
+
+        // The cursor blinks once per 500ms of clock time, however often the UI polls
+        let mut blinks = 0;
+        for _ in 0..50 {
+            clock.advance(Duration::from_millis(100));
+            let before = app.cursor_blink;
+            app.update_metrics(&cpu);
+            blinks += (app.cursor_blink != before) as u32;
+        }
+        assert_eq!(blinks, 10);
+
+        // 1000 one-cycle instructions over the next half second is 2000 cycles a second
+        for _ in 0..1000 {
+            cpu.step().unwrap();
+        }
+        clock.advance(Duration::from_millis(500));
+        app.update_metrics(&cpu);
+        assert_eq!(app.effective_mhz, 0.002);
+        assert_eq!(app.cpi, 1.0);
+    }
 }